@@ -154,13 +154,21 @@ impl AsyncRuntime {
         *current = inner;
     }
 
-    pub(crate) async fn connect_tcp(addr: &str) -> Result<impl Stream, Error> {
+    /// Connects to the address, returning the stream and its local address.
+    pub(crate) async fn connect_tcp(addr: SocketAddr) -> Result<(impl Stream, SocketAddr), Error> {
         use Inner::*;
         Ok(match current() {
             TokioSingle | TokioShared | TokioOwned => async_tokio::connect_tcp(addr).await?,
         })
     }
 
+    pub(crate) async fn lookup_host(addr: &str) -> io::Result<Vec<SocketAddr>> {
+        use Inner::*;
+        Ok(match current() {
+            TokioSingle | TokioShared | TokioOwned => async_tokio::lookup_host(addr).await?,
+        })
+    }
+
     pub(crate) async fn timeout(duration: Duration) {
         use Inner::*;
         match current() {
@@ -264,8 +272,13 @@ pub(crate) mod async_tokio {
         (handle, runtime)
     }
 
-    pub(crate) async fn connect_tcp(addr: &str) -> Result<impl Stream, Error> {
-        Ok(from_tokio(TcpStream::connect(addr).await?))
+    pub(crate) async fn connect_tcp(addr: SocketAddr) -> Result<(impl Stream, SocketAddr), Error> {
+        let tcp = TcpStream::connect(addr).await?;
+        let local_addr = tcp.local_addr()?;
+        Ok((from_tokio(tcp), local_addr))
+    }
+    pub(crate) async fn lookup_host(addr: &str) -> io::Result<Vec<SocketAddr>> {
+        Ok(tokio::net::lookup_host(addr).await?.collect())
     }
     pub(crate) async fn timeout(duration: Duration) {
         tokio::time::sleep(duration).await;
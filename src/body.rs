@@ -1,34 +1,41 @@
 //! Request and response body. content-encoding, charset etc.
 
+use crate::body_channel::BodySender;
 use crate::body_codec::{BodyCodec, BodyImpl};
 use crate::bw::BandwidthMonitor;
 use crate::charset::CharCodec;
+use crate::client::Unfinished;
 use crate::from_utf8::from_utf8_lossy_replace;
 use crate::head_ext::HeaderMapExt;
+use crate::multipart::Multipart;
 use crate::params::HReqParams;
 use crate::uninit::UninitBuf;
 use crate::AsyncRead;
 use crate::AsyncRuntime;
 use crate::Error;
+use bytes::Bytes;
 use encoding_rs::Encoding;
+use futures_core::stream::Stream;
 use futures_util::future::poll_fn;
 use futures_util::io::AsyncReadExt;
 use futures_util::ready;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use std::borrow::Cow;
 use std::fmt;
 use std::future::Future;
 use std::io;
 use std::io::Cursor;
 use std::io::Read;
 use std::pin::Pin;
-use std::sync::Arc;
 use std::task::{Context, Poll};
 
 const CT_TEXT: &str = "text/plain; charset=utf-8";
 const CT_BIN: &str = "application/octet-stream";
 const CT_JSON: &str = "application/json; charset=utf-8";
+const CT_FORM: &str = "application/x-www-form-urlencoded";
 const MAX_STRING_SIZE: usize = 10 * 1024 * 1024;
+const STREAM_CHUNK_SIZE: usize = 16_384;
 
 /// Body of an http request or response.
 ///
@@ -69,6 +76,8 @@ const MAX_STRING_SIZE: usize = 10 * 1024 * 1024;
 /// | `Body::from_file(file)`                | `file`               |
 /// | `Body::from_async_read(reader, None)`  | -                    |
 /// | `Body::from_sync_read(reader, None)`   | -                    |
+/// | `Body::from_stream(stream)`            | -                    |
+/// | `Body::channel()`                      | -                    |
 ///
 /// ## Readers and performance
 ///
@@ -102,7 +111,9 @@ const MAX_STRING_SIZE: usize = 10 * 1024 * 1024;
 ///
 ///   * `content-encoding: gzip`
 ///
-/// The only supported algorithm is `gzip`.
+/// The supported algorithms are `gzip`, `br`, `deflate` and `zstd`, each behind
+/// a cargo feature of the same name (`brotli` for `br`). Stacked encodings like
+/// `gzip, br` are applied in the order listed.
 ///
 /// # Reading a body
 ///
@@ -117,6 +128,23 @@ const MAX_STRING_SIZE: usize = 10 * 1024 * 1024;
 /// Finaly `Body` implements `AsyncRead`, which means that in many cases, it can be used
 /// as is in rust's async ecosystem.
 ///
+/// `Body` is also a `Stream` of `Bytes` chunks. The chunks are passed on without
+/// copying when possible, such as the data frames of an HTTP/2 response without
+/// compression or charset decoding.
+///
+/// ```no_run
+/// use hreq::prelude::*;
+/// use futures_util::stream::StreamExt;
+///
+/// let res = Request::get("https://my-special-host/")
+///     .call().block().unwrap();
+///
+/// let mut body = res.into_body();
+/// while let Some(chunk) = body.next().block() {
+///     println!("{} bytes", chunk.unwrap().len());
+/// }
+/// ```
+///
 /// ```no_run
 /// use hreq::prelude::*;
 /// use futures_util::io::AsyncReadExt;
@@ -149,10 +177,11 @@ const MAX_STRING_SIZE: usize = 10 * 1024 * 1024;
 /// hreq decompresses the request body. The mechanic is triggered by the presence
 /// of a `content-encoding: gzip` response header.
 ///
-/// One can "ask" the server to compress the response by providing a header like
-/// `accept-encoding: gzip`. There's however no guarantee the server will provide compression.
+/// Requests "ask" the server to compress the response with an `accept-encoding`
+/// header listing the compiled in algorithms, unless the header is already set.
+/// There's however no guarantee the server will provide compression.
 ///
-/// The only supported algorithm is currently `gzip`.
+/// The supported algorithms are `gzip`, `br`, `deflate` and `zstd`.
 ///
 /// [`Body.read()`]: struct.Body.html#method.read
 /// [`Body.read_to_vec()`]: struct.Body.html#method.read_to_vec
@@ -166,14 +195,16 @@ const MAX_STRING_SIZE: usize = 10 * 1024 * 1024;
 pub struct Body {
     codec: BodyCodec,
     length: Option<u64>, // incoming length if given with reader
-    content_typ: Option<&'static str>,
+    content_typ: Option<Cow<'static, str>>,
     override_source_enc: Option<&'static Encoding>,
     has_read: bool,
     char_codec: Option<CharCodec>,
     deadline_fut: Option<Pin<Box<dyn Future<Output = io::Error> + Send + Sync>>>,
-    unfinished_recs: Option<Arc<()>>,
+    unfinished_recs: Option<Unfinished>,
     prebuffered: Option<Cursor<Vec<u8>>>,
     bw: Option<BandwidthMonitor>,
+    on_first_read: Option<Box<dyn FnOnce() + Send + Sync>>,
+    form_charset: &'static Encoding,
 }
 
 impl Body {
@@ -384,6 +415,68 @@ impl Body {
         Self::from_vec(vec).ctype(CT_JSON)
     }
 
+    /// Creates a body from a form encodable type.
+    ///
+    /// The form is encoded as `application/x-www-form-urlencoded` using `utf-8`. This
+    /// also sets the `content-type` and `content-length` headers.
+    ///
+    /// Panics if the type can't be encoded as a form, such as nested structs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hreq::Body;
+    /// use serde_derive::Serialize;
+    ///
+    /// #[derive(Serialize)]
+    /// struct Login {
+    ///   username: String,
+    ///   password: String,
+    /// }
+    ///
+    /// let form = Login {
+    ///   username: "martin".to_string(),
+    ///   password: "secret".to_string(),
+    /// };
+    ///
+    /// let body = Body::from_form(&form);
+    /// ```
+    pub fn from_form<B: Serialize + ?Sized>(form: &B) -> Self {
+        Self::from_form_charset(form, encoding_rs::UTF_8)
+    }
+
+    pub(crate) fn from_form_charset<B: Serialize + ?Sized>(
+        form: &B,
+        enc: &'static Encoding,
+    ) -> Self {
+        let encoded = crate::form::encode(form, enc);
+        Self::from_vec(encoded.into_bytes()).ctype(CT_FORM)
+    }
+
+    /// Creates a body from a [`Multipart`] form.
+    ///
+    /// This sets the `content-type` header with the form boundary. The `content-length`
+    /// is set if the length of every part is known.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use hreq::{Body, Multipart};
+    ///
+    /// let form = Multipart::new()
+    ///     .text("name", "Martin");
+    ///
+    /// // The are the same.
+    /// let body1: Body = Body::from_multipart(form);
+    /// # let form = Multipart::new();
+    /// let body2: Body = form.into();
+    /// ```
+    ///
+    /// [`Multipart`]: struct.Multipart.html
+    pub fn from_multipart(form: Multipart) -> Self {
+        form.into_body()
+    }
+
     /// Creates a body from anything implementing the `AsyncRead` trait.
     ///
     /// This is a very efficient way of sending bodies since the content
@@ -419,6 +512,68 @@ impl Body {
         Self::new(BodyImpl::RequestRead(boxed), length, true).ctype(CT_BIN)
     }
 
+    /// Creates a body from a `Stream` of byte chunks.
+    ///
+    /// The chunks are passed on without copying when possible. An error from the
+    /// stream fails the sending of the body. The `content-length` is not known,
+    /// which means HTTP/1.1 uses chunked transfer encoding.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    /// use futures_util::stream;
+    ///
+    /// let chunks = vec![Ok::<_, hreq::Error>("hello "), Ok("world")];
+    /// let body = Body::from_stream(stream::iter(chunks));
+    ///
+    /// Request::post("https://post-to-here")
+    ///     .send(body).block().unwrap();
+    /// ```
+    pub fn from_stream<S, B, E>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<B, E>> + Send + Sync + 'static,
+        B: Into<Bytes>,
+        E: Into<Error>,
+    {
+        let mapped = futures_util::stream::StreamExt::map(stream, |item| {
+            item.map(Into::into).map_err(Into::into)
+        });
+        Self::new(BodyImpl::RequestStream(Box::pin(mapped)), None, false).ctype(CT_BIN)
+    }
+
+    /// Creates a body fed chunk by chunk from a [`BodySender`].
+    ///
+    /// Use this to send data as it is produced while the body is already in flight.
+    /// The `content-length` is not known, which means HTTP/1.1 uses chunked
+    /// transfer encoding.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    /// use hreq::AsyncRuntime;
+    ///
+    /// let (mut sender, body) = Body::channel();
+    ///
+    /// AsyncRuntime::spawn(async move {
+    ///     for i in 0..10 {
+    ///         sender.send_data(format!("line {}\n", i).as_bytes()).await.unwrap();
+    ///     }
+    ///     sender.finish();
+    /// });
+    ///
+    /// Request::post("https://post-to-here")
+    ///     .send(body).block().unwrap();
+    /// ```
+    ///
+    /// [`BodySender`]: struct.BodySender.html
+    pub fn channel() -> (BodySender, Self) {
+        let (sender, recv) = crate::body_channel::channel();
+        let body = Self::new(BodyImpl::RequestChannel(recv), None, false).ctype(CT_BIN);
+        (sender, body)
+    }
+
     /// Creates a new Body
     pub(crate) fn new(bimpl: BodyImpl, length: Option<u64>, prebuffer: bool) -> Self {
         let codec = BodyCodec::deferred(bimpl, prebuffer);
@@ -433,15 +588,22 @@ impl Body {
             unfinished_recs: None,
             prebuffered: None,
             bw: None,
+            on_first_read: None,
+            form_charset: encoding_rs::UTF_8,
         }
     }
 
     fn ctype(mut self, c: &'static str) -> Self {
-        self.content_typ = Some(c);
+        self.content_typ = Some(Cow::Borrowed(c));
+        self
+    }
+
+    pub(crate) fn with_content_type(mut self, c: String) -> Self {
+        self.content_typ = Some(Cow::Owned(c));
         self
     }
 
-    pub(crate) fn set_unfinished_recs(&mut self, unfin: Arc<()>) {
+    pub(crate) fn set_unfinished_recs(&mut self, unfin: Unfinished) {
         self.unfinished_recs = Some(unfin);
     }
 
@@ -449,6 +611,12 @@ impl Body {
         self.bw = bw;
     }
 
+    /// Callback for when the body is read for the first time.
+    #[cfg(feature = "server")]
+    pub(crate) fn set_on_first_read<F: FnOnce() + Send + Sync + 'static>(&mut self, f: F) {
+        self.on_first_read = Some(Box::new(f));
+    }
+
     /// Tells if we know _for sure_, there is no body.
     pub(crate) fn is_definitely_no_body(&self) -> bool {
         self.length.map(|l| l == 0).unwrap_or(false)
@@ -472,9 +640,16 @@ impl Body {
         }
     }
 
+    /// Tells if the body is compressed/decompressed, which means the length
+    /// of the body is not the length of the content.
+    #[cfg(feature = "server")]
+    pub(crate) fn has_content_codec(&self) -> bool {
+        self.codec.affects_content_size()
+    }
+
     /// The content type set by the body, if any.
     pub(crate) fn content_type(&self) -> Option<&str> {
-        self.content_typ
+        self.content_typ.as_deref()
     }
 
     pub(crate) fn is_configurable(&self) -> bool {
@@ -482,7 +657,6 @@ impl Body {
     }
 
     /// Undo the effects of configure()
-    #[cfg(feature = "server")]
     pub(crate) fn unconfigure(self) -> Self {
         Body {
             codec: self.codec.into_deferred(),
@@ -536,6 +710,8 @@ impl Body {
             &params.charset_tx
         };
 
+        self.form_charset = charset_config.resolve_form(is_incoming, headers);
+
         // TODO sniff charset from html pages like
         // <meta content="text/html; charset=UTF-8" http-equiv="Content-Type">
         if let Some((from, to)) =
@@ -571,6 +747,35 @@ impl Body {
         Ok(())
     }
 
+    /// Bookkeeping before each read. Fails if the deadline is passed.
+    fn start_read(&mut self, cx: &mut Context) -> io::Result<()> {
+        if !self.has_read {
+            self.has_read = true;
+            if let Some(f) = self.on_first_read.take() {
+                f();
+            }
+        }
+
+        // use deadline if it's present
+        if let Some(deadl) = self.deadline_fut.as_mut() {
+            if let Poll::Ready(err) = deadl.as_mut().poll(cx) {
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Trailers to send after the body, once it is read to end.
+    pub(crate) fn take_trailers(&mut self) -> Option<http::HeaderMap> {
+        self.codec.take_trailers()
+    }
+
+    /// Tells whether `configure` has been called on this body.
+    pub(crate) fn is_configured(&self) -> bool {
+        !matches!(self.codec, BodyCodec::Deferred(Some(_)))
+    }
+
     #[cfg(test)]
     #[allow(dead_code)]
     pub(crate) fn set_codec_pass(&mut self) {
@@ -741,6 +946,35 @@ impl Body {
         Ok(serde_json::from_str(&s)?)
     }
 
+    /// Reads to body into a form deserializable type.
+    ///
+    /// The body is `application/x-www-form-urlencoded`. The percent encoded values are
+    /// decoded using the `charset` of the `content-type` header, if there is one, and
+    /// fall back on `utf-8`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    /// use serde_derive::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Login {
+    ///   username: String,
+    ///   password: String,
+    /// }
+    ///
+    /// async fn login(mut req: http::Request<Body>) -> String {
+    ///     let form: Login = req.body_mut().read_to_form().await.unwrap();
+    ///     format!("Hello {}", form.username)
+    /// }
+    /// ```
+    pub async fn read_to_form<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
+        let vec = self.read_to_vec(MAX_STRING_SIZE).await?;
+        let utf8 = crate::form::decode(&vec, self.form_charset);
+        Ok(serde_urlencoded::from_bytes(&utf8)?)
+    }
+
     /// Reads to body to end and discards it.
     ///
     /// HTTP/1.1 has no "multiplexing" of several concurrent request over the same socket;
@@ -826,6 +1060,12 @@ impl From<std::fs::File> for Body {
     }
 }
 
+impl From<Multipart> for Body {
+    fn from(form: Multipart) -> Self {
+        Body::from_multipart(form)
+    }
+}
+
 impl AsyncRead for Body {
     fn poll_read(
         self: Pin<&mut Self>,
@@ -833,17 +1073,8 @@ impl AsyncRead for Body {
         buf: &mut [u8],
     ) -> Poll<io::Result<usize>> {
         let this = self.get_mut();
-        if !this.has_read {
-            this.has_read = true;
-        }
 
-        // use deadline if it's present
-        let deadl = this.deadline_fut.as_mut();
-        if let Some(deadl) = deadl {
-            if let Poll::Ready(err) = deadl.as_mut().poll(cx) {
-                return Poll::Ready(Err(err));
-            }
-        }
+        this.start_read(cx)?;
 
         let amount = if let Some(prebuf) = &mut this.prebuffered {
             // entire contents is prebuffered
@@ -858,7 +1089,7 @@ impl AsyncRead for Body {
         };
 
         if amount == 0 {
-            // by removing this arc, we reduce the unfinished recs count.
+            // by removing the guard, we reduce the unfinished recs count.
             this.unfinished_recs.take();
         }
 
@@ -866,6 +1097,43 @@ impl AsyncRead for Body {
     }
 }
 
+impl Stream for Body {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        this.start_read(cx)?;
+
+        let chunk = if let Some(prebuf) = &mut this.prebuffered {
+            // entire contents is prebuffered
+            let pos = prebuf.position() as usize;
+            let chunk = if pos == 0 {
+                std::mem::take(prebuf.get_mut()).into()
+            } else {
+                Bytes::copy_from_slice(&prebuf.get_ref()[pos..])
+            };
+            prebuf.set_position(prebuf.get_ref().len() as u64);
+            chunk
+        } else if let Some(char_codec) = &mut this.char_codec {
+            let mut buf = vec![0; STREAM_CHUNK_SIZE];
+            let amount = ready!(char_codec.poll_codec(cx, &mut this.codec, &mut buf))?;
+            buf.truncate(amount);
+            buf.into()
+        } else {
+            ready!(this.codec.poll_chunk(cx))?
+        };
+
+        if chunk.is_empty() {
+            // by removing the guard, we reduce the unfinished recs count.
+            this.unfinished_recs.take();
+            return None.into();
+        }
+
+        Some(Ok(chunk)).into()
+    }
+}
+
 impl fmt::Debug for Body {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Body {{ codec: {:?}", self.codec)?;
@@ -0,0 +1,293 @@
+//! Bodies fed from a channel.
+
+use crate::Error;
+use bytes::Bytes;
+use futures_util::future::poll_fn;
+use futures_util::ready;
+use http::HeaderMap;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Sending half of a [`Body::channel()`].
+///
+/// Each [`send_data`] waits until the previous chunk has been taken by the
+/// connection, which in turn only happens when the HTTP/1.1 or HTTP/2 flow control
+/// allows more data to be sent. A slow peer therefore slows down the sender.
+///
+/// The body ends with [`finish`], [`send_trailers`] or by dropping the sender.
+/// [`abort`] ends the body with an error.
+///
+/// [`Body::channel()`]: struct.Body.html#method.channel
+/// [`send_data`]: struct.BodySender.html#method.send_data
+/// [`finish`]: struct.BodySender.html#method.finish
+/// [`send_trailers`]: struct.BodySender.html#method.send_trailers
+/// [`abort`]: struct.BodySender.html#method.abort
+pub struct BodySender {
+    shared: Arc<Mutex<Shared>>,
+}
+
+/// Receiving half, read by the `Body`.
+pub(crate) struct BodyReceiver {
+    shared: Arc<Mutex<Shared>>,
+}
+
+struct Shared {
+    chunk: Option<(Bytes, usize)>,
+    state: State,
+    trailers: Option<HeaderMap>,
+    receiver_gone: bool,
+    rx_waker: Option<Waker>,
+    tx_waker: Option<Waker>,
+}
+
+enum State {
+    Open,
+    Ended,
+    Aborted(Option<Error>),
+}
+
+pub(crate) fn channel() -> (BodySender, BodyReceiver) {
+    let shared = Arc::new(Mutex::new(Shared {
+        chunk: None,
+        state: State::Open,
+        trailers: None,
+        receiver_gone: false,
+        rx_waker: None,
+        tx_waker: None,
+    }));
+
+    (
+        BodySender {
+            shared: shared.clone(),
+        },
+        BodyReceiver { shared },
+    )
+}
+
+impl BodySender {
+    /// Send a chunk of body data.
+    ///
+    /// Waits until the previous chunk has been sent on. Fails if the body is gone,
+    /// such as when the request or connection failed.
+    pub async fn send_data(&mut self, data: &[u8]) -> Result<(), Error> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        poll_fn(|cx| self.poll_ready(cx)).await?;
+
+        let mut shared = self.shared.lock().unwrap();
+        shared.chunk = Some((Bytes::copy_from_slice(data), 0));
+        if let Some(waker) = shared.rx_waker.take() {
+            waker.wake();
+        }
+
+        Ok(())
+    }
+
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), Error>> {
+        let mut shared = self.shared.lock().unwrap();
+
+        if shared.receiver_gone {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "Body dropped before sending entire body",
+            )))
+            .into();
+        }
+
+        if shared.chunk.is_some() {
+            shared.tx_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        Ok(()).into()
+    }
+
+    /// End the body with trailing headers.
+    ///
+    /// Trailers are sent over HTTP/2. HTTP/1.1 bodies end without them, since
+    /// chunked trailers are not supported.
+    pub fn send_trailers(self, trailers: HeaderMap) {
+        self.shared.lock().unwrap().trailers = Some(trailers);
+        self.finish();
+    }
+
+    /// End the body.
+    ///
+    /// This is the same as dropping the sender.
+    pub fn finish(self) {
+        // see Drop
+    }
+
+    /// End the body with an error.
+    ///
+    /// Reading the body fails with the error. When the body is being sent, an HTTP/2
+    /// stream is reset, and an HTTP/1.1 connection is closed, since the peer can't
+    /// tell a cut short body from a complete one otherwise.
+    pub fn abort(self, err: Error) {
+        self.shared.lock().unwrap().end(State::Aborted(Some(err)));
+    }
+}
+
+impl Drop for BodySender {
+    fn drop(&mut self) {
+        self.shared.lock().unwrap().end(State::Ended);
+    }
+}
+
+impl Shared {
+    fn end(&mut self, state: State) {
+        if let State::Open = self.state {
+            self.state = state;
+        }
+        if let Some(waker) = self.rx_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Ready when there is no more data, with an error if aborted.
+    fn poll_end(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        match &mut self.state {
+            State::Open => {
+                self.rx_waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            State::Ended => Ok(()).into(),
+            State::Aborted(err) => {
+                let err = match err.take() {
+                    Some(Error::Io(e)) => e,
+                    Some(e) => io::Error::new(io::ErrorKind::Other, e),
+                    None => io::Error::new(io::ErrorKind::Other, "Body aborted"),
+                };
+                Err(err).into()
+            }
+        }
+    }
+}
+
+impl BodyReceiver {
+    pub(crate) fn poll_read(
+        &mut self,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut shared = self.shared.lock().unwrap();
+
+        if let Some((data, pos)) = &mut shared.chunk {
+            let amt = (data.len() - *pos).min(buf.len());
+            buf[..amt].copy_from_slice(&data[*pos..*pos + amt]);
+            *pos += amt;
+
+            if *pos == data.len() {
+                shared.chunk = None;
+                if let Some(waker) = shared.tx_waker.take() {
+                    waker.wake();
+                }
+            }
+
+            return Ok(amt).into();
+        }
+
+        ready!(shared.poll_end(cx))?;
+
+        Ok(0).into()
+    }
+
+    /// The next chunk as is. Empty at the end.
+    pub(crate) fn poll_chunk(&mut self, cx: &mut Context) -> Poll<io::Result<Bytes>> {
+        let mut shared = self.shared.lock().unwrap();
+
+        if let Some((data, pos)) = shared.chunk.take() {
+            if let Some(waker) = shared.tx_waker.take() {
+                waker.wake();
+            }
+            return Ok(data.slice(pos..)).into();
+        }
+
+        ready!(shared.poll_end(cx))?;
+
+        Ok(Bytes::new()).into()
+    }
+
+    /// Trailers, once the body is read to end.
+    pub(crate) fn take_trailers(&mut self) -> Option<HeaderMap> {
+        self.shared.lock().unwrap().trailers.take()
+    }
+}
+
+impl Drop for BodyReceiver {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.receiver_gone = true;
+        if let Some(waker) = shared.tx_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl fmt::Debug for BodySender {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BodySender")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::AsyncRuntime;
+
+    fn read(rx: &mut BodyReceiver, len: usize) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0; len];
+        let amt = AsyncRuntime::block_on(poll_fn(|cx| rx.poll_read(cx, &mut buf)))?;
+        buf.truncate(amt);
+        Ok(buf)
+    }
+
+    #[test]
+    fn send_read_trailers() {
+        let (mut tx, mut rx) = channel();
+
+        AsyncRuntime::block_on(tx.send_data(b"hello")).unwrap();
+        assert_eq!(read(&mut rx, 3).unwrap(), b"hel");
+        assert_eq!(read(&mut rx, 10).unwrap(), b"lo");
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("x-sum", "42".parse().unwrap());
+        tx.send_trailers(trailers);
+
+        assert_eq!(read(&mut rx, 10).unwrap(), b"");
+        assert_eq!(rx.take_trailers().unwrap()["x-sum"], "42");
+    }
+
+    #[test]
+    fn wait_for_chunk_taken() {
+        use futures_util::future::FutureExt;
+
+        let (mut tx, mut rx) = channel();
+
+        assert!(tx.send_data(b"one").now_or_never().is_some());
+        assert!(tx.send_data(b"two").now_or_never().is_none());
+
+        assert_eq!(read(&mut rx, 10).unwrap(), b"one");
+        assert!(tx.send_data(b"two").now_or_never().is_some());
+    }
+
+    #[test]
+    fn abort_and_gone() {
+        let (mut tx, mut rx) = channel();
+        AsyncRuntime::block_on(tx.send_data(b"x")).unwrap();
+        tx.abort(Error::Proto("stop".into()));
+
+        // data before the abort is still read.
+        assert_eq!(read(&mut rx, 10).unwrap(), b"x");
+        let err = read(&mut rx, 10).unwrap_err();
+        assert!(err.to_string().contains("stop"));
+
+        let (mut tx, rx) = channel();
+        drop(rx);
+        assert!(AsyncRuntime::block_on(tx.send_data(b"x")).is_err());
+    }
+}
@@ -1,12 +1,16 @@
+use crate::body_channel::BodyReceiver;
 use crate::bw::BandwidthMonitor;
 use crate::uninit::UninitBuf;
 use crate::AsyncRead;
+use crate::Error;
 use bytes::Bytes;
+use futures_core::stream::Stream;
 use futures_io::AsyncBufRead;
 use futures_util::future::poll_fn;
 use futures_util::ready;
 use h2::RecvStream as H2RecvStream;
 use hreq_h1::RecvStream as H1RecvStream;
+use once_cell::sync::Lazy;
 use std::fmt;
 use std::io;
 use std::io::Read;
@@ -16,21 +20,80 @@ use std::task::{Context, Poll};
 #[cfg(feature = "gzip")]
 use async_compression::futures::bufread::{GzipDecoder, GzipEncoder};
 
-#[cfg(feature = "gzip")]
+#[cfg(feature = "brotli")]
+use async_compression::futures::bufread::{BrotliDecoder, BrotliEncoder};
+
+#[cfg(feature = "deflate")]
+use async_compression::futures::bufread::{ZlibDecoder, ZlibEncoder};
+
+#[cfg(feature = "zstd")]
+use async_compression::futures::bufread::{ZstdDecoder, ZstdEncoder};
+
+#[cfg(any(
+    feature = "gzip",
+    feature = "brotli",
+    feature = "deflate",
+    feature = "zstd"
+))]
 use futures_util::io::BufReader;
 
 const START_BUF_SIZE: usize = 16_384;
 const MAX_BUF_SIZE: usize = 2 * 1024 * 1024;
 const MAX_PREBUFFER: usize = 256 * 1024;
 
+/// The content encodings compiled in, in order of preference.
+pub(crate) const ENCODINGS: &[&str] = &[
+    #[cfg(feature = "brotli")]
+    "br",
+    #[cfg(feature = "zstd")]
+    "zstd",
+    #[cfg(feature = "gzip")]
+    "gzip",
+    #[cfg(feature = "deflate")]
+    "deflate",
+];
+
+static ACCEPT_ENCODING: Lazy<String> = Lazy::new(|| ENCODINGS.join(", "));
+
+/// Value for an `accept-encoding` header listing the encodings we can decode.
+pub(crate) fn accept_encoding() -> Option<&'static str> {
+    if ENCODINGS.is_empty() {
+        None
+    } else {
+        Some(&ACCEPT_ENCODING)
+    }
+}
+
+/// Tells whether we can encode/decode the given (single) content encoding.
+pub(crate) fn is_supported_encoding(encoding: &str) -> bool {
+    ENCODINGS.contains(&encoding)
+}
+
+/// Stacked codecs wrap each other, the innermost wraps `Pass`. The codec variants
+/// are boxed, since some of them (brotli) hold a lot of state.
+#[allow(unused)]
+type Inner = Box<BodyCodec>;
+
 #[allow(clippy::large_enum_variant)]
 pub(crate) enum BodyCodec {
     Deferred(Option<BodyReader>),
     Pass(BodyReader),
     #[cfg(feature = "gzip")]
-    GzipDecoder(BufReader<GzipDecoder<BodyReader>>),
+    GzipDecoder(Box<BufReader<GzipDecoder<Inner>>>),
     #[cfg(feature = "gzip")]
-    GzipEncoder(BufReader<GzipEncoder<BodyReader>>),
+    GzipEncoder(Box<BufReader<GzipEncoder<Inner>>>),
+    #[cfg(feature = "brotli")]
+    BrotliDecoder(Box<BufReader<BrotliDecoder<Inner>>>),
+    #[cfg(feature = "brotli")]
+    BrotliEncoder(Box<BufReader<BrotliEncoder<Inner>>>),
+    #[cfg(feature = "deflate")]
+    DeflateDecoder(Box<BufReader<ZlibDecoder<Inner>>>),
+    #[cfg(feature = "deflate")]
+    DeflateEncoder(Box<BufReader<ZlibEncoder<Inner>>>),
+    #[cfg(feature = "zstd")]
+    ZstdDecoder(Box<BufReader<ZstdDecoder<Inner>>>),
+    #[cfg(feature = "zstd")]
+    ZstdEncoder(Box<BufReader<ZstdEncoder<Inner>>>),
 }
 
 impl BodyCodec {
@@ -39,40 +102,110 @@ impl BodyCodec {
         BodyCodec::Deferred(Some(reader))
     }
 
-    #[cfg(feature = "server")]
     pub fn into_deferred(self) -> Self {
         let reader = self.into_inner();
         BodyCodec::Deferred(Some(reader))
     }
 
-    #[cfg(feature = "server")]
     fn into_inner(self) -> BodyReader {
         match self {
             BodyCodec::Deferred(_) => panic!("into_inner() on Deferred"),
             BodyCodec::Pass(b) => b,
             #[cfg(feature = "gzip")]
-            BodyCodec::GzipDecoder(z) => z.into_inner().into_inner(),
+            BodyCodec::GzipDecoder(z) => (*z).into_inner().into_inner().into_inner(),
             #[cfg(feature = "gzip")]
-            BodyCodec::GzipEncoder(z) => z.into_inner().into_inner(),
+            BodyCodec::GzipEncoder(z) => (*z).into_inner().into_inner().into_inner(),
+            #[cfg(feature = "brotli")]
+            BodyCodec::BrotliDecoder(z) => (*z).into_inner().into_inner().into_inner(),
+            #[cfg(feature = "brotli")]
+            BodyCodec::BrotliEncoder(z) => (*z).into_inner().into_inner().into_inner(),
+            #[cfg(feature = "deflate")]
+            BodyCodec::DeflateDecoder(z) => (*z).into_inner().into_inner().into_inner(),
+            #[cfg(feature = "deflate")]
+            BodyCodec::DeflateEncoder(z) => (*z).into_inner().into_inner().into_inner(),
+            #[cfg(feature = "zstd")]
+            BodyCodec::ZstdDecoder(z) => (*z).into_inner().into_inner().into_inner(),
+            #[cfg(feature = "zstd")]
+            BodyCodec::ZstdEncoder(z) => (*z).into_inner().into_inner().into_inner(),
         }
     }
 
+    /// Create a codec for a `content-encoding` header value.
+    ///
+    /// The value can be a list of stacked encodings, like `gzip, br`, in the order
+    /// they are applied. Incoming bodies are decoded in reverse order.
     pub fn from_encoding(reader: BodyReader, encoding: Option<&str>, is_incoming: bool) -> Self {
         trace!("Body codec from encoding: {:?}", encoding);
-        match (encoding, is_incoming) {
-            (None, _) => BodyCodec::Pass(reader),
+
+        let encodings: Vec<&str> = encoding
+            .map(|e| {
+                e.split(',')
+                    .map(|e| e.trim())
+                    .filter(|e| !e.is_empty() && !e.eq_ignore_ascii_case("identity"))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if let Some(unknown) = encodings
+            .iter()
+            .find(|e| !is_supported_encoding(&e.to_ascii_lowercase()))
+        {
+            warn!("Unknown content-encoding: {:?}", unknown);
+            return BodyCodec::Pass(reader);
+        }
+
+        let mut codec = BodyCodec::Pass(reader);
+
+        if is_incoming {
+            for enc in encodings.iter().rev() {
+                codec = codec.wrap(enc, true);
+            }
+        } else {
+            for enc in encodings.iter() {
+                codec = codec.wrap(enc, false);
+            }
+        }
+
+        codec
+    }
+
+    #[allow(unused)]
+    fn wrap(self, encoding: &str, is_incoming: bool) -> Self {
+        let inner = Box::new(self);
+        match (encoding.to_ascii_lowercase().as_str(), is_incoming) {
             #[cfg(feature = "gzip")]
-            (Some("gzip"), true) => {
-                BodyCodec::GzipDecoder(BufReader::new(GzipDecoder::new(reader)))
+            ("gzip", true) => {
+                BodyCodec::GzipDecoder(Box::new(BufReader::new(GzipDecoder::new(inner))))
             }
             #[cfg(feature = "gzip")]
-            (Some("gzip"), false) => {
-                BodyCodec::GzipEncoder(BufReader::new(GzipEncoder::new(reader)))
+            ("gzip", false) => {
+                BodyCodec::GzipEncoder(Box::new(BufReader::new(GzipEncoder::new(inner))))
             }
-            _ => {
-                warn!("Unknown content-encoding: {:?}", encoding);
-                BodyCodec::Pass(reader)
+            #[cfg(feature = "brotli")]
+            ("br", true) => {
+                BodyCodec::BrotliDecoder(Box::new(BufReader::new(BrotliDecoder::new(inner))))
+            }
+            #[cfg(feature = "brotli")]
+            ("br", false) => {
+                BodyCodec::BrotliEncoder(Box::new(BufReader::new(BrotliEncoder::new(inner))))
+            }
+            #[cfg(feature = "deflate")]
+            ("deflate", true) => {
+                BodyCodec::DeflateDecoder(Box::new(BufReader::new(ZlibDecoder::new(inner))))
+            }
+            #[cfg(feature = "deflate")]
+            ("deflate", false) => {
+                BodyCodec::DeflateEncoder(Box::new(BufReader::new(ZlibEncoder::new(inner))))
+            }
+            #[cfg(feature = "zstd")]
+            ("zstd", true) => {
+                BodyCodec::ZstdDecoder(Box::new(BufReader::new(ZstdDecoder::new(inner))))
             }
+            #[cfg(feature = "zstd")]
+            ("zstd", false) => {
+                BodyCodec::ZstdEncoder(Box::new(BufReader::new(ZstdEncoder::new(inner))))
+            }
+            _ => unreachable!("wrap with unsupported encoding: {}", encoding),
         }
     }
 
@@ -81,20 +214,49 @@ impl BodyCodec {
             BodyCodec::Deferred(r) => r.as_mut(),
             BodyCodec::Pass(r) => Some(r),
             #[cfg(feature = "gzip")]
-            BodyCodec::GzipDecoder(r) => Some(r.get_mut().get_mut()),
+            BodyCodec::GzipDecoder(r) => r.get_mut().get_mut().reader_mut(),
             #[cfg(feature = "gzip")]
-            BodyCodec::GzipEncoder(r) => Some(r.get_mut().get_mut()),
+            BodyCodec::GzipEncoder(r) => r.get_mut().get_mut().reader_mut(),
+            #[cfg(feature = "brotli")]
+            BodyCodec::BrotliDecoder(r) => r.get_mut().get_mut().reader_mut(),
+            #[cfg(feature = "brotli")]
+            BodyCodec::BrotliEncoder(r) => r.get_mut().get_mut().reader_mut(),
+            #[cfg(feature = "deflate")]
+            BodyCodec::DeflateDecoder(r) => r.get_mut().get_mut().reader_mut(),
+            #[cfg(feature = "deflate")]
+            BodyCodec::DeflateEncoder(r) => r.get_mut().get_mut().reader_mut(),
+            #[cfg(feature = "zstd")]
+            BodyCodec::ZstdDecoder(r) => r.get_mut().get_mut().reader_mut(),
+            #[cfg(feature = "zstd")]
+            BodyCodec::ZstdEncoder(r) => r.get_mut().get_mut().reader_mut(),
         }
     }
 
     pub fn affects_content_size(&self) -> bool {
+        !matches!(self, BodyCodec::Deferred(_) | BodyCodec::Pass(_))
+    }
+
+    /// The codec as a plain AsyncBufRead.
+    fn as_buf_read(&mut self) -> Pin<&mut (dyn AsyncBufRead + Send + Unpin)> {
         match self {
-            BodyCodec::Deferred(_) => false,
-            BodyCodec::Pass(_) => false,
+            BodyCodec::Deferred(_) => panic!("Read on BodyCodec::Deferred"),
+            BodyCodec::Pass(r) => Pin::new(r),
             #[cfg(feature = "gzip")]
-            BodyCodec::GzipDecoder(_) => true,
+            BodyCodec::GzipDecoder(r) => Pin::new(r),
             #[cfg(feature = "gzip")]
-            BodyCodec::GzipEncoder(_) => true,
+            BodyCodec::GzipEncoder(r) => Pin::new(r),
+            #[cfg(feature = "brotli")]
+            BodyCodec::BrotliDecoder(r) => Pin::new(r),
+            #[cfg(feature = "brotli")]
+            BodyCodec::BrotliEncoder(r) => Pin::new(r),
+            #[cfg(feature = "deflate")]
+            BodyCodec::DeflateDecoder(r) => Pin::new(r),
+            #[cfg(feature = "deflate")]
+            BodyCodec::DeflateEncoder(r) => Pin::new(r),
+            #[cfg(feature = "zstd")]
+            BodyCodec::ZstdDecoder(r) => Pin::new(r),
+            #[cfg(feature = "zstd")]
+            BodyCodec::ZstdEncoder(r) => Pin::new(r),
         }
     }
 
@@ -110,14 +272,39 @@ impl BodyCodec {
         }
         Ok(None)
     }
+
+    /// Read the next chunk, passing on the underlying `Bytes` when there is no codec.
+    /// Empty at the end.
+    pub fn poll_chunk(&mut self, cx: &mut Context) -> Poll<io::Result<Bytes>> {
+        if let BodyCodec::Pass(reader) = self {
+            return reader.poll_chunk(cx);
+        }
+
+        let mut reader = self.as_buf_read();
+        let buf = ready!(reader.as_mut().poll_fill_buf(cx))?;
+        let data = Bytes::copy_from_slice(buf);
+        reader.consume(data.len());
+
+        Ok(data).into()
+    }
+
+    /// Trailers of a channel body, once it is read to end.
+    pub fn take_trailers(&mut self) -> Option<http::HeaderMap> {
+        match self.reader_mut().map(|r| &mut r.imp) {
+            Some(BodyImpl::RequestChannel(recv)) => recv.take_trailers(),
+            _ => None,
+        }
+    }
 }
 
+pub(crate) type BoxStream = Pin<Box<dyn Stream<Item = Result<Bytes, Error>> + Send + Sync>>;
+
 pub struct BodyReader {
     imp: BodyImpl,
     prebuffer_to: usize,
     buffer: UninitBuf,
     consumed: usize,
-    h2_leftover_bytes: Option<H2BytesReader>,
+    leftover_bytes: Option<BytesReader>,
     is_finished: bool,
     bw: Option<BandwidthMonitor>,
 }
@@ -126,6 +313,8 @@ pub(crate) enum BodyImpl {
     RequestEmpty,
     RequestAsyncRead(Box<dyn AsyncRead + Unpin + Send + Sync>),
     RequestRead(Box<dyn io::Read + Send + Sync>),
+    RequestChannel(BodyReceiver),
+    RequestStream(BoxStream),
     Http1(H1RecvStream),
     Http2(H2RecvStream),
 }
@@ -135,7 +324,7 @@ impl BodyReader {
         BodyReader {
             imp,
             prebuffer_to: if prebuffer { MAX_PREBUFFER } else { 0 },
-            h2_leftover_bytes: None,
+            leftover_bytes: None,
             buffer: UninitBuf::with_capacity(START_BUF_SIZE, MAX_BUF_SIZE),
             consumed: 0,
             is_finished: false,
@@ -153,6 +342,12 @@ impl BodyReader {
     /// Returns the number of bytes read if the underlying reader is read to end, which
     /// means we got all contents in memory.
     async fn attempt_prebuffer(&mut self) -> io::Result<Option<usize>> {
+        // channel bodies are sent as they are produced, waiting for data here would
+        // hold back the head.
+        if let BodyImpl::RequestChannel(_) = &self.imp {
+            return Ok(None);
+        }
+
         poll_fn(|cx| Pin::new(&mut *self).poll_refill_buf(cx)).await?;
 
         Ok(if self.is_finished {
@@ -172,12 +367,12 @@ impl BodyReader {
             return Ok(0).into();
         }
 
-        // h2 streams might have leftovers to use up before reading any more.
-        if let Some(br) = &mut self.h2_leftover_bytes {
+        // h2 streams and streams might have leftovers to use up before reading any more.
+        if let Some(br) = &mut self.leftover_bytes {
             let amt = br.read(buf)?;
 
             if br.len() == 0 {
-                self.h2_leftover_bytes = None;
+                self.leftover_bytes = None;
             }
 
             return Ok(amt).into();
@@ -195,47 +390,113 @@ impl BodyReader {
                     return Err(e).into();
                 }
             },
+            BodyImpl::RequestChannel(recv) => ready!(recv.poll_read(cx, buf))?,
             BodyImpl::Http1(recv) => ready!(Pin::new(recv).poll_read(cx, buf))?,
-            BodyImpl::Http2(recv) => {
-                if let Some(data) = ready!(recv.poll_data(cx)) {
-                    let data = data.map_err(|e| {
-                        let other = format!("Other h2 error (poll_data): {}", e);
-                        e.into_io()
-                            .unwrap_or_else(|| io::Error::new(io::ErrorKind::Other, other))
-                    })?;
-
-                    recv.flow_control()
-                        .release_capacity(data.len())
-                        .map_err(|e| {
-                            let other = format!("Other h2 error (release_capacity): {}", e);
+            BodyImpl::RequestStream(_) | BodyImpl::Http2(_) => {
+                let data = ready!(self.poll_next_bytes(cx))?;
+
+                let mut br = BytesReader(data, 0);
+                let amt = br.read(buf)?;
+
+                // if any is left, leave it for later.
+                if br.len() > 0 {
+                    self.leftover_bytes = Some(br);
+                }
+
+                amt
+            }
+        };
+
+        if amount == 0 {
+            self.is_finished = true;
+        }
+
+        if let Some(bw) = &self.bw {
+            bw.append_read_bytes(amount);
+        }
+
+        Ok(amount).into()
+    }
+
+    /// Next chunk from the underlying sources that produce `Bytes`. Empty at the end.
+    fn poll_next_bytes(&mut self, cx: &mut Context) -> Poll<io::Result<Bytes>> {
+        loop {
+            let data = match &mut self.imp {
+                BodyImpl::Http2(recv) => match ready!(recv.poll_data(cx)) {
+                    Some(data) => {
+                        let data = data.map_err(|e| {
+                            let other = format!("Other h2 error (poll_data): {}", e);
                             e.into_io()
                                 .unwrap_or_else(|| io::Error::new(io::ErrorKind::Other, other))
                         })?;
 
-                    let mut br = H2BytesReader(data, 0);
-                    let amt = br.read(buf)?;
+                        recv.flow_control()
+                            .release_capacity(data.len())
+                            .map_err(|e| {
+                                let other = format!("Other h2 error (release_capacity): {}", e);
+                                e.into_io()
+                                    .unwrap_or_else(|| io::Error::new(io::ErrorKind::Other, other))
+                            })?;
 
-                    // if any is left, leave it for later.
-                    if br.len() > 0 {
-                        self.h2_leftover_bytes = Some(br);
+                        data
                     }
+                    None => return Ok(Bytes::new()).into(),
+                },
+                BodyImpl::RequestStream(stream) => match ready!(stream.as_mut().poll_next(cx)) {
+                    Some(Ok(data)) => data,
+                    Some(Err(Error::Io(e))) => return Err(e).into(),
+                    Some(Err(e)) => return Err(io::Error::new(io::ErrorKind::Other, e)).into(),
+                    None => return Ok(Bytes::new()).into(),
+                },
+                _ => unreachable!("poll_next_bytes on {:?}", self.imp),
+            };
+
+            // an empty chunk in the middle is not the end.
+            if !data.is_empty() {
+                return Ok(data).into();
+            }
+        }
+    }
 
-                    amt
-                } else {
-                    0
-                }
+    /// Read the next chunk as is, without copying when the underlying source
+    /// produces `Bytes`. Empty at the end.
+    fn poll_chunk(&mut self, cx: &mut Context) -> Poll<io::Result<Bytes>> {
+        // data already buffered must go first.
+        if self.unconsumed_len() > 0 {
+            let data = Bytes::copy_from_slice(self.unconsumed());
+            self.consumed = self.buffer.len();
+            return Ok(data).into();
+        }
+
+        if let Some(br) = self.leftover_bytes.take() {
+            return Ok(br.0.slice(br.1..)).into();
+        }
+
+        if self.is_finished {
+            return Ok(Bytes::new()).into();
+        }
+
+        let data = match &mut self.imp {
+            BodyImpl::RequestChannel(recv) => ready!(recv.poll_chunk(cx))?,
+            BodyImpl::RequestStream(_) | BodyImpl::Http2(_) => ready!(self.poll_next_bytes(cx))?,
+            _ => {
+                // everything else reads via the buffer.
+                ready!(self.poll_refill_buf(cx))?;
+                let data = Bytes::copy_from_slice(self.unconsumed());
+                self.consumed = self.buffer.len();
+                return Ok(data).into();
             }
         };
 
-        if amount == 0 {
+        if data.is_empty() {
             self.is_finished = true;
         }
 
         if let Some(bw) = &self.bw {
-            bw.append_read_bytes(amount);
+            bw.append_read_bytes(data.len());
         }
 
-        Ok(amount).into()
+        Ok(data).into()
     }
 
     fn poll_refill_buf(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
@@ -329,50 +590,41 @@ impl AsyncRead for BodyCodec {
         cx: &mut Context,
         buf: &mut [u8],
     ) -> Poll<io::Result<usize>> {
-        let this = self.get_mut();
-        match this {
-            BodyCodec::Deferred(_) => panic!("poll_read on BodyCodec::Deferred"),
-            BodyCodec::Pass(r) => Pin::new(r).poll_read(cx, buf),
-            #[cfg(feature = "gzip")]
-            BodyCodec::GzipDecoder(r) => Pin::new(r).poll_read(cx, buf),
-            #[cfg(feature = "gzip")]
-            BodyCodec::GzipEncoder(r) => Pin::new(r).poll_read(cx, buf),
-        }
+        self.get_mut().as_buf_read().poll_read(cx, buf)
     }
 }
 
 impl AsyncBufRead for BodyCodec {
     fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<&[u8]>> {
-        match self.get_mut() {
-            BodyCodec::Deferred(_) => panic!("poll_fill_buf on Deferred"),
-            BodyCodec::Pass(r) => Pin::new(r).poll_fill_buf(cx),
-            #[cfg(feature = "gzip")]
-            BodyCodec::GzipDecoder(r) => Pin::new(r).poll_fill_buf(cx),
-            #[cfg(feature = "gzip")]
-            BodyCodec::GzipEncoder(r) => Pin::new(r).poll_fill_buf(cx),
-        }
+        self.get_mut().as_buf_read().poll_fill_buf(cx)
     }
 
     fn consume(self: Pin<&mut Self>, amt: usize) {
-        match self.get_mut() {
-            BodyCodec::Deferred(_) => panic!("consume on Deferred"),
-            BodyCodec::Pass(r) => Pin::new(r).consume(amt),
-            #[cfg(feature = "gzip")]
-            BodyCodec::GzipDecoder(r) => Pin::new(r).consume(amt),
-            #[cfg(feature = "gzip")]
-            BodyCodec::GzipEncoder(r) => Pin::new(r).consume(amt),
-        }
+        self.get_mut().as_buf_read().consume(amt)
     }
 }
+
 impl fmt::Debug for BodyCodec {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             BodyCodec::Deferred(_) => write!(f, "defer"),
             BodyCodec::Pass(_) => write!(f, "pass"),
             #[cfg(feature = "gzip")]
-            BodyCodec::GzipDecoder(_) => write!(f, "gzip_dec"),
+            BodyCodec::GzipDecoder(r) => write!(f, "gzip_dec({:?})", r.get_ref().get_ref()),
             #[cfg(feature = "gzip")]
-            BodyCodec::GzipEncoder(_) => write!(f, "gzip_enc"),
+            BodyCodec::GzipEncoder(r) => write!(f, "gzip_enc({:?})", r.get_ref().get_ref()),
+            #[cfg(feature = "brotli")]
+            BodyCodec::BrotliDecoder(r) => write!(f, "br_dec({:?})", r.get_ref().get_ref()),
+            #[cfg(feature = "brotli")]
+            BodyCodec::BrotliEncoder(r) => write!(f, "br_enc({:?})", r.get_ref().get_ref()),
+            #[cfg(feature = "deflate")]
+            BodyCodec::DeflateDecoder(r) => write!(f, "deflate_dec({:?})", r.get_ref().get_ref()),
+            #[cfg(feature = "deflate")]
+            BodyCodec::DeflateEncoder(r) => write!(f, "deflate_enc({:?})", r.get_ref().get_ref()),
+            #[cfg(feature = "zstd")]
+            BodyCodec::ZstdDecoder(r) => write!(f, "zstd_dec({:?})", r.get_ref().get_ref()),
+            #[cfg(feature = "zstd")]
+            BodyCodec::ZstdEncoder(r) => write!(f, "zstd_enc({:?})", r.get_ref().get_ref()),
         }
     }
 }
@@ -389,6 +641,8 @@ impl fmt::Debug for BodyImpl {
             BodyImpl::RequestEmpty => write!(f, "empty"),
             BodyImpl::RequestAsyncRead(_) => write!(f, "async"),
             BodyImpl::RequestRead(_) => write!(f, "sync"),
+            BodyImpl::RequestChannel(_) => write!(f, "channel"),
+            BodyImpl::RequestStream(_) => write!(f, "stream"),
             BodyImpl::Http1(_) => write!(f, "http1"),
             BodyImpl::Http2(_) => write!(f, "http2"),
         }
@@ -396,15 +650,15 @@ impl fmt::Debug for BodyImpl {
 }
 
 /// Helper to deal with bytes::Bytes not being fully read.
-struct H2BytesReader(Bytes, usize);
+struct BytesReader(Bytes, usize);
 
-impl H2BytesReader {
+impl BytesReader {
     fn len(&self) -> usize {
         self.0.len() - self.1
     }
 }
 
-impl Read for H2BytesReader {
+impl Read for BytesReader {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         if self.len() == 0 {
             return Ok(0);
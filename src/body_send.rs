@@ -3,22 +3,23 @@ use bytes::Bytes;
 use futures_util::future::poll_fn;
 use h2;
 use hreq_h1 as h1;
+use http::HeaderMap;
 
 /// Generalisation over sending body data.
-pub(crate) enum BodySender {
+pub(crate) enum BodySend {
     H1(h1::SendStream),
     H2(h2::SendStream<Bytes>),
 }
 
-impl BodySender {
+impl BodySend {
     pub async fn send_data(&mut self, mut buf: &[u8]) -> Result<(), Error> {
         if buf.is_empty() {
             return Ok(());
         }
 
         match self {
-            BodySender::H1(s) => Ok(s.send_data(buf, false).await?),
-            BodySender::H2(s) => {
+            BodySend::H1(s) => Ok(s.send_data(buf, false).await?),
+            BodySend::H2(s) => {
                 loop {
                     if buf.len() == 0 {
                         break;
@@ -55,10 +56,28 @@ impl BodySender {
         }
     }
 
-    pub async fn send_end(&mut self) -> Result<(), Error> {
+    pub async fn send_end(&mut self, trailers: Option<HeaderMap>) -> Result<(), Error> {
         match self {
-            BodySender::H1(s) => Ok(s.send_data(&[], true).await?),
-            BodySender::H2(s) => Ok(s.send_data(Bytes::new(), true)?),
+            BodySend::H1(s) => {
+                if trailers.is_some() {
+                    debug!("Trailers are not sent over http1.1");
+                }
+                Ok(s.send_data(&[], true).await?)
+            }
+            BodySend::H2(s) => match trailers {
+                Some(trailers) => Ok(s.send_trailers(trailers)?),
+                None => Ok(s.send_data(Bytes::new(), true)?),
+            },
+        }
+    }
+
+    /// Give up on sending the rest of the body.
+    ///
+    /// http2 resets the stream. For http1.1, dropping the stream before the end
+    /// fails the connection.
+    pub fn abort(&mut self) {
+        if let BodySend::H2(s) = self {
+            s.send_reset(h2::Reason::CANCEL);
         }
     }
 }
@@ -0,0 +1,966 @@
+//! Private HTTP cache (RFC 9111).
+
+use super::{Middleware, Next, ResponseMeta};
+use crate::either::Either;
+use crate::head_ext::HeaderMapExt;
+use crate::params::HReqParams;
+use crate::Body;
+use crate::Error;
+use futures_util::io::AsyncReadExt;
+use futures_util::io::Cursor;
+use http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Largest response body that is buffered to be stored in the cache.
+const MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
+
+/// Upper bound for the heuristic freshness of responses without explicit expiry.
+const MAX_HEURISTIC: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Request headers that make the request the caller's own business.
+const CONDITIONAL_HEADERS: &[&str] = &[
+    "if-none-match",
+    "if-modified-since",
+    "if-match",
+    "if-unmodified-since",
+    "range",
+];
+
+/// Headers of a `304` that must not replace the stored ones.
+const KEEP_ON_UPDATE: &[&str] = &["content-length", "content-encoding", "transfer-encoding"];
+
+/// Whether a response was served from the cache.
+///
+/// Available through [`ResponseExt::cache_status`] for requests sent with an
+/// [`Agent`] that has a cache.
+///
+/// [`ResponseExt::cache_status`]: trait.ResponseExt.html#tymethod.cache_status
+/// [`Agent`]: struct.Agent.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    /// Served from the cache without contacting the server.
+    Hit,
+    /// Fetched from the server, because it wasn't in the cache or couldn't be used.
+    Miss,
+    /// A stale entry the server confirmed to still be valid with a `304`.
+    Revalidated,
+}
+
+/// Storage for cached responses.
+///
+/// The built in stores are [`MemoryCache`] and [`DiskCache`]. The methods are called
+/// from within the request, and are expected to be quick.
+///
+/// [`MemoryCache`]: struct.MemoryCache.html
+/// [`DiskCache`]: struct.DiskCache.html
+pub trait CacheStore: Send + Sync + 'static {
+    /// Look up the entry for a key.
+    fn get(&self, key: &str) -> Option<CacheEntry>;
+
+    /// Store an entry, replacing any previous entry for the key.
+    fn put(&self, key: &str, entry: CacheEntry);
+
+    /// Remove the entry for a key.
+    fn remove(&self, key: &str);
+}
+
+/// A stored response, with what is needed to tell whether it is fresh.
+///
+/// Entries can be serialized with [`to_bytes`] for stores that keep them outside
+/// of memory.
+///
+/// [`to_bytes`]: struct.CacheEntry.html#method.to_bytes
+#[derive(Clone)]
+pub struct CacheEntry {
+    status: StatusCode,
+    version: http::Version,
+    headers: HeaderMap,
+    /// The body as received, before any content or charset decoding.
+    body: Vec<u8>,
+    /// Request header values for the names in the response `vary`.
+    vary: Vec<(String, Option<String>)>,
+    request_time: SystemTime,
+    response_time: SystemTime,
+}
+
+impl CacheEntry {
+    /// Approximate size of the entry in bytes.
+    pub fn size(&self) -> usize {
+        let headers: usize = self
+            .headers
+            .iter()
+            .map(|(k, v)| k.as_str().len() + v.len())
+            .sum();
+        headers + self.body.len()
+    }
+
+    /// Serialize the entry to bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.size() + 256);
+
+        out.extend_from_slice(ENTRY_MAGIC);
+        out.extend_from_slice(&self.status.as_u16().to_be_bytes());
+        out.push(version_to_u8(self.version));
+        out.extend_from_slice(&millis(self.request_time).to_be_bytes());
+        out.extend_from_slice(&millis(self.response_time).to_be_bytes());
+
+        out.extend_from_slice(&(self.headers.len() as u32).to_be_bytes());
+        for (name, value) in &self.headers {
+            put_bytes(&mut out, name.as_str().as_bytes());
+            put_bytes(&mut out, value.as_bytes());
+        }
+
+        out.extend_from_slice(&(self.vary.len() as u32).to_be_bytes());
+        for (name, value) in &self.vary {
+            put_bytes(&mut out, name.as_bytes());
+            match value {
+                Some(v) => {
+                    out.push(1);
+                    put_bytes(&mut out, v.as_bytes());
+                }
+                None => out.push(0),
+            }
+        }
+
+        put_bytes(&mut out, &self.body);
+
+        out
+    }
+
+    /// Deserialize an entry made by [`to_bytes`]. `None` if the bytes are not a valid
+    /// entry.
+    ///
+    /// [`to_bytes`]: struct.CacheEntry.html#method.to_bytes
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut r = ByteReader(bytes);
+
+        if r.take(ENTRY_MAGIC.len())? != ENTRY_MAGIC {
+            return None;
+        }
+
+        let status = StatusCode::from_u16(r.u16()?).ok()?;
+        let version = version_from_u8(r.u8()?)?;
+        let request_time = UNIX_EPOCH + Duration::from_millis(r.u64()?);
+        let response_time = UNIX_EPOCH + Duration::from_millis(r.u64()?);
+
+        let mut headers = HeaderMap::new();
+        for _ in 0..r.u32()? {
+            let name = http::header::HeaderName::from_bytes(r.bytes()?).ok()?;
+            let value = HeaderValue::from_bytes(r.bytes()?).ok()?;
+            headers.append(name, value);
+        }
+
+        let mut vary = vec![];
+        for _ in 0..r.u32()? {
+            let name = String::from_utf8(r.bytes()?.to_vec()).ok()?;
+            let value = match r.u8()? {
+                0 => None,
+                _ => Some(String::from_utf8(r.bytes()?.to_vec()).ok()?),
+            };
+            vary.push((name, value));
+        }
+
+        let body = r.bytes()?.to_vec();
+
+        Some(CacheEntry {
+            status,
+            version,
+            headers,
+            body,
+            vary,
+            request_time,
+            response_time,
+        })
+    }
+
+    fn time_header(&self, name: &str) -> Option<SystemTime> {
+        self.headers
+            .get_str(name)
+            .and_then(|v| httpdate::parse_http_date(v).ok())
+    }
+
+    /// The current age of the response (RFC 9111 4.2.3).
+    fn age(&self, now: SystemTime) -> Duration {
+        let since = |a: SystemTime, b: SystemTime| a.duration_since(b).unwrap_or_default();
+
+        let apparent = self
+            .time_header("date")
+            .map(|d| since(self.response_time, d))
+            .unwrap_or_default();
+        let delay = since(self.response_time, self.request_time);
+        let age_value = Duration::from_secs(self.headers.get_as("age").unwrap_or(0));
+
+        let initial = apparent.max(age_value + delay);
+
+        initial + since(now, self.response_time)
+    }
+
+    /// For how long the response is fresh (RFC 9111 4.2.1).
+    fn lifetime(&self) -> Duration {
+        let cc = CacheControl::from_headers(&self.headers);
+
+        if let Some(max_age) = cc.max_age {
+            return Duration::from_secs(max_age);
+        }
+
+        let date = self.time_header("date").unwrap_or(self.response_time);
+
+        if let Some(expires) = self.headers.get("expires") {
+            // invalid dates, like "0", mean already expired.
+            return expires
+                .to_str()
+                .ok()
+                .and_then(|e| httpdate::parse_http_date(e).ok())
+                .and_then(|e| e.duration_since(date).ok())
+                .unwrap_or_default();
+        }
+
+        match self.time_header("last-modified") {
+            Some(modified) if is_heuristic_status(self.status) => {
+                let since = date.duration_since(modified).unwrap_or_default();
+                (since / 10).min(MAX_HEURISTIC)
+            }
+            _ => Duration::from_secs(0),
+        }
+    }
+
+    /// Whether the entry can be used without revalidating.
+    fn is_fresh(&self, now: SystemTime, req_cc: &CacheControl) -> bool {
+        let cc = CacheControl::from_headers(&self.headers);
+
+        if cc.no_cache || req_cc.no_cache {
+            return false;
+        }
+
+        let age = self.age(now);
+
+        if let Some(max_age) = req_cc.max_age {
+            if age > Duration::from_secs(max_age) {
+                return false;
+            }
+        }
+
+        age < self.lifetime()
+    }
+
+    /// Whether the request has the same values for the `vary` headers.
+    fn matches_vary(&self, headers: &HeaderMap) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| header_joined(headers, name) == *value)
+    }
+
+    /// Freshen the entry with the headers of a `304` (RFC 9111 4.3.4).
+    fn update(&mut self, headers: &HeaderMap, request_time: SystemTime, response_time: SystemTime) {
+        for name in headers.keys() {
+            if KEEP_ON_UPDATE.contains(&name.as_str()) {
+                continue;
+            }
+            self.headers.remove(name);
+            for value in headers.get_all(name) {
+                self.headers.append(name.clone(), value.clone());
+            }
+        }
+
+        self.request_time = request_time;
+        self.response_time = response_time;
+    }
+
+    fn to_response(&self, now: SystemTime, status: CacheStatus) -> Response<Body> {
+        let mut res = Response::builder()
+            .status(self.status)
+            .version(self.version)
+            .body(Body::from_vec(self.body.clone()))
+            .expect("Response from cache entry");
+
+        *res.headers_mut() = self.headers.clone();
+        res.headers_mut()
+            .set("age", self.age(now).as_secs().to_string());
+        res.extensions_mut().insert(status);
+
+        res
+    }
+}
+
+impl fmt::Debug for CacheEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CacheEntry")
+            .field("status", &self.status)
+            .field("size", &self.size())
+            .finish()
+    }
+}
+
+const ENTRY_MAGIC: &[u8] = b"hreq-cache-1\n";
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+/// Reads the fields written by `CacheEntry::to_bytes`.
+struct ByteReader<'a>(&'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (a, b) = self.0.split_at(n);
+        self.0 = b;
+        Some(a)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2)
+            .map(|b| u16::from_be_bytes(b.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8)
+            .map(|b| u64::from_be_bytes(b.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+}
+
+fn millis(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+fn version_to_u8(v: http::Version) -> u8 {
+    match v {
+        http::Version::HTTP_09 => 0,
+        http::Version::HTTP_10 => 1,
+        http::Version::HTTP_2 => 3,
+        _ => 2,
+    }
+}
+
+fn version_from_u8(v: u8) -> Option<http::Version> {
+    Some(match v {
+        0 => http::Version::HTTP_09,
+        1 => http::Version::HTTP_10,
+        2 => http::Version::HTTP_11,
+        3 => http::Version::HTTP_2,
+        _ => return None,
+    })
+}
+
+/// The `cache-control` directives we act on.
+#[derive(Debug, Default, PartialEq)]
+struct CacheControl {
+    no_store: bool,
+    no_cache: bool,
+    max_age: Option<u64>,
+}
+
+impl CacheControl {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let mut cc = CacheControl::default();
+        let mut found = false;
+
+        for value in headers.get_all("cache-control") {
+            let value = match value.to_str() {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+
+            for directive in value.split(',') {
+                found = true;
+
+                let mut kv = directive.splitn(2, '=');
+                let key = kv.next().unwrap_or("").trim().to_ascii_lowercase();
+                let arg = kv.next().map(|v| v.trim().trim_matches('"'));
+
+                match key.as_str() {
+                    "no-store" => cc.no_store = true,
+                    "no-cache" => cc.no_cache = true,
+                    // an invalid max-age means stale.
+                    "max-age" => cc.max_age = Some(arg.and_then(|v| v.parse().ok()).unwrap_or(0)),
+                    _ => {}
+                }
+            }
+        }
+
+        // HTTP/1.0 pragma only counts without cache-control.
+        if !found {
+            let pragma = headers.get_str("pragma").unwrap_or("");
+            cc.no_cache = pragma.to_ascii_lowercase().contains("no-cache");
+        }
+
+        cc
+    }
+}
+
+/// Statuses that may be given a heuristic freshness (RFC 9110 15.1).
+fn is_heuristic_status(status: StatusCode) -> bool {
+    matches!(
+        status.as_u16(),
+        200 | 203 | 204 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501
+    )
+}
+
+/// Header names listed in `vary`, lowercased.
+fn vary_names(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .get_all("vary")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|n| n.trim().to_ascii_lowercase())
+        .filter(|n| !n.is_empty())
+        .collect()
+}
+
+/// All values of a header joined with `, `.
+fn header_joined(headers: &HeaderMap, name: &str) -> Option<String> {
+    let values: Vec<_> = headers
+        .get_all(name)
+        .iter()
+        .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+        .collect();
+
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.join(", "))
+    }
+}
+
+/// Whether a response may be stored (RFC 9111 3).
+fn is_storable(req_cc: &CacheControl, res: &Response<Body>) -> bool {
+    let status = res.status();
+
+    if status.is_informational() || status == 206 || status == 304 {
+        return false;
+    }
+
+    let cc = CacheControl::from_headers(res.headers());
+
+    if req_cc.no_store || cc.no_store {
+        return false;
+    }
+
+    if vary_names(res.headers()).iter().any(|n| n == "*") {
+        return false;
+    }
+
+    let headers = res.headers();
+    let explicit = cc.max_age.is_some() || headers.contains_key("expires");
+    let validator = headers.contains_key("etag") || headers.contains_key("last-modified");
+
+    explicit || validator && is_heuristic_status(status)
+}
+
+/// Whether the response is for the request uri, and not the end of some redirects,
+/// or made up by middleware.
+fn is_direct(res: &Response<Body>) -> bool {
+    res.extensions()
+        .get::<ResponseMeta>()
+        .map(|m| m.redirects == 0)
+        .unwrap_or(false)
+}
+
+fn with_status(mut res: Response<Body>, status: CacheStatus) -> Response<Body> {
+    res.extensions_mut().insert(status);
+    res
+}
+
+/// Reads the body as received, without content or charset decoding.
+///
+/// Bodies bigger than `MAX_BODY_SIZE` are given back as a body to read instead.
+async fn read_raw(
+    body: Body,
+    parts: &http::response::Parts,
+) -> Result<Either<Vec<u8>, Body>, Error> {
+    let mut params = parts
+        .extensions
+        .get::<HReqParams>()
+        .cloned()
+        .unwrap_or_else(HReqParams::new);
+    params.content_decode = false;
+    params.charset_rx.toggle_target(false);
+
+    let mut body = body.unconfigure();
+    body.configure(&params, &parts.headers, true);
+
+    let too_big = parts
+        .headers
+        .get_as::<u64>("content-length")
+        .map(|l| l > MAX_BODY_SIZE as u64)
+        .unwrap_or(false);
+
+    if too_big {
+        return Ok(Either::B(Body::from_async_read(body, None)));
+    }
+
+    let mut buf = Vec::new();
+    (&mut body)
+        .take(MAX_BODY_SIZE as u64 + 1)
+        .read_to_end(&mut buf)
+        .await?;
+
+    if buf.len() > MAX_BODY_SIZE {
+        // give back what was read, followed by the rest.
+        let reader = Cursor::new(buf).chain(body);
+        return Ok(Either::B(Body::from_async_read(reader, None)));
+    }
+
+    Ok(Either::A(buf))
+}
+
+/// The middleware serving requests from a `CacheStore`.
+pub(crate) struct Cache {
+    store: Box<dyn CacheStore>,
+}
+
+impl Cache {
+    pub fn new<S: CacheStore>(store: S) -> Self {
+        Cache {
+            store: Box::new(store),
+        }
+    }
+
+    async fn handle(&self, mut req: Request<Body>, next: Next) -> Result<Response<Body>, Error> {
+        let key = req.uri().to_string();
+        let method = req.method().clone();
+
+        if method != Method::GET {
+            let res = next.run(req).await?;
+
+            // a successful unsafe request invalidates what we have (RFC 9111 4.4).
+            let status = res.status();
+            if !method.is_safe() && (status.is_success() || status.is_redirection()) {
+                self.store.remove(&key);
+            }
+
+            return Ok(res);
+        }
+
+        let req_cc = CacheControl::from_headers(req.headers());
+
+        let is_conditional = CONDITIONAL_HEADERS
+            .iter()
+            .any(|h| req.headers().contains_key(*h));
+
+        if req_cc.no_store || is_conditional {
+            let res = next.run(req).await?;
+            return Ok(with_status(res, CacheStatus::Miss));
+        }
+
+        let entry = self
+            .store
+            .get(&key)
+            .filter(|e| e.matches_vary(req.headers()));
+
+        if let Some(entry) = &entry {
+            let now = SystemTime::now();
+
+            if entry.is_fresh(now, &req_cc) {
+                trace!("Cache hit: {}", key);
+                return Ok(entry.to_response(now, CacheStatus::Hit));
+            }
+
+            // stale, ask the server whether it's still valid.
+            if let Some(etag) = entry.headers.get("etag") {
+                req.headers_mut().insert("if-none-match", etag.clone());
+            }
+            if let Some(modified) = entry.headers.get("last-modified") {
+                req.headers_mut()
+                    .insert("if-modified-since", modified.clone());
+            }
+        }
+
+        let req_headers = req.headers().clone();
+
+        let request_time = SystemTime::now();
+        let res = next.run(req).await?;
+        let response_time = SystemTime::now();
+
+        if res.status() == 304 && is_direct(&res) {
+            if let Some(mut entry) = entry {
+                trace!("Cache revalidated: {}", key);
+
+                let (parts, mut body) = res.into_parts();
+
+                // there's no body, but reading it ends the request on the connection.
+                body.read_and_discard().await?;
+
+                entry.update(&parts.headers, request_time, response_time);
+                self.store.put(&key, entry.clone());
+
+                let mut res = entry.to_response(response_time, CacheStatus::Revalidated);
+
+                // the metadata is from the request that revalidated.
+                if let Some(meta) = parts.extensions.get::<ResponseMeta>() {
+                    res.extensions_mut().insert(meta.clone());
+                }
+
+                return Ok(res);
+            }
+        }
+
+        if !is_direct(&res) || !is_storable(&req_cc, &res) {
+            return Ok(with_status(res, CacheStatus::Miss));
+        }
+
+        let vary = vary_names(res.headers())
+            .into_iter()
+            .map(|name| {
+                let value = header_joined(&req_headers, &name);
+                (name, value)
+            })
+            .collect();
+
+        let (mut parts, body) = res.into_parts();
+
+        let body = match read_raw(body, &parts).await? {
+            Either::A(data) => {
+                trace!("Cache store: {}", key);
+
+                let entry = CacheEntry {
+                    status: parts.status,
+                    version: parts.version,
+                    headers: parts.headers.clone(),
+                    body: data,
+                    vary,
+                    request_time,
+                    response_time,
+                };
+
+                let body = Body::from_vec(entry.body.clone());
+                self.store.put(&key, entry);
+
+                body
+            }
+            Either::B(body) => {
+                debug!("Response too big to cache: {}", key);
+                body
+            }
+        };
+
+        parts.extensions.insert(CacheStatus::Miss);
+
+        Ok(Response::from_parts(parts, body))
+    }
+}
+
+impl Middleware for Cache {
+    fn call<'a>(
+        &'a self,
+        req: Request<Body>,
+        next: Next,
+    ) -> Pin<Box<dyn Future<Output = Result<Response<Body>, Error>> + Send + 'a>> {
+        Box::pin(self.handle(req, next))
+    }
+}
+
+/// In-memory cache store, evicting the least recently used entries.
+///
+/// ```
+/// use hreq::{Agent, MemoryCache};
+///
+/// let mut agent = Agent::new();
+/// agent.cache(MemoryCache::new(16 * 1024 * 1024));
+/// ```
+pub struct MemoryCache {
+    max_size: usize,
+    inner: Mutex<Lru>,
+}
+
+#[derive(Default)]
+struct Lru {
+    tick: u64,
+    size: usize,
+    entries: HashMap<String, (u64, CacheEntry)>,
+}
+
+impl MemoryCache {
+    /// Creates a store holding at most `max_size` bytes of entries.
+    pub fn new(max_size: usize) -> Self {
+        MemoryCache {
+            max_size,
+            inner: Mutex::new(Lru::default()),
+        }
+    }
+}
+
+impl Default for MemoryCache {
+    /// A store of at most 32MB.
+    fn default() -> Self {
+        MemoryCache::new(32 * 1024 * 1024)
+    }
+}
+
+impl CacheStore for MemoryCache {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        let mut lru = self.inner.lock().unwrap();
+        lru.tick += 1;
+        let tick = lru.tick;
+
+        lru.entries.get_mut(key).map(|(used, entry)| {
+            *used = tick;
+            entry.clone()
+        })
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        self.remove(key);
+
+        let size = entry.size();
+        if size > self.max_size {
+            return;
+        }
+
+        let mut lru = self.inner.lock().unwrap();
+        lru.tick += 1;
+        let tick = lru.tick;
+
+        lru.size += size;
+        lru.entries.insert(key.to_string(), (tick, entry));
+
+        while lru.size > self.max_size {
+            let oldest = lru
+                .entries
+                .iter()
+                .min_by_key(|(_, (used, _))| *used)
+                .map(|(k, _)| k.clone());
+
+            if let Some(k) = oldest {
+                trace!("Cache evict: {}", k);
+                if let Some((_, e)) = lru.entries.remove(&k) {
+                    lru.size -= e.size();
+                }
+            }
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        let mut lru = self.inner.lock().unwrap();
+        if let Some((_, e)) = lru.entries.remove(key) {
+            lru.size -= e.size();
+        }
+    }
+}
+
+impl fmt::Debug for MemoryCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lru = self.inner.lock().unwrap();
+        f.debug_struct("MemoryCache")
+            .field("max_size", &self.max_size)
+            .field("size", &lru.size)
+            .field("entries", &lru.entries.len())
+            .finish()
+    }
+}
+
+/// On-disk cache store, one file per entry in a directory.
+///
+/// The directory is created when the first entry is stored. Files are read and
+/// written with blocking IO.
+///
+/// ```
+/// use hreq::{Agent, DiskCache};
+///
+/// let mut agent = Agent::new();
+/// agent.cache(DiskCache::new("/tmp/my-cache"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    /// Creates a store keeping entries in `dir`.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        DiskCache { dir: dir.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let hash = Sha256::digest(key.as_bytes());
+        let name: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
+        self.dir.join(name)
+    }
+}
+
+impl CacheStore for DiskCache {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        let bytes = fs::read(self.path(key)).ok()?;
+        let mut r = ByteReader(&bytes);
+
+        // the file starts with the key, to rule out hash collisions.
+        if r.bytes()? != key.as_bytes() {
+            return None;
+        }
+
+        CacheEntry::from_bytes(r.0)
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        let path = self.path(key);
+        let tmp = path.with_extension("tmp");
+
+        let mut bytes = vec![];
+        put_bytes(&mut bytes, key.as_bytes());
+        bytes.extend_from_slice(&entry.to_bytes());
+
+        let res = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&tmp, bytes))
+            .and_then(|_| fs::rename(&tmp, &path));
+
+        if let Err(e) = res {
+            debug!("Failed to write cache file {:?}: {}", path, e);
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        let _ = fs::remove_file(self.path(key));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(headers: &[(&'static str, &str)], age: u64) -> CacheEntry {
+        let mut map = HeaderMap::new();
+        for (k, v) in headers {
+            map.append(*k, v.parse().unwrap());
+        }
+        let time = SystemTime::now() - Duration::from_secs(age);
+        CacheEntry {
+            status: StatusCode::OK,
+            version: http::Version::HTTP_11,
+            headers: map,
+            body: b"hello".to_vec(),
+            vary: vec![("accept".into(), Some("text/plain".into()))],
+            request_time: time,
+            response_time: time,
+        }
+    }
+
+    fn cc(v: &str) -> CacheControl {
+        let mut map = HeaderMap::new();
+        map.insert("cache-control", v.parse().unwrap());
+        CacheControl::from_headers(&map)
+    }
+
+    #[test]
+    fn parse_cache_control() {
+        assert_eq!(
+            cc("public, max-age=60"),
+            CacheControl {
+                max_age: Some(60),
+                ..Default::default()
+            }
+        );
+        assert!(cc("no-cache=\"set-cookie\"").no_cache);
+        assert!(cc("No-Store").no_store);
+        assert_eq!(cc("max-age=abc").max_age, Some(0));
+
+        let mut map = HeaderMap::new();
+        map.insert("pragma", "no-cache".parse().unwrap());
+        assert!(CacheControl::from_headers(&map).no_cache);
+    }
+
+    #[test]
+    fn freshness() {
+        let now = SystemTime::now();
+        let none = CacheControl::default();
+
+        assert!(entry(&[("cache-control", "max-age=60")], 10).is_fresh(now, &none));
+        assert!(!entry(&[("cache-control", "max-age=60")], 70).is_fresh(now, &none));
+        assert!(!entry(&[("cache-control", "max-age=60"), ("age", "55")], 10).is_fresh(now, &none));
+        assert!(!entry(&[("cache-control", "max-age=60, no-cache")], 0).is_fresh(now, &none));
+        assert!(!entry(&[("cache-control", "max-age=60")], 10).is_fresh(now, &cc("max-age=5")));
+        assert!(!entry(&[("cache-control", "max-age=60")], 10).is_fresh(now, &cc("no-cache")));
+
+        let date = httpdate::fmt_http_date(now - Duration::from_secs(10));
+        let expires = httpdate::fmt_http_date(now + Duration::from_secs(50));
+        assert!(entry(&[("date", &date), ("expires", &expires)], 10).is_fresh(now, &none));
+        assert!(!entry(&[("date", &date), ("expires", "0")], 10).is_fresh(now, &none));
+
+        // heuristic, 10% of 100 days since modified.
+        let modified = httpdate::fmt_http_date(now - Duration::from_secs(100 * 86400));
+        let e = entry(&[("date", &date), ("last-modified", &modified)], 10);
+        assert_eq!(e.lifetime(), MAX_HEURISTIC);
+        assert!(e.is_fresh(now, &none));
+
+        assert!(!entry(&[("etag", "\"x\"")], 0).is_fresh(now, &none));
+    }
+
+    #[test]
+    fn vary_match() {
+        let e = entry(&[], 0);
+        let mut map = HeaderMap::new();
+        assert!(!e.matches_vary(&map));
+        map.insert("accept", "text/plain".parse().unwrap());
+        assert!(e.matches_vary(&map));
+        map.insert("accept", "text/html".parse().unwrap());
+        assert!(!e.matches_vary(&map));
+    }
+
+    #[test]
+    fn entry_bytes_roundtrip() {
+        let e = entry(
+            &[
+                ("etag", "\"x\""),
+                ("set-cookie", "a=1"),
+                ("set-cookie", "b=2"),
+            ],
+            3,
+        );
+        let bytes = e.to_bytes();
+        let e2 = CacheEntry::from_bytes(&bytes).unwrap();
+
+        assert_eq!(e2.status, e.status);
+        assert_eq!(e2.headers, e.headers);
+        assert_eq!(e2.body, e.body);
+        assert_eq!(e2.vary, e.vary);
+        assert_eq!(millis(e2.response_time), millis(e.response_time));
+
+        assert!(CacheEntry::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+        assert!(CacheEntry::from_bytes(b"garbage").is_none());
+    }
+
+    #[test]
+    fn memory_lru_evicts() {
+        let size = entry(&[], 0).size();
+        let store = MemoryCache::new(size * 2);
+
+        store.put("a", entry(&[], 0));
+        store.put("b", entry(&[], 0));
+        assert!(store.get("a").is_some());
+
+        // b is least recently used.
+        store.put("c", entry(&[], 0));
+        assert!(store.get("a").is_some());
+        assert!(store.get("b").is_none());
+        assert!(store.get("c").is_some());
+
+        store.remove("a");
+        assert!(store.get("a").is_none());
+    }
+}
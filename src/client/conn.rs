@@ -1,15 +1,23 @@
+use crate::body_codec::accept_encoding;
 use crate::body_codec::BodyImpl;
-use crate::body_send::BodySender;
+use crate::body_send::BodySend;
 use crate::bw::BandwidthMonitor;
+use crate::client::expect::Expect;
+use crate::client::Proxy;
+#[cfg(feature = "tls")]
+use crate::client::TlsConfig;
+use crate::client::{ConnMeta, ResponseMeta};
 use crate::head_ext::HeaderMapExt;
 use crate::params::HReqParams;
 use crate::uninit::UninitBuf;
 use crate::uri_ext::HostPort;
 use crate::uri_ext::MethodExt;
+use crate::AsyncRuntime;
 use crate::Body;
 use crate::Error;
 use crate::AGENT_IDENT;
 use bytes::Bytes;
+use futures_util::future::poll_fn;
 use futures_util::ready;
 use h2;
 use h2::client::SendRequest as H2SendRequest;
@@ -20,49 +28,216 @@ use std::fmt;
 use std::future::Future;
 use std::io;
 use std::pin::Pin;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::task::Context;
 use std::task::Poll;
+use std::time::{Duration, Instant};
 
 static ID_COUNTER: Lazy<AtomicUsize> = Lazy::new(|| AtomicUsize::new(0));
 const START_BUF_SIZE: usize = 16_384;
 const MAX_BUF_SIZE: usize = 2 * 1024 * 1024;
 
-// #[derive(Clone)]
+/// A connection to a host.
+///
+/// Clones share the same underlying connection. A clone held outside the pool means
+/// the connection is checked out, which for http1 means it's not idle.
+#[derive(Clone)]
 pub struct Connection {
     id: usize,
     host_port: HostPort,
     inner: Inner,
     unfinished_reqs: Arc<()>,
     bw: Option<BandwidthMonitor>,
+    proxy: Option<Proxy>,
+    via_proxy: Option<Proxy>,
+    force_http2: bool,
+    tls_disable_verify: bool,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<TlsConfig>>,
+    alive: Arc<AtomicBool>,
+    last_used: LastUsed,
+    meta: Arc<ConnMeta>,
 }
 
+/// The time a connection was last used, shared between all clones of it.
+#[derive(Clone)]
+struct LastUsed {
+    epoch: Instant,
+    millis: Arc<AtomicU64>,
+}
+
+impl LastUsed {
+    fn new() -> Self {
+        LastUsed {
+            epoch: Instant::now(),
+            millis: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn touch(&self) {
+        let millis = self.epoch.elapsed().as_millis() as u64;
+        self.millis.fetch_max(millis, Ordering::Relaxed);
+    }
+
+    fn elapsed(&self) -> Duration {
+        let last = Duration::from_millis(self.millis.load(Ordering::Relaxed));
+        self.epoch.elapsed().saturating_sub(last)
+    }
+}
+
+/// Keeps the connection busy while a request is unfinished.
+///
+/// Releasing it counts as using the connection, so the idle time is measured from
+/// when the request finished.
+pub(crate) struct Unfinished {
+    _reqs: Arc<()>,
+    last_used: LastUsed,
+}
+
+impl Drop for Unfinished {
+    fn drop(&mut self) {
+        self.last_used.touch();
+    }
+}
+
+#[derive(Clone)]
 enum Inner {
-    H1(H1SendRequest),
+    H1(H1SendRequest, Expect),
     H2(H2SendRequest<Bytes>),
 }
 
 impl Connection {
-    pub(crate) fn new_h1(host_port: HostPort, conn: H1SendRequest) -> Self {
-        Self::new(host_port, Inner::H1(conn), None)
+    pub(crate) fn new_h1(
+        host_port: HostPort,
+        conn: H1SendRequest,
+        expect: Expect,
+        alive: Arc<AtomicBool>,
+    ) -> Self {
+        Self::new(host_port, Inner::H1(conn, expect), None, alive)
     }
 
     pub(crate) fn new_h2(
         host_port: HostPort,
         conn: H2SendRequest<Bytes>,
         bw: BandwidthMonitor,
+        alive: Arc<AtomicBool>,
     ) -> Self {
-        Self::new(host_port, Inner::H2(conn), Some(bw))
+        Self::new(host_port, Inner::H2(conn), Some(bw), alive)
     }
 
-    fn new(host_port: HostPort, inner: Inner, bw: Option<BandwidthMonitor>) -> Self {
+    fn new(
+        host_port: HostPort,
+        inner: Inner,
+        bw: Option<BandwidthMonitor>,
+        alive: Arc<AtomicBool>,
+    ) -> Self {
         Connection {
             id: ID_COUNTER.fetch_add(1, Ordering::Relaxed),
             host_port,
             inner: inner,
             unfinished_reqs: Arc::new(()),
             bw,
+            proxy: None,
+            via_proxy: None,
+            force_http2: false,
+            tls_disable_verify: false,
+            #[cfg(feature = "tls")]
+            tls: None,
+            alive,
+            last_used: LastUsed::new(),
+            meta: Arc::new(ConnMeta::default()),
+        }
+    }
+
+    /// This connection goes via a proxy. For absolute-form, the requests are rewritten
+    /// to be sent to the proxy.
+    pub(crate) fn via_proxy(mut self, proxy: &Proxy, absolute_form: bool) -> Self {
+        self.via_proxy = Some(proxy.clone());
+        if absolute_form {
+            self.proxy = Some(proxy.clone());
+        }
+        self
+    }
+
+    /// Whether the connection goes via the same kind of proxy, with the same credentials.
+    /// The credentials are sent with every request in absolute-form.
+    pub(crate) fn is_same_proxy(&self, proxy: Option<&Proxy>) -> bool {
+        match (&self.via_proxy, proxy) {
+            (None, None) => true,
+            (Some(a), Some(b)) => a.is_same(b),
+            _ => false,
+        }
+    }
+
+    /// Facts about the connection, noted when it was opened.
+    pub(crate) fn with_meta(mut self, meta: ConnMeta) -> Self {
+        self.meta = Arc::new(meta);
+        self
+    }
+
+    /// The request settings this connection was made with.
+    pub(crate) fn with_settings(mut self, force_http2: bool, tls_disable_verify: bool) -> Self {
+        self.force_http2 = force_http2;
+        self.tls_disable_verify = tls_disable_verify;
+        self
+    }
+
+    /// Whether the connection was made with the same request settings. An unverified
+    /// TLS connection must not be used for a request that wants verification.
+    pub(crate) fn is_same_settings(&self, force_http2: bool, tls_disable_verify: bool) -> bool {
+        self.force_http2 == force_http2 && self.tls_disable_verify == tls_disable_verify
+    }
+
+    /// The TLS config this connection was made with.
+    #[cfg(feature = "tls")]
+    pub(crate) fn with_tls(mut self, tls: Option<Arc<TlsConfig>>) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Whether the connection was made with the same TLS config. Configs are compared
+    /// by identity, not by the settings within.
+    #[cfg(feature = "tls")]
+    pub(crate) fn is_same_tls(&self, tls: Option<&Arc<TlsConfig>>) -> bool {
+        match (&self.tls, tls) {
+            (None, None) => true,
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// Tells whether the task driving the connection is still running.
+    pub(crate) fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::Relaxed)
+    }
+
+    /// Tells whether there are no ongoing requests on this connection.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.unfinished_requests() == 0
+    }
+
+    /// How long this connection has been idle.
+    ///
+    /// The time is measured from the last finished request or the last time the pool
+    /// observed the connection not being idle.
+    pub(crate) fn idle_for(&self) -> Duration {
+        if self.is_idle() {
+            self.last_used.elapsed()
+        } else {
+            Duration::from_secs(0)
+        }
+    }
+
+    /// Update the last used time.
+    pub(crate) fn touch(&self) {
+        self.last_used.touch();
+    }
+
+    /// Update the last used time if the connection is busy.
+    pub(crate) fn touch_if_busy(&self) {
+        if !self.is_idle() {
+            self.touch();
         }
     }
 
@@ -76,7 +251,7 @@ impl Connection {
 
     pub(crate) fn is_http2(&self) -> bool {
         match self.inner {
-            Inner::H1(_) => false,
+            Inner::H1(..) => false,
             Inner::H2(_) => true,
         }
     }
@@ -91,7 +266,12 @@ impl Connection {
         body_buffer: &mut BodyBuf,
     ) -> Result<http::Response<Body>, Error> {
         // up the arc-counter on unfinished reqs
-        let unfin = self.unfinished_reqs.clone();
+        let unfin = Unfinished {
+            _reqs: self.unfinished_reqs.clone(),
+            last_used: self.last_used.clone(),
+        };
+
+        self.touch();
 
         let (mut parts, mut body) = req.into_parts();
 
@@ -110,6 +290,10 @@ impl Connection {
 
         configure_request(&mut parts, &body, self.is_http2());
 
+        if let Some(proxy) = &self.proxy {
+            proxy.configure_request(&mut parts)?;
+        }
+
         let req = http::Request::from_parts(parts, body);
 
         trace!(
@@ -124,11 +308,24 @@ impl Connection {
         // every request gets their own copy to track received bytes
         let bw = self.bw.clone();
 
+        let start = Instant::now();
+
         // send request against a deadline
-        let response = deadline
-            .race(send_req(req, body_buffer, &self.inner, unfin, bw))
+        let mut response = deadline
+            .race(send_req(
+                req,
+                body_buffer,
+                &self.inner,
+                unfin,
+                bw,
+                &self.alive,
+            ))
             .await?;
 
+        response
+            .extensions_mut()
+            .insert(ResponseMeta::new(self.meta.clone(), start.elapsed()));
+
         Ok(response)
     }
 }
@@ -161,6 +358,19 @@ pub(crate) fn configure_request(parts: &mut http::request::Parts, body: &Body, i
         parts.headers.set("accept", "*/*");
     }
 
+    if parts.headers.get("accept-encoding").is_none() {
+        let content_decode = parts
+            .extensions
+            .get::<HReqParams>()
+            .map(|p| p.content_decode)
+            .unwrap_or(true);
+
+        // advertise the encodings we can decode.
+        if let Some(encodings) = accept_encoding().filter(|_| content_decode) {
+            parts.headers.set("accept-encoding", encodings);
+        }
+    }
+
     if parts.headers.get("content-type").is_none() {
         if let Some(ctype) = body.content_type() {
             parts.headers.set("content-type", ctype);
@@ -172,23 +382,68 @@ async fn send_req(
     req: http::Request<Body>,
     body_buffer: &mut BodyBuf,
     proto: &Inner,
-    unfin: Arc<()>,
+    unfin: Unfinished,
     bw: Option<BandwidthMonitor>,
+    alive: &AtomicBool,
 ) -> Result<http::Response<Body>, Error> {
     let params = req.extensions().get::<HReqParams>().unwrap().clone();
 
-    let (parts, mut body_read) = req.into_parts();
-    let req = http::Request::from_parts(parts, ());
+    let (mut parts, mut body_read) = req.into_parts();
 
     let no_body = body_read.is_definitely_no_body() && body_buffer.len() == 0;
 
+    // http1.1 can ask the server whether it wants the body before sending it.
+    let expect = match proto {
+        Inner::H1(_, expect) if !no_body => {
+            let user_set = parts.headers.get_str("expect") == Some("100-continue");
+            if user_set || params.use_expect_continue(body_read.content_encoded_length()) {
+                Some(expect)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+
+    if let Some(expect) = expect {
+        parts.headers.set("expect", "100-continue");
+        expect.arm();
+    }
+
+    let is_chunked = parts.headers.get_str("transfer-encoding") == Some("chunked");
+
+    let req = http::Request::from_parts(parts, ());
+
     let (mut res_fut, mut body_send) = proto.do_send(req, no_body).await?;
     let mut early_response = None;
 
+    if let Some(expect) = expect {
+        let mut timer = Box::pin(AsyncRuntime::timeout(params.expect_continue_timeout));
+
+        // wait for 100 Continue, a final response or the timeout. the body is sent
+        // unless the server responds with a final status.
+        early_response = poll_fn(|cx| {
+            if let Poll::Ready(v) = Pin::new(&mut res_fut).poll(cx) {
+                trace!("Final response instead of 100 Continue");
+                return Poll::Ready(Some(v));
+            }
+            if expect.poll_continue(cx).is_ready() {
+                trace!("Got 100 Continue");
+                return Poll::Ready(None);
+            }
+            if timer.as_mut().poll(cx).is_ready() {
+                trace!("No 100 Continue before timeout, send body");
+                return Poll::Ready(None);
+            }
+            Poll::Pending
+        })
+        .await;
+    }
+
     // this buffer should probably be less than h2 window size
     let mut buf = UninitBuf::with_capacity(START_BUF_SIZE, MAX_BUF_SIZE);
 
-    if !no_body {
+    if !no_body && early_response.is_none() {
         let mut use_body_buf = true;
 
         loop {
@@ -199,8 +454,9 @@ async fn send_req(
                     // early response did not happen, keep sending body
                 }
                 TryOnce::Ready(v) => {
-                    // TODO: For now we assume an early response means aborting the
-                    // body sending. This is not true for expect 100-continue.
+                    // a final response stops the body sending (a 100 Continue is
+                    // waited for above). for expect, a body cut short of its
+                    // content-length makes the connection unusable, see below.
                     early_response = Some(v);
                     break;
                 }
@@ -221,7 +477,16 @@ async fn send_req(
 
             // read new body data
             if !use_body_buf {
-                let n = buf.read_from_async(&mut body_read).await?;
+                let n = match buf.read_from_async(&mut body_read).await {
+                    Ok(n) => n,
+                    Err(e) => {
+                        // the server can't tell a cut short body from a complete one.
+                        trace!("Body read failed, abort sending: {}", e);
+                        body_send.abort();
+                        alive.store(false, Ordering::Relaxed);
+                        return Err(e.into());
+                    }
+                };
 
                 // Append read data to the body_buffer in case of 307/308 redirect.
                 // The body_buffer might be inert and no bytes are retained.break
@@ -241,11 +506,22 @@ async fn send_req(
             // Ship it to they underlying http1.1/http2 layer.
             body_send.send_data(&buf[0..amount_read]).await?;
         }
+    }
+
+    if !no_body {
+        let trailers = body_read.take_trailers();
 
         // pass the body back with the buffer
         body_buffer.return_body = Some(body_read);
 
-        body_send.send_end().await?;
+        body_send.send_end(trailers).await?;
+    }
+
+    if expect.is_some() && early_response.is_some() && !is_chunked {
+        // the body was cut short of the content-length, the server is likely still
+        // expecting the rest which makes the connection unusable for more requests.
+        trace!("Body aborted, connection not reusable");
+        alive.store(false, Ordering::Relaxed);
     }
 
     let (mut parts, mut res_body) = if let Some(res) = early_response {
@@ -268,17 +544,17 @@ impl Inner {
         &self,
         req: http::Request<()>,
         no_body: bool,
-    ) -> Result<(ResponseFuture, BodySender), Error> {
+    ) -> Result<(ResponseFuture, BodySend), Error> {
         Ok(match self {
-            Inner::H1(h1) => {
+            Inner::H1(h1, _) => {
                 let mut h1 = h1.clone();
                 let (fut, send_body) = h1.send_request(req, no_body)?;
-                (ResponseFuture::H1(fut), BodySender::H1(send_body))
+                (ResponseFuture::H1(fut), BodySend::H1(send_body))
             }
             Inner::H2(h2) => {
                 let mut h2 = h2.clone().ready().await?;
                 let (fut, send_body) = h2.send_request(req, no_body)?;
-                (ResponseFuture::H2(fut), BodySender::H2(send_body))
+                (ResponseFuture::H2(fut), BodySend::H2(send_body))
             }
         })
     }
@@ -400,6 +676,11 @@ impl BodyBuf {
         }
     }
 
+    /// Whether all data appended so far is retained, and can be sent again.
+    pub fn is_retained(&self) -> bool {
+        self.vec.is_some()
+    }
+
     /// Current amount of retained bytes.
     fn len(&self) -> usize {
         self.vec.as_ref().map(|v| v.len()).unwrap_or(0)
@@ -424,7 +705,7 @@ impl io::Read for BodyBuf {
 impl fmt::Display for Inner {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Inner::H1(_) => write!(f, "Http1"),
+            Inner::H1(..) => write!(f, "Http1"),
             Inner::H2(_) => write!(f, "Http2"),
         }
     }
@@ -1,9 +1,14 @@
-//! The structure cookie::CookieJar does not separate cookies per domain. Cookies does.
+//! Cookie storage for the agent, separating cookies per domain.
 
 use crate::uri_ext::UriExt;
-use cookie::{Cookie, CookieJar};
+use crate::Error;
+use cookie::{Cookie, SameSite};
 use psl::{List, Psl};
+use serde_json::{json, Value};
 use std::collections::hash_map::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::Mutex;
 use time::{Duration, OffsetDateTime};
 
 /// Technically a cookie without a max age, is a session cookie. hreq currently
@@ -11,46 +16,197 @@ use time::{Duration, OffsetDateTime};
 /// just offset sessions cookies indefinitely.
 const DEFAULT_COOKIE_MAX_AGES_DAYS: i64 = 9999;
 
-#[derive(Debug)]
-pub(crate) struct Cookies {
-    domains: HashMap<String, CookieJar>,
+/// Storage of cookies for an [`Agent`].
+///
+/// The agent adds cookies from `set-cookie` response headers, and gets the cookies
+/// to send with each request. The built in store is [`CookieJar`]. The methods are
+/// called from within the request, and are expected to be quick.
+///
+/// Stores can be saved and loaded, for instance to keep sessions across restarts.
+///
+/// ```no_run
+/// use hreq::{Agent, CookieJar, CookieStore};
+/// use std::fs::File;
+///
+/// let jar = CookieJar::new();
+/// if let Ok(mut file) = File::open("cookies.json") {
+///     jar.load_json(&mut file).unwrap();
+/// }
+///
+/// let mut agent = Agent::new();
+/// agent.cookie_store(jar);
+///
+/// // ... use the agent ...
+///
+/// let mut file = File::create("cookies.json").unwrap();
+/// agent.get_cookie_store().save_json(&mut file).unwrap();
+/// ```
+///
+/// [`Agent`]: struct.Agent.html
+/// [`CookieJar`]: struct.CookieJar.html
+pub trait CookieStore: Send + Sync + 'static {
+    /// Store a cookie received from `uri`.
+    ///
+    /// The cookie replaces any stored cookie with the same domain, path and name. An
+    /// expired cookie removes it.
+    fn add(&self, uri: &http::Uri, cookie: Cookie<'static>);
+
+    /// The cookies to send in a request to `uri`.
+    fn get(&self, uri: &http::Uri) -> Vec<Cookie<'static>>;
+
+    /// Remove the cookie with the domain, path and name.
+    fn remove(&self, domain: &str, path: &str, name: &str);
+
+    /// Remove all cookies.
+    fn clear(&self);
+
+    /// All stored cookies. Each has a domain, path and expires.
+    ///
+    /// As in `cookies.txt`, the domain of a cookie that is also sent to sub-domains
+    /// starts with a dot, `.example.com`. A host-only cookie, received without a
+    /// `Domain` attribute, has the plain host name.
+    fn all(&self) -> Vec<Cookie<'static>>;
+
+    /// Remove cookies that have expired.
+    fn evict_expired(&self);
+
+    /// Save all cookies as a JSON array.
+    fn save_json(&self, out: &mut dyn io::Write) -> Result<(), Error> {
+        let all: Vec<Value> = self.all().iter().map(to_json).collect();
+        let bytes = serde_json::to_vec_pretty(&all)?;
+        out.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Load cookies saved with [`save_json`], adding them to the store.
+    ///
+    /// [`save_json`]: trait.CookieStore.html#method.save_json
+    fn load_json(&self, input: &mut dyn io::Read) -> Result<(), Error> {
+        let mut bytes = vec![];
+        input.read_to_end(&mut bytes)?;
+
+        let all: Vec<Value> = serde_json::from_slice(&bytes)?;
+
+        for value in &all {
+            match from_json(value) {
+                Some(cookie) => add_loaded(self, cookie),
+                None => debug!("Ignore bad cookie in JSON: {}", value),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Save all cookies in the Netscape `cookies.txt` format, as used by curl and wget.
+    fn save_netscape(&self, out: &mut dyn io::Write) -> Result<(), Error> {
+        writeln!(out, "# Netscape HTTP Cookie File")?;
+        for cookie in self.all() {
+            writeln!(out, "{}", to_netscape(&cookie))?;
+        }
+        Ok(())
+    }
+
+    /// Load cookies in the Netscape `cookies.txt` format, adding them to the store.
+    fn load_netscape(&self, input: &mut dyn io::Read) -> Result<(), Error> {
+        let mut text = String::new();
+        input.read_to_string(&mut text)?;
+
+        for line in text.lines() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with('#') && !line.starts_with("#HttpOnly_") {
+                continue;
+            }
+            match from_netscape(line) {
+                Some(cookie) => add_loaded(self, cookie),
+                None => debug!("Ignore bad cookie line: {}", line),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The default, in memory, cookie store.
+///
+/// Cookies are kept per domain, and sent to the domain and its sub-domains when the
+/// path matches. Cookies without a `Domain` attribute are only sent to the host they
+/// came from. Cookies without `Max-Age` or `Expires` are kept for the lifetime of
+/// the store.
+#[derive(Default)]
+pub struct CookieJar {
+    domains: Mutex<HashMap<String, Vec<Cookie<'static>>>>,
 }
 
-impl Cookies {
+impl CookieJar {
+    /// Creates an empty cookie jar.
     pub fn new() -> Self {
-        Cookies {
-            domains: HashMap::new(),
+        CookieJar {
+            domains: Mutex::new(HashMap::new()),
         }
     }
+}
 
-    pub fn add(&mut self, uri: &http::Uri, mut cookie: Cookie<'static>) {
+impl CookieStore for CookieJar {
+    fn add(&self, uri: &http::Uri, mut cookie: Cookie<'static>) {
         let domain = match cookie.validated_domain(uri) {
             Some(v) => v,
             // the reason is logged already
             None => return,
         };
+
+        // RFC 6265 5.3 step 6, without a domain attribute the cookie is host-only.
+        if cookie.domain().is_some() {
+            cookie.set_domain(format!(".{}", domain));
+        } else {
+            cookie.set_domain(domain.clone());
+        }
+
+        // a missing, or bad, path is the "directory" of the uri path.
+        if !cookie.path().map(|p| p.starts_with('/')).unwrap_or(false) {
+            cookie.set_path(default_path(uri.path()).to_string());
+        }
+
+        let now = OffsetDateTime::now_utc();
+
         // all cookies must have an expires so we know when to remove them.
-        if cookie.expires().is_none() {
-            let max = if let Some(max) = cookie.max_age() {
-                max
-            } else {
-                Duration::days(DEFAULT_COOKIE_MAX_AGES_DAYS)
-            };
-            let exp = OffsetDateTime::now_utc() + max;
-            cookie.set_expires(Some(exp))
+        // max-age takes precedence over expires.
+        if let Some(max) = cookie.max_age() {
+            cookie.set_expires(now + max);
+        } else if expiry(&cookie).is_none() {
+            cookie.set_expires(now + Duration::days(DEFAULT_COOKIE_MAX_AGES_DAYS));
+        }
+
+        let mut domains = self.domains.lock().unwrap();
+        let jar = domains.entry(domain.clone()).or_insert_with(Vec::new);
+
+        jar.retain(|c| !(c.name() == cookie.name() && c.path() == cookie.path()));
+        if !is_expired(&cookie, now) {
+            jar.push(cookie);
+        }
+        jar.retain(|c| !is_expired(c, now));
+
+        if jar.is_empty() {
+            domains.remove(&domain);
         }
-        let jar = self.domains.entry(domain).or_insert_with(CookieJar::new);
-        jar.add(cookie);
     }
 
-    pub fn get(&self, uri: &http::Uri) -> Vec<&Cookie<'static>> {
+    fn get(&self, uri: &http::Uri) -> Vec<Cookie<'static>> {
         let mut ret = vec![];
 
         let is_secure = uri.is_secure();
+        let path = if uri.path().is_empty() {
+            "/"
+        } else {
+            uri.path()
+        };
         let now = OffsetDateTime::now_utc();
 
+        let domains = self.domains.lock().unwrap();
+
         // hold current host name. will go "a.b.com", "b.com", "com"
         let mut cur = Some(uri.clone());
+        // host-only cookies are only sent to the host itself, the first round.
+        let mut is_host = true;
         loop {
             // current host name, normalized
             let maybe_host = cur
@@ -66,31 +222,222 @@ impl Cookies {
 
             // if we have a jar for this hostname, add all the cookies with
             // matching path in it.
-            if let Some(jar) = self.domains.get(&host) {
-                for cookie in jar.iter() {
-                    // if there is no path in the cookie, it's a match.
-                    let path_match = cookie
-                        .path()
-                        .map(|p| uri.path().starts_with(p))
-                        .unwrap_or(true);
+            if let Some(jar) = domains.get(&host) {
+                for cookie in jar {
+                    if !is_host && is_host_only(cookie) {
+                        continue;
+                    }
+
+                    // all cookies have a path after added to jars above.
+                    let path_match = cookie.path().map(|p| path_match(path, p)).unwrap_or(true);
 
                     // if we are using https, no need to check cookie.
                     let secure_match = is_secure || !cookie.secure().unwrap_or(false);
 
-                    // unwrap is ok cause all cookies have expires() after added to jars above.
-                    let expired = cookie.expires().unwrap().datetime().unwrap() < now;
-
-                    if path_match && secure_match && !expired {
-                        ret.push(cookie);
+                    if path_match && secure_match && !is_expired(cookie, now) {
+                        ret.push(cookie.clone());
                     }
                 }
             }
 
             cur = cur.unwrap().parent_host();
+            is_host = false;
         }
 
+        // cookies with longer paths are listed first.
+        ret.sort_by_key(|c| std::cmp::Reverse(c.path().map(|p| p.len()).unwrap_or(0)));
+
         ret
     }
+
+    fn remove(&self, domain: &str, path: &str, name: &str) {
+        let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+        let mut domains = self.domains.lock().unwrap();
+        if let Some(jar) = domains.get_mut(&domain) {
+            jar.retain(|c| !(c.name() == name && c.path() == Some(path)));
+            if jar.is_empty() {
+                domains.remove(&domain);
+            }
+        }
+    }
+
+    fn clear(&self) {
+        self.domains.lock().unwrap().clear();
+    }
+
+    fn all(&self) -> Vec<Cookie<'static>> {
+        let domains = self.domains.lock().unwrap();
+        domains.values().flatten().cloned().collect()
+    }
+
+    fn evict_expired(&self) {
+        let now = OffsetDateTime::now_utc();
+        let mut domains = self.domains.lock().unwrap();
+        for jar in domains.values_mut() {
+            jar.retain(|c| !is_expired(c, now));
+        }
+        domains.retain(|_, jar| !jar.is_empty());
+    }
+}
+
+impl fmt::Debug for CookieJar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let domains = self.domains.lock().unwrap();
+        f.debug_struct("CookieJar")
+            .field("domains", &domains.len())
+            .field("cookies", &domains.values().map(|j| j.len()).sum::<usize>())
+            .finish()
+    }
+}
+
+/// Host-only cookies are stored without the leading dot of a domain cookie.
+fn is_host_only(cookie: &Cookie) -> bool {
+    !cookie.domain().map(|d| d.starts_with('.')).unwrap_or(false)
+}
+
+fn expiry(cookie: &Cookie) -> Option<OffsetDateTime> {
+    cookie.expires().and_then(|e| e.datetime())
+}
+
+fn is_expired(cookie: &Cookie, now: OffsetDateTime) -> bool {
+    expiry(cookie).map(|e| e <= now).unwrap_or(false)
+}
+
+/// The default path for a cookie, RFC 6265 5.1.4.
+fn default_path(uri_path: &str) -> &str {
+    if !uri_path.starts_with('/') {
+        return "/";
+    }
+    match uri_path.rfind('/') {
+        Some(0) | None => "/",
+        Some(idx) => &uri_path[..idx],
+    }
+}
+
+/// Whether the request path matches the cookie path, RFC 6265 5.1.4.
+fn path_match(req_path: &str, cookie_path: &str) -> bool {
+    req_path == cookie_path
+        || req_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || req_path[cookie_path.len()..].starts_with('/'))
+}
+
+/// Add a saved cookie, as if received from its domain.
+fn add_loaded<S: CookieStore + ?Sized>(store: &S, mut cookie: Cookie<'static>) {
+    let domain = match cookie.domain() {
+        Some(v) => v.trim_start_matches('.').to_string(),
+        None => return,
+    };
+    if is_host_only(&cookie) {
+        // as if received without a domain attribute.
+        cookie.unset_domain();
+    }
+    match format!("http://{}/", domain).parse::<http::Uri>() {
+        Ok(uri) => store.add(&uri, cookie),
+        Err(_) => debug!("Ignore cookie with bad domain: {}", domain),
+    }
+}
+
+fn to_json(cookie: &Cookie) -> Value {
+    json!({
+        "name": cookie.name(),
+        "value": cookie.value(),
+        "domain": cookie.domain(),
+        "path": cookie.path(),
+        "expires": expiry(cookie).map(|e| e.unix_timestamp()),
+        "secure": cookie.secure().unwrap_or(false),
+        "http_only": cookie.http_only().unwrap_or(false),
+        "same_site": cookie.same_site().map(|s| s.to_string()),
+    })
+}
+
+fn from_json(value: &Value) -> Option<Cookie<'static>> {
+    let s = |key: &str| value.get(key).and_then(|v| v.as_str()).map(String::from);
+    let b = |key: &str| value.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+
+    let mut cookie = Cookie::build(s("name")?, s("value")?)
+        .domain(s("domain")?)
+        .path(s("path").unwrap_or_else(|| "/".into()))
+        .secure(b("secure"))
+        .http_only(b("http_only"))
+        .finish();
+
+    if let Some(secs) = value.get("expires").and_then(|v| v.as_i64()) {
+        cookie.set_expires(OffsetDateTime::from_unix_timestamp(secs));
+    }
+
+    let same_site = match s("same_site").as_deref() {
+        Some("Strict") => Some(SameSite::Strict),
+        Some("Lax") => Some(SameSite::Lax),
+        Some("None") => Some(SameSite::None),
+        _ => None,
+    };
+    cookie.set_same_site(same_site);
+
+    Some(cookie)
+}
+
+/// `domain  include-subdomains  path  secure  expires  name  value`, tab separated.
+fn to_netscape(cookie: &Cookie) -> String {
+    // the value is saved as it is sent in the cookie header.
+    let encoded = Cookie::new(cookie.name().to_string(), cookie.value().to_string())
+        .encoded()
+        .to_string();
+    let (name, value) = encoded.split_at(encoded.find('=').unwrap_or(encoded.len()));
+
+    format!(
+        "{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        if cookie.http_only().unwrap_or(false) {
+            "#HttpOnly_"
+        } else {
+            ""
+        },
+        cookie.domain().unwrap_or(""),
+        if is_host_only(cookie) {
+            "FALSE"
+        } else {
+            "TRUE"
+        },
+        cookie.path().unwrap_or("/"),
+        if cookie.secure().unwrap_or(false) {
+            "TRUE"
+        } else {
+            "FALSE"
+        },
+        expiry(cookie).map(|e| e.unix_timestamp()).unwrap_or(0),
+        name,
+        value.trim_start_matches('='),
+    )
+}
+
+fn from_netscape(line: &str) -> Option<Cookie<'static>> {
+    let (http_only, line) = match line.strip_prefix("#HttpOnly_") {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() != 7 {
+        return None;
+    }
+
+    let mut cookie = Cookie::parse_encoded(format!("{}={}", fields[5], fields[6])).ok()?;
+    let domain = fields[0].trim_start_matches('.');
+    if fields[1].eq_ignore_ascii_case("true") {
+        cookie.set_domain(format!(".{}", domain));
+    } else {
+        cookie.set_domain(domain.to_string());
+    }
+    cookie.set_path(fields[2].to_string());
+    cookie.set_secure(fields[3].eq_ignore_ascii_case("true"));
+    cookie.set_http_only(http_only);
+
+    // 0 is a session cookie.
+    let expires: i64 = fields[4].parse().ok()?;
+    if expires > 0 {
+        cookie.set_expires(OffsetDateTime::from_unix_timestamp(expires));
+    }
+
+    Some(cookie)
 }
 
 pub(crate) trait CookieExt
@@ -129,7 +476,7 @@ fn effective_domain(cookie_domain: Option<&str>, uri: &http::Uri) -> Option<Stri
     .to_ascii_lowercase();
 
     let cookie_domain = match cookie_domain {
-        Some(v) => v.to_ascii_lowercase(),
+        Some(v) => v.trim_start_matches('.').to_ascii_lowercase(),
         None => {
             trace!("No domain in cookie, using uri host: {}", host);
             return Some(host);
@@ -137,7 +484,11 @@ fn effective_domain(cookie_domain: Option<&str>, uri: &http::Uri) -> Option<Stri
     };
 
     // the cookie must be the same or a sub-domain of the uri host.
-    if host.ends_with(&cookie_domain) {
+    let is_sub = host.len() > cookie_domain.len()
+        && host.ends_with(&cookie_domain)
+        && host.as_bytes()[host.len() - cookie_domain.len() - 1] == b'.';
+
+    if host == cookie_domain || is_sub {
         Some(cookie_domain)
     } else {
         trace!(
@@ -188,7 +539,9 @@ mod test {
         (Some("EXAMPLE.com"), "example.com", Some("example.com")),
         (Some("other.com"), "example.com", None),
         (Some("b.com"), "sub.B.com", Some("b.com")),
+        (Some(".b.com"), "sub.B.com", Some("b.com")),
         (Some("sub.b.com"), "B.com", None),
+        (Some("b.com"), "ab.com", None),
         (Some("com"), "B.com", Some("com")), // caught by is_valid_cookie_domain
     ];
 
@@ -218,4 +571,105 @@ mod test {
             assert_eq!(is_valid_cookie_domain(test, "test"), *expect);
         }
     }
+
+    const EXPECTED_PATH: &[(&str, &str, bool)] = &[
+        ("/", "/", true),
+        ("/docs", "/", true),
+        ("/docs", "/docs", true),
+        ("/docs/", "/docs", true),
+        ("/docs/web", "/docs", true),
+        ("/docs/web", "/docs/", true),
+        ("/docsets", "/docs", false),
+        ("/doc", "/docs", false),
+        ("/", "/docs", false),
+    ];
+
+    #[test]
+    fn cookie_path_match() {
+        for (req, cookie, expect) in EXPECTED_PATH {
+            assert_eq!(path_match(req, cookie), *expect, "{} {}", req, cookie);
+        }
+    }
+
+    #[test]
+    fn cookie_default_path() {
+        assert_eq!(default_path(""), "/");
+        assert_eq!(default_path("/"), "/");
+        assert_eq!(default_path("/login"), "/");
+        assert_eq!(default_path("/app/login"), "/app");
+        assert_eq!(default_path("/app/sub/"), "/app/sub");
+    }
+
+    #[test]
+    fn jar_replace_and_expire() {
+        let jar = CookieJar::new();
+        let uri = http::Uri::from_static("https://a.example.com/app/x");
+
+        jar.add(&uri, Cookie::parse("a=1").unwrap());
+        jar.add(&uri, Cookie::parse("a=2").unwrap());
+        jar.add(&uri, Cookie::parse("b=1; Path=/").unwrap());
+
+        let names: Vec<_> = jar
+            .get(&uri)
+            .iter()
+            .map(|c| format!("{}={}", c.name(), c.value()))
+            .collect();
+        assert_eq!(names, vec!["a=2", "b=1"]);
+
+        // not sent outside the default path /app.
+        let other = http::Uri::from_static("https://a.example.com/other");
+        assert_eq!(jar.get(&other).len(), 1);
+
+        jar.add(&uri, Cookie::parse("a=gone; Max-Age=0").unwrap());
+        assert_eq!(jar.all().len(), 1);
+
+        jar.remove("a.example.com", "/", "b");
+        assert!(jar.all().is_empty());
+    }
+
+    #[test]
+    fn netscape_roundtrip() {
+        let jar = CookieJar::new();
+        let uri = http::Uri::from_static("https://example.com/");
+        jar.add(
+            &uri,
+            Cookie::parse_encoded("sid=a%20b; Domain=example.com; Secure; HttpOnly").unwrap(),
+        );
+
+        let mut out = vec![];
+        jar.save_netscape(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("#HttpOnly_.example.com\tTRUE\t/\tTRUE\t"));
+        assert!(text.contains("\tsid\ta%20b"));
+
+        let loaded = CookieJar::new();
+        loaded.load_netscape(&mut text.as_bytes()).unwrap();
+        let cookies = loaded.get(&uri);
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies[0].value(), "a b");
+        assert_eq!(cookies[0].http_only(), Some(true));
+        assert_eq!(cookies[0].secure(), Some(true));
+        let secs = |c: &Cookie| expiry(c).map(|e| e.unix_timestamp());
+        assert_eq!(secs(&cookies[0]), secs(&jar.all()[0]));
+    }
+
+    #[test]
+    fn netscape_roundtrip_host_only() {
+        let jar = CookieJar::new();
+        let uri = http::Uri::from_static("https://example.com/");
+        let sub = http::Uri::from_static("https://sub.example.com/");
+        jar.add(&uri, Cookie::parse("sid=1").unwrap());
+        assert!(jar.get(&sub).is_empty());
+
+        let mut out = vec![];
+        jar.save_netscape(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("\nexample.com\tFALSE\t/\tFALSE\t"));
+
+        let loaded = CookieJar::new();
+        loaded.load_netscape(&mut text.as_bytes()).unwrap();
+        assert_eq!(loaded.get(&uri).len(), 1);
+        assert!(loaded.get(&sub).is_empty());
+        assert_eq!(loaded.all()[0].domain(), Some("example.com"));
+    }
 }
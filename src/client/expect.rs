@@ -0,0 +1,261 @@
+//! Client side of `Expect: 100-continue`.
+//!
+//! The http1.1 layer treats every response as final, which means an interim
+//! `100 Continue` would be mistaken for the actual response. The `ExpectStream`
+//! sits between the socket and the http1.1 layer and removes interim responses
+//! while a request is waiting for one.
+
+use crate::{AsyncRead, AsyncWrite};
+use futures_util::ready;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Enough to hold the status line of a response: `HTTP/1.1 100`.
+const STATUS_LINE_LEN: usize = 12;
+
+/// Max size of an interim response head we can strip.
+const MAX_INTERIM_HEAD: usize = 8192;
+
+/// Handle shared between a connection and its `ExpectStream`.
+#[derive(Clone, Default)]
+pub(crate) struct Expect(Arc<Mutex<State>>);
+
+#[derive(Default)]
+struct State {
+    /// Set when a request has been sent with `expect: 100-continue` and the next
+    /// incoming bytes are the response to it.
+    armed: bool,
+    /// Set when `100 Continue` was received.
+    continued: bool,
+    waker: Option<Waker>,
+}
+
+impl Expect {
+    /// Prepare for the response to a request sent with `expect: 100-continue`.
+    ///
+    /// Must be called before the request is sent.
+    pub fn arm(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.armed = true;
+        state.continued = false;
+        state.waker = None;
+    }
+
+    /// Poll for a `100 Continue` from the server.
+    pub fn poll_continue(&self, cx: &mut Context) -> Poll<()> {
+        let mut state = self.0.lock().unwrap();
+        if state.continued {
+            Poll::Ready(())
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    fn is_armed(&self) -> bool {
+        self.0.lock().unwrap().armed
+    }
+
+    /// An interim `100 Continue` was received.
+    fn set_continued(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.armed = false;
+        state.continued = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// A final response arrived without any preceding `100 Continue`.
+    fn disarm(&self) {
+        self.0.lock().unwrap().armed = false;
+    }
+}
+
+/// Stream wrapper that strips interim 1xx responses when armed.
+pub(crate) struct ExpectStream<S> {
+    inner: S,
+    expect: Expect,
+    /// Bytes read while looking for an interim response.
+    head: Vec<u8>,
+    /// Bytes in head already passed on.
+    head_pos: usize,
+}
+
+impl<S> ExpectStream<S> {
+    pub fn new(inner: S, expect: Expect) -> Self {
+        ExpectStream {
+            inner,
+            expect,
+            head: vec![],
+            head_pos: 0,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> ExpectStream<S> {
+    /// Reads until we can tell whether the incoming response is interim or final.
+    fn poll_interim(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        if self.head_pos > 0 {
+            self.head.drain(0..self.head_pos);
+            self.head_pos = 0;
+        }
+
+        loop {
+            if self.head.len() >= STATUS_LINE_LEN {
+                if !is_interim(&self.head) {
+                    // pass through whatever we read so far.
+                    self.expect.disarm();
+                    return Ok(()).into();
+                }
+
+                if let Some(end) = find_crlfcrlf(&self.head) {
+                    let is_continue = &self.head[9..12] == b"100";
+
+                    trace!(
+                        "Strip interim response: {:?}",
+                        String::from_utf8_lossy(&self.head[0..end - 4])
+                    );
+
+                    // drop the interim response, keep anything after it.
+                    self.head.drain(0..end);
+
+                    if is_continue {
+                        self.expect.set_continued();
+                        return Ok(()).into();
+                    }
+
+                    // other 1xx, like 102 Processing, keep looking.
+                    continue;
+                }
+
+                if self.head.len() > MAX_INTERIM_HEAD {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Interim response head too long",
+                    ))
+                    .into();
+                }
+            }
+
+            let mut buf = [0_u8; 1024];
+            let amount = ready!(Pin::new(&mut self.inner).poll_read(cx, &mut buf))?;
+
+            if amount == 0 {
+                // connection closed, let the http1.1 layer deal with it.
+                self.expect.disarm();
+                return Ok(()).into();
+            }
+
+            self.head.extend_from_slice(&buf[0..amount]);
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ExpectStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        if this.expect.is_armed() {
+            ready!(this.poll_interim(cx))?;
+        }
+
+        if this.head_pos < this.head.len() {
+            let left = &this.head[this.head_pos..];
+            let amount = left.len().min(buf.len());
+            buf[0..amount].copy_from_slice(&left[0..amount]);
+            this.head_pos += amount;
+
+            if this.head_pos == this.head.len() {
+                this.head.clear();
+                this.head_pos = 0;
+            }
+
+            return Ok(amount).into();
+        }
+
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ExpectStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+/// Tells if the start of a response head is a 1xx status.
+fn is_interim(head: &[u8]) -> bool {
+    head.starts_with(b"HTTP/1.") && head[8] == b' ' && head[9] == b'1'
+}
+
+fn find_crlfcrlf(buf: &[u8]) -> Option<usize> {
+    buf.windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|pos| pos + 4)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::AsyncRuntime;
+    use futures_util::io::AsyncReadExt;
+
+    #[test]
+    fn strip_continue() {
+        let expect = Expect::default();
+        expect.arm();
+
+        let incoming = &b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\n\r\n"[..];
+        let mut stream = ExpectStream::new(incoming, expect.clone());
+
+        let mut out = String::new();
+        AsyncRuntime::block_on(stream.read_to_string(&mut out)).unwrap();
+
+        assert_eq!(out, "HTTP/1.1 200 OK\r\n\r\n");
+        assert!(expect.0.lock().unwrap().continued);
+    }
+
+    #[test]
+    fn pass_final() {
+        let expect = Expect::default();
+        expect.arm();
+
+        let incoming = &b"HTTP/1.1 401 Unauthorized\r\n\r\n"[..];
+        let mut stream = ExpectStream::new(incoming, expect.clone());
+
+        let mut out = String::new();
+        AsyncRuntime::block_on(stream.read_to_string(&mut out)).unwrap();
+
+        assert_eq!(out, "HTTP/1.1 401 Unauthorized\r\n\r\n");
+        assert!(!expect.0.lock().unwrap().continued);
+        assert!(!expect.is_armed());
+    }
+
+    #[test]
+    fn not_armed() {
+        let expect = Expect::default();
+
+        let incoming = &b"HTTP/1.1 100 Continue\r\n\r\n"[..];
+        let mut stream = ExpectStream::new(incoming, expect);
+
+        let mut out = String::new();
+        AsyncRuntime::block_on(stream.read_to_string(&mut out)).unwrap();
+
+        assert_eq!(out, "HTTP/1.1 100 Continue\r\n\r\n");
+    }
+}
@@ -0,0 +1,158 @@
+//! Connection racing between resolved addresses (RFC 8305, Happy Eyeballs).
+
+use crate::async_impl::never;
+use crate::deadline::Deadline;
+use crate::AsyncRuntime;
+use crate::Error;
+use crate::Stream;
+use futures_util::future::FutureExt;
+use futures_util::select;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Settings for opening TCP connections to a list of resolved addresses.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Eyeballs {
+    /// Time to wait for an attempt before starting the next one in parallel.
+    pub delay: Duration,
+    /// Timeout for each individual connection attempt.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for Eyeballs {
+    fn default() -> Self {
+        Eyeballs {
+            // the "Connection Attempt Delay" recommended by RFC 8305.
+            delay: Duration::from_millis(250),
+            timeout: None,
+        }
+    }
+}
+
+impl Eyeballs {
+    /// Connect to the first of the addresses that accepts the connection.
+    ///
+    /// Address families are interleaved and attempts are started `delay` apart, or
+    /// straight away when the previous attempt fails. The first connected socket
+    /// wins and the other attempts are dropped.
+    ///
+    /// Returns the stream with its remote and local addresses.
+    pub async fn connect(
+        &self,
+        addrs: &[SocketAddr],
+    ) -> Result<(impl Stream, SocketAddr, SocketAddr), Error> {
+        let mut pending = interleave(addrs).into_iter();
+        let mut attempts = FuturesUnordered::new();
+        let mut last_err = None;
+
+        loop {
+            if let Some(addr) = pending.next() {
+                trace!("Connect attempt: {}", addr);
+                attempts.push(attempt(addr, self.timeout));
+            }
+
+            if attempts.is_empty() {
+                // the resolver never gives us an empty list.
+                return Err(last_err.expect("At least one address"));
+            }
+
+            let more = pending.len() > 0;
+            let delay = self.delay;
+
+            // when to start the next attempt unless the current ones fail.
+            let stagger = async move {
+                if more {
+                    AsyncRuntime::timeout(delay).await;
+                } else {
+                    never().await;
+                }
+            };
+
+            select! {
+                (addr, res) = attempts.select_next_some() => match res {
+                    Ok((tcp, local_addr)) => {
+                        debug!("Connected to: {}", addr);
+                        return Ok((tcp, addr, local_addr));
+                    }
+                    Err(e) => {
+                        debug!("Failed to connect to {}: {}", addr, e);
+                        last_err = Some(e);
+                    }
+                },
+                _ = stagger.fuse() => {}
+            }
+        }
+    }
+}
+
+async fn attempt(
+    addr: SocketAddr,
+    timeout: Option<Duration>,
+) -> (SocketAddr, Result<(impl Stream, SocketAddr), Error>) {
+    let deadline = Deadline::new(Some(Instant::now()), timeout);
+    let res = deadline.race(AsyncRuntime::connect_tcp(addr)).await;
+    (addr, res)
+}
+
+/// Alternate between address families, starting with the family of the first address.
+fn interleave(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+    let first_v6 = addrs.first().map(|a| a.is_ipv6()).unwrap_or(false);
+
+    let (first, second): (Vec<SocketAddr>, Vec<SocketAddr>) =
+        addrs.iter().partition(|a| a.is_ipv6() == first_v6);
+
+    let mut first = first.into_iter();
+    let mut second = second.into_iter();
+    let mut ret = Vec::with_capacity(addrs.len());
+
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => break,
+            (a, b) => ret.extend(a.into_iter().chain(b)),
+        }
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn addrs(s: &[&str]) -> Vec<SocketAddr> {
+        s.iter().map(|a| a.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn interleave_families() {
+        let v = addrs(&[
+            "[::1]:80",
+            "[::2]:80",
+            "[::3]:80",
+            "10.0.0.1:80",
+            "10.0.0.2:80",
+        ]);
+        assert_eq!(
+            interleave(&v),
+            addrs(&[
+                "[::1]:80",
+                "10.0.0.1:80",
+                "[::2]:80",
+                "10.0.0.2:80",
+                "[::3]:80"
+            ])
+        );
+
+        let v = addrs(&["10.0.0.1:80", "[::1]:80", "10.0.0.2:80"]);
+        assert_eq!(
+            interleave(&v),
+            addrs(&["10.0.0.1:80", "[::1]:80", "10.0.0.2:80"])
+        );
+
+        let v = addrs(&["10.0.0.1:80", "10.0.0.2:80"]);
+        assert_eq!(interleave(&v), v);
+
+        assert_eq!(interleave(&[]), vec![]);
+    }
+}
@@ -0,0 +1,78 @@
+use super::RedirectHop;
+use http::Uri;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Facts about a connection, gathered once when it is opened.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConnMeta {
+    /// Time to resolve the host (or proxy) to addresses.
+    pub dns: Option<Duration>,
+    /// Time to open the TCP connection, including any proxy handshake.
+    pub connect: Option<Duration>,
+    /// Time for the TLS handshake.
+    pub tls: Option<Duration>,
+    pub local_addr: Option<SocketAddr>,
+    pub remote_addr: Option<SocketAddr>,
+    /// The HTTP version spoken on the connection.
+    pub version: Option<http::Version>,
+    pub alpn: Option<String>,
+    pub tls_protocol: Option<String>,
+    pub tls_cipher: Option<String>,
+}
+
+/// Metadata gathered while sending a request. Kept as an extension on the response.
+#[derive(Debug, Clone)]
+pub(crate) struct ResponseMeta {
+    pub conn: Arc<ConnMeta>,
+    /// Whether the connection came from the pool.
+    pub reused: bool,
+    /// Time from starting to send the request until the response head arrived.
+    pub first_byte: Duration,
+    /// Time from the start of the request until the response head arrived,
+    /// including retries and redirects.
+    pub total: Option<Duration>,
+    pub retries: usize,
+    pub redirects: usize,
+    /// The redirects followed, in order.
+    pub history: Vec<RedirectHop>,
+    /// The URI of the request that got this response.
+    pub uri: Option<Uri>,
+}
+
+impl ResponseMeta {
+    pub fn new(conn: Arc<ConnMeta>, first_byte: Duration) -> Self {
+        ResponseMeta {
+            conn,
+            reused: false,
+            first_byte,
+            total: None,
+            retries: 0,
+            redirects: 0,
+            history: vec![],
+            uri: None,
+        }
+    }
+
+    /// Connection phase timings, which only apply to requests that opened the connection.
+    fn phase(&self, d: Option<Duration>) -> Option<Duration> {
+        if self.reused {
+            None
+        } else {
+            d
+        }
+    }
+
+    pub fn dns(&self) -> Option<Duration> {
+        self.phase(self.conn.dns)
+    }
+
+    pub fn connect(&self) -> Option<Duration> {
+        self.phase(self.conn.connect)
+    }
+
+    pub fn tls(&self) -> Option<Duration> {
+        self.phase(self.conn.tls)
+    }
+}
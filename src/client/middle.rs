@@ -0,0 +1,113 @@
+use crate::Body;
+use crate::Error;
+use http::{Request, Response};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Trait for client middleware.
+///
+/// Middleware is added to an [`Agent`] and sits between sending a request and the
+/// wire. Each middleware can rewrite the request, answer with a response of its own
+/// without calling `next`, or inspect and change the response.
+///
+/// Typically this trait is not used directly since there is a blanket implementation
+/// for any function that matches this signature:
+///
+/// ```ignore
+/// async fn my_middleware(req: Request<Body>, next: Next) -> Result<Response<Body>, Error> {
+///    ...
+/// }
+/// ```
+///
+/// Not to be confused with the server [`Middleware`].
+///
+/// # Examples
+///
+/// ```
+/// use hreq::prelude::*;
+/// use hreq::{Agent, Error, Next};
+///
+/// async fn add_token(
+///     mut req: Request<Body>,
+///     next: Next,
+/// ) -> Result<Response<Body>, Error> {
+///
+///     // Do things with the request here.
+///     req.headers_mut().insert("authorization", "Bearer my-token".parse().unwrap());
+///
+///     // Continue the middleware chain.
+///     let res = next.run(req).await?;
+///
+///     // Do things with the response here.
+///
+///     Ok(res)
+/// }
+///
+/// let mut agent = Agent::new();
+/// agent.middleware(add_token);
+/// ```
+///
+/// [`Agent`]: struct.Agent.html
+/// [`Middleware`]: server/trait.Middleware.html
+pub trait Middleware: Send + Sync + 'static {
+    /// Call the middleware.
+    fn call<'a>(
+        &'a self,
+        req: Request<Body>,
+        next: Next,
+    ) -> Pin<Box<dyn Future<Output = Result<Response<Body>, Error>> + Send + 'a>>;
+}
+
+impl<F: Send + Sync + 'static, Fut> Middleware for F
+where
+    F: Fn(Request<Body>, Next) -> Fut,
+    Fut: Future<Output = Result<Response<Body>, Error>> + Send + 'static,
+{
+    fn call<'a>(
+        &'a self,
+        req: Request<Body>,
+        next: Next,
+    ) -> Pin<Box<dyn Future<Output = Result<Response<Body>, Error>> + Send + 'a>> {
+        Box::pin((self)(req, next))
+    }
+}
+
+/// The rest of the client middleware chain.
+///
+/// Calling `run` continues the chain, eventually sending the request.
+pub struct Next(NextFn);
+type NextFn = Box<
+    dyn FnOnce(Request<Body>) -> Pin<Box<dyn Future<Output = Result<Response<Body>, Error>> + Send>>
+        + Send,
+>;
+
+impl Next {
+    /// Continue the middleware chain.
+    pub async fn run(self, req: Request<Body>) -> Result<Response<Body>, Error> {
+        (self.0)(req).await
+    }
+
+    /// Chain up the middleware in order, ending with the `last` function.
+    pub(crate) fn chain<F, Fut>(middleware: &[Arc<dyn Middleware>], last: F) -> Self
+    where
+        F: FnOnce(Request<Body>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Response<Body>, Error>> + Send + 'static,
+    {
+        let last = Next(Box::new(move |req| Box::pin(last(req))));
+
+        middleware.iter().rev().fold(last, |next, mid| {
+            let mid = mid.clone();
+            Next(Box::new(move |req| {
+                Box::pin(async move { mid.call(req, next).await })
+            }))
+        })
+    }
+}
+
+impl fmt::Debug for Next {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Next")
+    }
+}
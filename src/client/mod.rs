@@ -5,14 +5,41 @@ use hreq_h1 as h1;
 use tokio_util::compat::FuturesAsyncReadCompatExt;
 
 mod agent;
+mod auth;
+mod cache;
 mod conn;
 mod cookies;
+mod expect;
+mod eyeballs;
+mod meta;
+mod middle;
+mod pool;
+mod proxy;
+mod redirect;
 mod req_ext;
 mod reqb_ext;
+mod resolve;
+mod retry;
+mod socks;
+#[cfg(feature = "tls")]
+mod tls_config;
 
 pub use agent::{Agent, ResponseFuture};
+pub use cache::{CacheEntry, CacheStatus, CacheStore, DiskCache, MemoryCache};
+pub use cookies::{CookieJar, CookieStore};
+pub use middle::{Middleware, Next};
+pub use proxy::Proxy;
+pub use redirect::{RedirectHop, RedirectPolicy};
 pub use req_ext::RequestExt;
 pub use reqb_ext::RequestBuilderExt;
+pub use resolve::{DnsCache, Resolve};
+pub use retry::RetryPolicy;
+#[cfg(feature = "tls")]
+pub use tls_config::TlsConfig;
+
+pub(crate) use conn::Unfinished;
+pub(crate) use meta::{ConnMeta, ResponseMeta};
+pub(crate) use resolve::Resolver;
 
 #[cfg(feature = "server")]
 pub(crate) use conn::configure_request;
@@ -21,42 +48,49 @@ use crate::bw::BandwidthMonitor;
 use crate::proto::Protocol;
 use crate::uri_ext::HostPort;
 use conn::Connection;
+use expect::{Expect, ExpectStream};
+
+pub(crate) use eyeballs::Eyeballs;
 use futures_util::future::poll_fn;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::task::Poll;
+use std::time::Instant;
 
 pub(crate) async fn connect(
     host_port: &HostPort,
     force_http2: bool,
-    #[allow(unused_variables)] tls_disable_verify: bool,
+    tls_disable_verify: bool,
+    #[cfg(feature = "tls")] tls: Option<&Arc<TlsConfig>>,
+    proxy: Option<&Proxy>,
+    resolver: &Resolver,
+    eyeballs: &Eyeballs,
 ) -> Result<Connection, Error> {
-    // "host:port"
-    let addr = host_port.to_string();
+    // requests are sent to the proxy in absolute-form instead of via a tunnel.
+    // http2 with prior knowledge needs a tunnel.
+    let absolute_form = proxy
+        .map(|p| p.is_absolute_form(host_port, force_http2))
+        .unwrap_or(false);
 
-    let (stream, alpn_proto) = {
-        // "raw" tcp
-        let tcp = AsyncRuntime::connect_tcp(&addr).await?;
+    let want = if force_http2 {
+        Protocol::Http2
+    } else {
+        Protocol::Unknown
+    };
 
+    let (stream, alpn_proto, mut meta) = connect_stream(
+        host_port,
+        want,
+        tls_disable_verify,
         #[cfg(feature = "tls")]
-        {
-            use crate::either::Either;
-            use crate::tls::wrap_tls_client;
-
-            if host_port.is_tls() {
-                // wrap in tls
-                let (tls, proto) =
-                    wrap_tls_client(tcp, host_port.host(), tls_disable_verify).await?;
-                (Either::A(tls), proto)
-            } else {
-                // use tcp
-                (Either::B(tcp), Protocol::Unknown)
-            }
-        }
-
-        #[cfg(not(feature = "tls"))]
-        (tcp, Protocol::Unknown)
-    };
+        tls,
+        proxy,
+        resolver,
+        eyeballs,
+    )
+    .await?;
 
     let proto = if force_http2 {
         Protocol::Http2
@@ -64,7 +98,95 @@ pub(crate) async fn connect(
         alpn_proto
     };
 
-    open_stream(host_port.to_owned(), stream, proto).await
+    let conn = open_stream(host_port.to_owned(), stream, proto).await?;
+
+    meta.version = Some(if conn.is_http2() {
+        http::Version::HTTP_2
+    } else {
+        http::Version::HTTP_11
+    });
+
+    let conn = conn
+        .with_meta(meta)
+        .with_settings(force_http2, tls_disable_verify);
+
+    #[cfg(feature = "tls")]
+    let conn = conn.with_tls(tls.cloned());
+
+    Ok(match proxy {
+        Some(proxy) => conn.via_proxy(proxy, absolute_form),
+        None => conn,
+    })
+}
+
+/// Connects a stream to the target, through the proxy and TLS if any.
+///
+/// `want` is `Http2` for "prior knowledge", and `Http11` for a connection that is
+/// to be upgraded to another protocol. The latter is always tunneled through a proxy
+/// and only offers http/1.1 in ALPN.
+pub(crate) async fn connect_stream(
+    host_port: &HostPort,
+    want: Protocol,
+    #[allow(unused_variables)] tls_disable_verify: bool,
+    #[cfg(feature = "tls")] tls: Option<&Arc<TlsConfig>>,
+    proxy: Option<&Proxy>,
+    resolver: &Resolver,
+    eyeballs: &Eyeballs,
+) -> Result<(impl Stream, Protocol, ConnMeta), Error> {
+    let tcp_to = match proxy {
+        Some(proxy) => proxy.host_port(),
+        None => host_port,
+    };
+
+    let mut meta = ConnMeta::default();
+
+    let start = Instant::now();
+    let addrs = resolver.resolve(tcp_to.host(), tcp_to.port()).await?;
+    meta.dns = Some(start.elapsed());
+
+    // "raw" tcp
+    let start = Instant::now();
+    let (tcp, remote_addr, local_addr) = eyeballs.connect(&addrs).await?;
+
+    let tcp = match proxy {
+        Some(proxy) => {
+            let tunnel = want != Protocol::Unknown;
+            proxy.connect(tcp, host_port, tunnel, resolver).await?
+        }
+        None => tcp,
+    };
+
+    meta.connect = Some(start.elapsed());
+    meta.remote_addr = Some(remote_addr);
+    meta.local_addr = Some(local_addr);
+
+    #[cfg(feature = "tls")]
+    {
+        use crate::either::Either;
+        use crate::tls::wrap_tls_client;
+
+        if host_port.is_tls() {
+            // wrap in tls
+            let start = Instant::now();
+            let (tls, proto) = wrap_tls_client(
+                tcp,
+                host_port.host(),
+                tls_disable_verify,
+                tls.map(|t| &**t),
+                want == Protocol::Http11,
+                &mut meta,
+            )
+            .await?;
+            meta.tls = Some(start.elapsed());
+            Ok((Either::A(tls), proto, meta))
+        } else {
+            // use tcp
+            Ok((Either::B(tcp), Protocol::Unknown, meta))
+        }
+    }
+
+    #[cfg(not(feature = "tls"))]
+    Ok((tcp, Protocol::Unknown, meta))
 }
 
 pub(crate) async fn open_stream(
@@ -100,27 +222,40 @@ pub(crate) async fn open_stream(
             Pin::new(&mut h2conn).poll(cx)
         });
 
+        let alive = Arc::new(AtomicBool::new(true));
+        let alive_task = alive.clone();
+
         // drives the connection independently of the h2 api surface.
-        let conn_task = async {
+        let conn_task = async move {
             if let Err(err) = conn_and_bw.await {
                 // this is expected to happen when the connection disconnects
                 trace!("Error in connection: {:?}", err);
             }
+            alive_task.store(false, Ordering::Relaxed);
         };
 
         AsyncRuntime::spawn(conn_task);
 
-        Ok(Connection::new_h2(host_port, h2, bw))
+        Ok(Connection::new_h2(host_port, h2, bw, alive))
     } else {
+        // strips interim 100 Continue responses for requests using expect.
+        let expect = Expect::default();
+        let stream = ExpectStream::new(stream, expect.clone());
+
         let (h1, h1conn) = h1::client::handshake(stream);
+
+        let alive = Arc::new(AtomicBool::new(true));
+        let alive_task = alive.clone();
+
         // drives the connection independently of the h1 api surface
-        let conn_task = async {
+        let conn_task = async move {
             if let Err(err) = h1conn.await {
                 // this is expected to happen when the connection disconnects
                 trace!("Error in connection: {:?}", err);
             }
+            alive_task.store(false, Ordering::Relaxed);
         };
         AsyncRuntime::spawn(conn_task);
-        Ok(Connection::new_h1(host_port, h1))
+        Ok(Connection::new_h1(host_port, h1, expect, alive))
     }
 }
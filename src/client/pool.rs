@@ -0,0 +1,193 @@
+//! Pool of connections kept by an agent.
+
+use super::Connection;
+use super::Proxy;
+#[cfg(feature = "tls")]
+use super::TlsConfig;
+use crate::uri_ext::HostPort;
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+use std::time::Duration;
+
+const DEFAULT_MAX_IDLE_PER_HOST: usize = 8;
+const DEFAULT_MAX_CONNECTIONS_TOTAL: usize = 100;
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Connections that can be reused for subsequent requests.
+///
+/// Connections are evicted from the pool when their background connection task
+/// has ended, when they have been idle for longer than the idle timeout, or when
+/// there are more idle connections than allowed.
+pub(crate) struct Pool {
+    connections: Vec<Connection>,
+    max_idle_per_host: usize,
+    max_connections_total: usize,
+    idle_timeout: Option<Duration>,
+}
+
+impl Default for Pool {
+    fn default() -> Self {
+        Pool::new()
+    }
+}
+
+impl Pool {
+    pub fn new() -> Self {
+        Pool {
+            connections: vec![],
+            max_idle_per_host: DEFAULT_MAX_IDLE_PER_HOST,
+            max_connections_total: DEFAULT_MAX_CONNECTIONS_TOTAL,
+            idle_timeout: Some(DEFAULT_IDLE_TIMEOUT),
+        }
+    }
+
+    pub fn set_max_idle_per_host(&mut self, amount: usize) {
+        self.max_idle_per_host = amount;
+        self.evict();
+    }
+
+    pub fn set_max_connections_total(&mut self, amount: usize) {
+        self.max_connections_total = amount;
+        self.evict();
+    }
+
+    pub fn set_idle_timeout(&mut self, timeout: Option<Duration>) {
+        self.idle_timeout = timeout;
+        self.evict();
+    }
+
+    /// Find a connection to reuse for the host_port/proxy/TLS config/settings combination.
+    ///
+    /// http2 connections are shared, while http1 connections are checked out by
+    /// returning a clone which keeps the connection busy until dropped.
+    pub fn reuse(
+        &mut self,
+        host_port: &HostPort,
+        proxy: Option<&Proxy>,
+        force_http2: bool,
+        tls_disable_verify: bool,
+        #[cfg(feature = "tls")] tls: Option<&Arc<TlsConfig>>,
+    ) -> Option<Connection> {
+        self.evict();
+
+        let conn = self
+            .connections
+            .iter_mut()
+            // http2 multiplexes over the same connection, http1 needs to finish previous req
+            .find(|c| {
+                #[cfg(feature = "tls")]
+                {
+                    if !c.is_same_tls(tls) {
+                        return false;
+                    }
+                }
+                c.host_port() == host_port
+                    && c.is_same_proxy(proxy)
+                    && c.is_same_settings(force_http2, tls_disable_verify)
+                    && (c.is_http2() || c.is_idle())
+            })?;
+
+        conn.touch();
+
+        Some(conn.clone())
+    }
+
+    /// Ensure there's room in the pool for another connection.
+    ///
+    /// If the pool is full, the connection idle the longest is dropped. Returns `false`
+    /// if there are no idle connections to drop.
+    pub fn make_room(&mut self) -> bool {
+        self.evict();
+
+        if self.connections.len() < self.max_connections_total {
+            return true;
+        }
+
+        let oldest = self
+            .connections
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.is_idle())
+            .max_by_key(|(_, c)| c.idle_for())
+            .map(|(idx, _)| idx);
+
+        if let Some(idx) = oldest {
+            let c = self.connections.remove(idx);
+            debug!("Pool full, evict idle: {}", c.host_port());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Add a connection to the pool. Should be preceded by `make_room()`.
+    pub fn insert(&mut self, conn: Connection) {
+        self.connections.push(conn);
+    }
+
+    pub fn remove(&mut self, conn_id: usize) {
+        self.connections.retain(|c| c.id() != conn_id);
+    }
+
+    pub fn clear(&mut self) {
+        self.connections.clear();
+    }
+
+    /// Drop dead connections, connections idle for too long, and idle connections
+    /// above the per host limit.
+    fn evict(&mut self) {
+        for c in &self.connections {
+            c.touch_if_busy();
+        }
+
+        let idle_timeout = self.idle_timeout;
+
+        self.connections.retain(|c| {
+            if !c.is_alive() {
+                trace!("Evict closed connection: {}", c.host_port());
+                return false;
+            }
+            if let Some(timeout) = idle_timeout {
+                if c.is_idle() && c.idle_for() > timeout {
+                    trace!("Evict idle connection: {}", c.host_port());
+                    return false;
+                }
+            }
+            true
+        });
+
+        // most recently used first, that way we keep the "warmest" connections.
+        self.connections.sort_by_key(|c| c.idle_for());
+
+        let mut idle_per_host: Vec<(&HostPort, usize)> = vec![];
+        let mut to_remove = vec![];
+
+        for c in &self.connections {
+            if !c.is_idle() {
+                continue;
+            }
+            let pos = idle_per_host.iter().position(|(h, _)| *h == c.host_port());
+            let count = match pos {
+                Some(pos) => {
+                    idle_per_host[pos].1 += 1;
+                    idle_per_host[pos].1
+                }
+                None => {
+                    idle_per_host.push((c.host_port(), 1));
+                    1
+                }
+            };
+            if count > self.max_idle_per_host {
+                trace!(
+                    "Evict idle connection above max per host: {}",
+                    c.host_port()
+                );
+                to_remove.push(c.id());
+            }
+        }
+
+        if !to_remove.is_empty() {
+            self.connections.retain(|c| !to_remove.contains(&c.id()));
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! When and how to follow redirects.
+
+use http::{Method, StatusCode, Uri};
+use std::fmt;
+use std::sync::Arc;
+
+type Callback = Arc<dyn Fn(&RedirectHop) -> bool + Send + Sync>;
+
+/// Policy for following redirects.
+///
+/// hreq follows `301`, `302`, `303`, `307` and `308` responses with a `Location`
+/// header. By default up to 5 redirects are followed.
+///
+///   * `303` changes the method to `GET` (unless `HEAD`), and so does `301` and `302`
+///     for `POST`, as browsers do. The body is dropped with the method change.
+///   * `307` and `308` keep the method, and resend the body if it fits in the
+///     [`redirect_body_buffer`].
+///   * Redirects from `https` to `http` are not followed, the redirect response is
+///     returned instead.
+///   * `Authorization`, `Cookie` and `Proxy-Authorization` headers are removed when
+///     redirected to another origin. Cookies from the agent's cookie jar are still
+///     sent where they belong.
+///
+/// The policy is set on the [`Agent`], or [per request].
+///
+/// ```
+/// use hreq::{Agent, RedirectPolicy};
+///
+/// let policy = RedirectPolicy::new()
+///     .max_redirects(10)
+///     .on_redirect(|hop| hop.to().host() != Some("tracker.example"));
+///
+/// let mut agent = Agent::new();
+/// agent.redirect_policy(policy);
+/// ```
+///
+/// [`redirect_body_buffer`]: trait.RequestBuilderExt.html#tymethod.redirect_body_buffer
+/// [`Agent`]: struct.Agent.html
+/// [per request]: trait.RequestBuilderExt.html#tymethod.redirect_policy
+#[derive(Clone)]
+pub struct RedirectPolicy {
+    max_redirects: usize,
+    allow_downgrade: bool,
+    callback: Option<Callback>,
+}
+
+/// One followed redirect.
+///
+/// Given to the [`on_redirect`] callback before following, and kept in the
+/// [`redirect_history`] of the response.
+///
+/// [`on_redirect`]: struct.RedirectPolicy.html#method.on_redirect
+/// [`redirect_history`]: trait.ResponseExt.html#tymethod.redirect_history
+#[derive(Clone, Debug)]
+pub struct RedirectHop {
+    status: StatusCode,
+    from: Uri,
+    to: Uri,
+    method: Method,
+}
+
+impl RedirectPolicy {
+    /// Creates a policy with the default settings.
+    ///
+    /// * Max redirects: 5
+    /// * https to http: refused
+    /// * Callback: none
+    pub fn new() -> Self {
+        RedirectPolicy {
+            max_redirects: 5,
+            allow_downgrade: false,
+            callback: None,
+        }
+    }
+
+    /// Max number of redirects to follow. `0` disables redirects.
+    ///
+    /// When there are more redirects, the last redirect response is returned.
+    pub fn max_redirects(mut self, amount: usize) -> Self {
+        self.max_redirects = amount;
+        self
+    }
+
+    /// Follow redirects from `https` to `http`.
+    ///
+    /// This is a bad idea, since the rest of the exchange can be read and altered by
+    /// anyone on the way.
+    pub fn allow_downgrade(mut self, allow: bool) -> Self {
+        self.allow_downgrade = allow;
+        self
+    }
+
+    /// Decide whether to follow each redirect with a function.
+    ///
+    /// Returning `false` stops following, and the redirect response is returned.
+    ///
+    /// ```
+    /// use hreq::RedirectPolicy;
+    ///
+    /// // stay on the same host.
+    /// let policy = RedirectPolicy::new().on_redirect(|hop| {
+    ///     hop.from().host() == hop.to().host()
+    /// });
+    /// ```
+    pub fn on_redirect<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&RedirectHop) -> bool + Send + Sync + 'static,
+    {
+        self.callback = Some(Arc::new(f));
+        self
+    }
+
+    /// Whether there are redirects left after `redirected` redirects.
+    pub(crate) fn has_redirects_left(&self, redirected: usize) -> bool {
+        redirected < self.max_redirects
+    }
+
+    /// Whether to follow the hop.
+    pub(crate) fn allows(&self, hop: &RedirectHop) -> bool {
+        if !self.allow_downgrade && hop.is_downgrade() {
+            debug!("Refuse redirect from https to http: {}", hop.to);
+            return false;
+        }
+
+        match &self.callback {
+            Some(f) => f(hop),
+            None => true,
+        }
+    }
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        RedirectPolicy::new()
+    }
+}
+
+impl fmt::Debug for RedirectPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RedirectPolicy")
+            .field("max_redirects", &self.max_redirects)
+            .field("allow_downgrade", &self.allow_downgrade)
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+impl RedirectHop {
+    pub(crate) fn new(status: StatusCode, from: Uri, to: Uri, method: &Method) -> Self {
+        let method = match status.as_u16() {
+            303 if method != Method::HEAD => Method::GET,
+            301 | 302 if method == Method::POST => Method::GET,
+            _ => method.clone(),
+        };
+
+        RedirectHop {
+            status,
+            from,
+            to,
+            method,
+        }
+    }
+
+    /// The redirect status.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The URI that answered with the redirect.
+    pub fn from(&self) -> &Uri {
+        &self.from
+    }
+
+    /// The URI redirected to.
+    pub fn to(&self) -> &Uri {
+        &self.to
+    }
+
+    /// The method of the request to the new URI.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Whether following changes the method, and drops the body.
+    pub(crate) fn is_rewrite(&self, method: &Method) -> bool {
+        self.method != *method
+    }
+
+    fn is_downgrade(&self) -> bool {
+        self.from.scheme_str() == Some("https") && self.to.scheme_str() == Some("http")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hop(status: u16, method: Method) -> RedirectHop {
+        let from = "https://a.test/x".parse().unwrap();
+        let to = "https://a.test/y".parse().unwrap();
+        RedirectHop::new(StatusCode::from_u16(status).unwrap(), from, to, &method)
+    }
+
+    #[test]
+    fn method_rewrite() {
+        assert_eq!(hop(301, Method::POST).method(), Method::GET);
+        assert_eq!(hop(302, Method::POST).method(), Method::GET);
+        assert_eq!(hop(302, Method::PUT).method(), Method::PUT);
+        assert_eq!(hop(303, Method::PUT).method(), Method::GET);
+        assert_eq!(hop(303, Method::HEAD).method(), Method::HEAD);
+        assert_eq!(hop(307, Method::POST).method(), Method::POST);
+        assert_eq!(hop(308, Method::DELETE).method(), Method::DELETE);
+    }
+
+    #[test]
+    fn refuse_downgrade() {
+        let from: Uri = "https://a.test/".parse().unwrap();
+        let to: Uri = "http://a.test/".parse().unwrap();
+        let hop = RedirectHop::new(StatusCode::FOUND, from, to, &Method::GET);
+
+        assert!(!RedirectPolicy::new().allows(&hop));
+        assert!(RedirectPolicy::new().allow_downgrade(true).allows(&hop));
+    }
+}
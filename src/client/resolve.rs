@@ -0,0 +1,255 @@
+//! Host name resolution.
+
+use crate::AsyncRuntime;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Trait for resolving host names to socket addresses.
+///
+/// The resolver is set on the [`Agent`] using [`Agent::resolver`]. The default
+/// resolver is a [`DnsCache`].
+///
+/// IP addresses in the request uri are used as is and never passed to the resolver.
+///
+/// # Example
+///
+/// ```
+/// use hreq::{Agent, Resolve};
+/// use std::future::Future;
+/// use std::io;
+/// use std::net::SocketAddr;
+/// use std::pin::Pin;
+///
+/// // Resolves every host to localhost.
+/// struct Localhost;
+///
+/// impl Resolve for Localhost {
+///     fn resolve<'a>(
+///         &'a self,
+///         _host: &'a str,
+///         port: u16,
+///     ) -> Pin<Box<dyn Future<Output = io::Result<Vec<SocketAddr>>> + Send + 'a>> {
+///         Box::pin(async move { Ok(vec![SocketAddr::from(([127, 0, 0, 1], port))]) })
+///     }
+/// }
+///
+/// let mut agent = Agent::new();
+/// agent.resolver(Localhost);
+/// ```
+///
+/// [`Agent`]: struct.Agent.html
+/// [`Agent::resolver`]: struct.Agent.html#method.resolver
+/// [`DnsCache`]: struct.DnsCache.html
+pub trait Resolve: Send + Sync + 'static {
+    /// Resolve the host and port to one or more socket addresses.
+    fn resolve<'a>(
+        &'a self,
+        host: &'a str,
+        port: u16,
+    ) -> Pin<Box<dyn Future<Output = io::Result<Vec<SocketAddr>>> + Send + 'a>>;
+}
+
+/// The default resolver. Uses the system resolver and caches the result.
+///
+/// The system resolver doesn't tell us the TTL of the DNS records, which means
+/// the cache uses a fixed time for all entries. Defaults to 60 seconds.
+///
+/// ```
+/// use hreq::{Agent, DnsCache};
+/// use std::time::Duration;
+///
+/// let mut agent = Agent::new();
+/// agent.resolver(DnsCache::new(Duration::from_secs(10)));
+/// ```
+pub struct DnsCache {
+    ttl: Duration,
+    cache: Mutex<HashMap<(String, u16), CacheEntry>>,
+}
+
+/// When the addresses were resolved, and the addresses.
+type CacheEntry = (Instant, Vec<SocketAddr>);
+
+impl DnsCache {
+    /// Creates a cache that keeps resolved addresses for `ttl`.
+    ///
+    /// A `ttl` of zero disables the caching.
+    pub fn new(ttl: Duration) -> Self {
+        DnsCache {
+            ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, key: &(String, u16)) -> Option<Vec<SocketAddr>> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(key)
+            .filter(|(at, _)| at.elapsed() < self.ttl)
+            .map(|(_, addrs)| addrs.clone())
+    }
+
+    fn insert(&self, key: (String, u16), addrs: Vec<SocketAddr>) {
+        if self.ttl == Duration::from_secs(0) {
+            return;
+        }
+        let mut cache = self.cache.lock().unwrap();
+        let ttl = self.ttl;
+        cache.retain(|_, (at, _)| at.elapsed() < ttl);
+        cache.insert(key, (Instant::now(), addrs));
+    }
+}
+
+impl Default for DnsCache {
+    fn default() -> Self {
+        DnsCache::new(Duration::from_secs(60))
+    }
+}
+
+impl Resolve for DnsCache {
+    fn resolve<'a>(
+        &'a self,
+        host: &'a str,
+        port: u16,
+    ) -> Pin<Box<dyn Future<Output = io::Result<Vec<SocketAddr>>> + Send + 'a>> {
+        Box::pin(async move {
+            let key = (host.to_ascii_lowercase(), port);
+
+            if let Some(addrs) = self.get(&key) {
+                trace!("DNS cache hit: {}:{}", host, port);
+                return Ok(addrs);
+            }
+
+            let addrs = AsyncRuntime::lookup_host(&format!("{}:{}", host, port)).await?;
+
+            self.insert(key, addrs.clone());
+
+            Ok(addrs)
+        })
+    }
+}
+
+impl fmt::Debug for DnsCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DnsCache {{ ttl: {:?} }}", self.ttl)
+    }
+}
+
+/// The resolver used by an agent, with static host overrides.
+///
+/// The overrides are shared between clones, like the resolver.
+#[derive(Clone)]
+pub(crate) struct Resolver {
+    resolve: Arc<dyn Resolve>,
+    statics: Arc<Mutex<Statics>>,
+}
+
+/// Overridden addresses by host and port.
+type Statics = HashMap<(String, u16), Vec<SocketAddr>>;
+
+impl Resolver {
+    pub fn set_resolve(&mut self, resolve: Arc<dyn Resolve>) {
+        self.resolve = resolve;
+    }
+
+    pub fn set_static(&mut self, host: &str, port: u16, addrs: &[SocketAddr]) {
+        let key = (host.to_ascii_lowercase(), port);
+        let mut statics = self.statics.lock().unwrap();
+        if addrs.is_empty() {
+            statics.remove(&key);
+        } else {
+            statics.insert(key, addrs.to_vec());
+        }
+    }
+
+    pub async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        // ipv6 hosts are in brackets, [::1]
+        let bare = host.trim_start_matches('[').trim_end_matches(']');
+
+        if let Ok(ip) = bare.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+
+        let key = (bare.to_ascii_lowercase(), port);
+        if let Some(addrs) = self.statics.lock().unwrap().get(&key).cloned() {
+            debug!("Resolved {}:{} (static) to {:?}", bare, port, addrs);
+            return Ok(addrs);
+        }
+
+        let addrs = self.resolve.resolve(bare, port).await?;
+
+        if addrs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No address found for: {}", bare),
+            ));
+        }
+
+        debug!("Resolved {}:{} to {:?}", bare, port, addrs);
+
+        Ok(addrs)
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Resolver {
+            resolve: Arc::new(DnsCache::default()),
+            statics: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BlockExt;
+
+    struct Fail;
+
+    impl Resolve for Fail {
+        fn resolve<'a>(
+            &'a self,
+            host: &'a str,
+            _port: u16,
+        ) -> Pin<Box<dyn Future<Output = io::Result<Vec<SocketAddr>>> + Send + 'a>> {
+            Box::pin(async move { panic!("Resolve called for: {}", host) })
+        }
+    }
+
+    #[test]
+    fn ip_and_static_bypass_resolve() {
+        let mut r = Resolver::default();
+        r.set_resolve(Arc::new(Fail));
+
+        let addr: SocketAddr = "10.0.0.1:8080".parse().unwrap();
+        r.set_static("Example.COM", 443, &[addr]);
+
+        let res = r.resolve("example.com", 443).block().unwrap();
+        assert_eq!(res, vec![addr]);
+
+        let res = r.resolve("127.0.0.1", 80).block().unwrap();
+        assert_eq!(res, vec!["127.0.0.1:80".parse().unwrap()]);
+
+        let res = r.resolve("[::1]", 80).block().unwrap();
+        assert_eq!(res, vec!["[::1]:80".parse().unwrap()]);
+    }
+
+    #[test]
+    fn dns_cache_ttl() {
+        let cache = DnsCache::new(Duration::from_secs(60));
+        let key = ("example.com".to_string(), 80);
+        let addrs = vec!["10.0.0.1:80".parse().unwrap()];
+        cache.insert(key.clone(), addrs.clone());
+        assert_eq!(cache.get(&key), Some(addrs.clone()));
+
+        let cache = DnsCache::new(Duration::from_secs(0));
+        cache.insert(key.clone(), addrs);
+        assert_eq!(cache.get(&key), None);
+    }
+}
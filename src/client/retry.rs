@@ -0,0 +1,329 @@
+//! When and how to retry failed requests.
+
+use crate::head_ext::HeaderMapExt;
+use crate::rand::random_u64;
+use crate::Body;
+use crate::Error;
+use http::{Request, Response};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+type Predicate = Arc<dyn Fn(Result<&Response<Body>, &Error>) -> bool + Send + Sync>;
+
+/// Policy for retrying requests.
+///
+/// By default, hreq makes up to 5 attempts, with a backoff starting at 125 milliseconds
+/// that doubles up to 10 seconds between attempts. Only idempotent methods (GET, HEAD,
+/// OPTIONS, TRACE, PUT and DELETE) are retried, and only on errors such as
+/// `ConnectionReset` that indicate the request can be sent again.
+///
+/// Requests with a body are only retried when the body can be sent again, which means
+/// it must fit in the [`redirect_body_buffer`].
+///
+/// The policy is set on the [`Agent`], or [per request].
+///
+/// ```
+/// use hreq::{Agent, RetryPolicy};
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy::new()
+///     .max_attempts(3)
+///     .backoff(Duration::from_millis(500), Duration::from_secs(5))
+///     .jitter(0.5)
+///     .statuses(&[429, 502, 503, 504]);
+///
+/// let mut agent = Agent::new();
+/// agent.retry_policy(policy);
+/// ```
+///
+/// [`redirect_body_buffer`]: trait.RequestBuilderExt.html#tymethod.redirect_body_buffer
+/// [`Agent`]: struct.Agent.html
+/// [per request]: trait.RequestBuilderExt.html#tymethod.retry_policy
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff_base: Duration,
+    backoff_max: Duration,
+    jitter: f64,
+    statuses: Vec<u16>,
+    max_retry_after: Option<Duration>,
+    idempotency_key: bool,
+    predicate: Option<Predicate>,
+}
+
+impl RetryPolicy {
+    /// Creates a policy with the default settings.
+    ///
+    /// * Max attempts: 5
+    /// * Backoff: 125 milliseconds doubling up to 10 seconds
+    /// * Jitter: none
+    /// * Statuses: none
+    /// * Retry-After: honoured up to 60 seconds
+    /// * Idempotency key: off
+    pub fn new() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            backoff_base: Duration::from_millis(125),
+            backoff_max: Duration::from_secs(10),
+            jitter: 0.0,
+            statuses: vec![],
+            max_retry_after: Some(Duration::from_secs(60)),
+            idempotency_key: false,
+            predicate: None,
+        }
+    }
+
+    /// Total number of attempts, including the first. `0` or `1` disables retries.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts;
+        self
+    }
+
+    /// The delay before the first retry, doubling for each retry up to `max`.
+    pub fn backoff(mut self, base: Duration, max: Duration) -> Self {
+        self.backoff_base = base;
+        self.backoff_max = max;
+        self
+    }
+
+    /// Randomly shorten each backoff by up to this fraction, between `0.0` and `1.0`.
+    ///
+    /// Jitter avoids many clients retrying in lockstep against a struggling server.
+    pub fn jitter(mut self, fraction: f64) -> Self {
+        self.jitter = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Response statuses to retry, typically `429`, `502`, `503` and `504`.
+    ///
+    /// The body of the response is discarded before retrying. When all attempts are
+    /// used up, the last response is returned.
+    pub fn statuses(mut self, statuses: &[u16]) -> Self {
+        self.statuses = statuses.to_vec();
+        self
+    }
+
+    /// Wait as long as a `Retry-After` header says before retrying on a status, up to
+    /// `max`.
+    ///
+    /// A response asking for a longer wait is returned without retrying. `None`
+    /// ignores the header and uses the backoff instead.
+    pub fn retry_after(mut self, max: Option<Duration>) -> Self {
+        self.max_retry_after = max;
+        self
+    }
+
+    /// Retry non-idempotent requests, like `POST`, by sending them with an
+    /// `Idempotency-Key` header.
+    ///
+    /// Requests without the header get a random key, which is the same for all
+    /// attempts. The server is expected to use the key to not carry out the same
+    /// request twice.
+    pub fn idempotency_key(mut self, enable: bool) -> Self {
+        self.idempotency_key = enable;
+        self
+    }
+
+    /// Decide which outcomes to retry with a function.
+    ///
+    /// The function is given the response or error of each attempt, and replaces the
+    /// check of [`statuses`] and error kinds. The number of attempts, the method and
+    /// whether the body can be sent again still apply.
+    ///
+    /// ```
+    /// use hreq::prelude::*;
+    /// use hreq::RetryPolicy;
+    ///
+    /// let policy = RetryPolicy::new().retry_if(|outcome| match outcome {
+    ///     Ok(res) => res.status_code() == 503 || res.header("x-retry").is_some(),
+    ///     Err(err) => err.is_io(),
+    /// });
+    /// ```
+    ///
+    /// [`statuses`]: struct.RetryPolicy.html#method.statuses
+    pub fn retry_if<F>(mut self, f: F) -> Self
+    where
+        F: Fn(Result<&Response<Body>, &Error>) -> bool + Send + Sync + 'static,
+    {
+        self.predicate = Some(Arc::new(f));
+        self
+    }
+
+    /// Whether there are attempts left after `retried` retries.
+    pub(crate) fn has_attempts_left(&self, retried: usize) -> bool {
+        retried + 1 < self.max_attempts as usize
+    }
+
+    /// Add an idempotency key, if the policy says so.
+    pub(crate) fn prepare(&self, parts: &mut http::request::Parts) {
+        if self.idempotency_key
+            && !parts.method.is_idempotent()
+            && !parts.headers.contains_key("idempotency-key")
+        {
+            parts.headers.set("idempotency-key", random_key());
+        }
+    }
+
+    /// Whether the method, or an idempotency key, allows the request to be sent again.
+    pub(crate) fn is_retryable_request(&self, req: &Request<Body>) -> bool {
+        req.method().is_idempotent()
+            || self.idempotency_key && req.headers().contains_key("idempotency-key")
+    }
+
+    pub(crate) fn is_retryable_error(&self, err: &Error) -> bool {
+        match &self.predicate {
+            Some(f) => f(Err(err)),
+            None => err.is_retryable(),
+        }
+    }
+
+    pub(crate) fn is_retryable_response(&self, res: &Response<Body>) -> bool {
+        match &self.predicate {
+            Some(f) => f(Ok(res)),
+            None => self.statuses.contains(&res.status().as_u16()),
+        }
+    }
+
+    /// The delay before retry number `retry`, counting from 1.
+    pub(crate) fn backoff_delay(&self, retry: usize) -> Duration {
+        let exp = retry.saturating_sub(1).min(31) as u32;
+        let delay = self
+            .backoff_base
+            .checked_mul(1 << exp)
+            .unwrap_or(self.backoff_max)
+            .min(self.backoff_max);
+
+        if self.jitter > 0.0 {
+            let r = (random_u64() >> 11) as f64 / (1u64 << 53) as f64;
+            delay.mul_f64(1.0 - self.jitter * r)
+        } else {
+            delay
+        }
+    }
+
+    /// The delay before retrying on a response status. `None` if `Retry-After` asks
+    /// for a longer wait than we accept.
+    pub(crate) fn response_delay(&self, retry: usize, res: &Response<Body>) -> Option<Duration> {
+        let max = match self.max_retry_after {
+            Some(max) => max,
+            None => return Some(self.backoff_delay(retry)),
+        };
+
+        match res
+            .headers()
+            .get_str("retry-after")
+            .and_then(parse_retry_after)
+        {
+            Some(wait) if wait > max => None,
+            Some(wait) => Some(wait),
+            None => Some(self.backoff_delay(retry)),
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new()
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("backoff_base", &self.backoff_base)
+            .field("backoff_max", &self.backoff_max)
+            .field("jitter", &self.jitter)
+            .field("statuses", &self.statuses)
+            .field("max_retry_after", &self.max_retry_after)
+            .field("idempotency_key", &self.idempotency_key)
+            .field("predicate", &self.predicate.is_some())
+            .finish()
+    }
+}
+
+/// `Retry-After` is either delay-seconds or an HTTP-date.
+fn parse_retry_after(v: &str) -> Option<Duration> {
+    let v = v.trim();
+
+    if let Ok(secs) = v.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let at = httpdate::parse_http_date(v).ok()?;
+
+    Some(at.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// A random key formatted like a UUID v4.
+fn random_key() -> String {
+    let a = random_u64();
+    let b = random_u64();
+    format!(
+        "{:08x}-{:04x}-4{:03x}-{:04x}-{:012x}",
+        a >> 32,
+        (a >> 16) & 0xffff,
+        a & 0x0fff,
+        0x8000 | (b >> 48) & 0x3fff,
+        b & 0xffff_ffff_ffff
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_to_max() {
+        let p = RetryPolicy::new();
+        let ms: Vec<_> = (1..=9).map(|n| p.backoff_delay(n).as_millis()).collect();
+        assert_eq!(
+            ms,
+            vec![125, 250, 500, 1000, 2000, 4000, 8000, 10000, 10000]
+        );
+        assert_eq!(p.backoff_delay(1000), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn backoff_jitter() {
+        let p = RetryPolicy::new().jitter(0.5);
+        for _ in 0..100 {
+            let d = p.backoff_delay(3);
+            assert!(d >= Duration::from_millis(250) && d <= Duration::from_millis(500));
+        }
+    }
+
+    #[test]
+    fn retry_after_header() {
+        assert_eq!(parse_retry_after(" 120 "), Some(Duration::from_secs(120)));
+        let at = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(30));
+        let d = parse_retry_after(&at).unwrap();
+        assert!(d > Duration::from_secs(25) && d <= Duration::from_secs(30));
+        assert_eq!(parse_retry_after("soon"), None);
+
+        let res = |v: &str| {
+            Response::builder()
+                .status(503)
+                .header("retry-after", v)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let p = RetryPolicy::new().retry_after(Some(Duration::from_secs(10)));
+        assert_eq!(p.response_delay(1, &res("2")), Some(Duration::from_secs(2)));
+        assert_eq!(p.response_delay(1, &res("20")), None);
+        assert_eq!(p.response_delay(1, &res("x")), Some(p.backoff_delay(1)));
+
+        let p = p.retry_after(None);
+        assert_eq!(p.response_delay(2, &res("20")), Some(p.backoff_delay(2)));
+    }
+
+    #[test]
+    fn idempotency_key_format() {
+        let k1 = random_key();
+        let k2 = random_key();
+        assert_eq!(k1.len(), 36);
+        assert_eq!(&k1[14..15], "4");
+        assert_ne!(k1, k2);
+    }
+}
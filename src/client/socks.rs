@@ -0,0 +1,170 @@
+//! SOCKS5 client handshake (RFC 1928, RFC 1929).
+
+use super::resolve::Resolver;
+use crate::error::SocksError;
+use crate::uri_ext::HostPort;
+use crate::Error;
+use crate::Stream;
+use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+use std::net::IpAddr;
+
+const VERSION: u8 = 0x05;
+const AUTH_VERSION: u8 = 0x01;
+
+const METHOD_NO_AUTH: u8 = 0x00;
+const METHOD_USER_PASS: u8 = 0x02;
+
+const CMD_CONNECT: u8 = 0x01;
+
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// Negotiate a connection to target over a stream connected to a SOCKS5 server.
+///
+/// With `remote_dns`, the host name is passed to the proxy to resolve. Otherwise the
+/// host name is resolved locally, and the proxy is given an IP address.
+pub(crate) async fn connect<S: Stream>(
+    mut stream: S,
+    target: &HostPort,
+    remote_dns: bool,
+    creds: Option<&(String, String)>,
+    resolver: &Resolver,
+) -> Result<S, Error> {
+    let addr = address(target, remote_dns, resolver).await?;
+
+    // greeting, offering username/password only if we got credentials.
+    if creds.is_some() {
+        stream
+            .write_all(&[VERSION, 2, METHOD_NO_AUTH, METHOD_USER_PASS])
+            .await?;
+    } else {
+        stream.write_all(&[VERSION, 1, METHOD_NO_AUTH]).await?;
+    }
+    stream.flush().await?;
+
+    let mut choice = [0_u8; 2];
+    stream.read_exact(&mut choice).await?;
+
+    if choice[0] != VERSION {
+        return Err(SocksError::BadVersion(choice[0]).into());
+    }
+
+    match (choice[1], creds) {
+        (METHOD_NO_AUTH, _) => {}
+        (METHOD_USER_PASS, Some((user, pass))) => {
+            authenticate(&mut stream, user, pass).await?;
+        }
+        // METHOD_NONE_ACCEPTABLE, or a method we didn't offer.
+        _ => return Err(SocksError::NoAcceptableAuth.into()),
+    }
+
+    // connect request
+    let mut req = vec![VERSION, CMD_CONNECT, 0x00];
+    req.extend_from_slice(&addr);
+    req.extend_from_slice(&target.port().to_be_bytes());
+
+    stream.write_all(&req).await?;
+    stream.flush().await?;
+
+    // VER REP RSV ATYP
+    let mut head = [0_u8; 4];
+    stream.read_exact(&mut head).await?;
+
+    if head[0] != VERSION {
+        return Err(SocksError::BadVersion(head[0]).into());
+    }
+
+    if head[1] != 0x00 {
+        return Err(SocksError::from_reply(head[1]).into());
+    }
+
+    // BND.ADDR and BND.PORT, which we don't use, but must consume.
+    let addr_len = match head[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => {
+            let mut len = [0_u8; 1];
+            stream.read_exact(&mut len).await?;
+            len[0] as usize
+        }
+        _ => return Err(SocksError::AddressTypeNotSupported.into()),
+    };
+
+    let mut bound = vec![0_u8; addr_len + 2];
+    stream.read_exact(&mut bound).await?;
+
+    trace!("SOCKS5 connection established to {}", target);
+
+    Ok(stream)
+}
+
+async fn authenticate<S: Stream>(stream: &mut S, user: &str, pass: &str) -> Result<(), Error> {
+    if user.len() > 255 || pass.len() > 255 {
+        return Err(Error::User(
+            "SOCKS5 username and password must be at most 255 bytes".into(),
+        ));
+    }
+
+    let mut req = vec![AUTH_VERSION, user.len() as u8];
+    req.extend_from_slice(user.as_bytes());
+    req.push(pass.len() as u8);
+    req.extend_from_slice(pass.as_bytes());
+
+    stream.write_all(&req).await?;
+    stream.flush().await?;
+
+    let mut res = [0_u8; 2];
+    stream.read_exact(&mut res).await?;
+
+    if res[0] != AUTH_VERSION {
+        return Err(SocksError::BadVersion(res[0]).into());
+    }
+
+    if res[1] != 0x00 {
+        return Err(SocksError::AuthFailed.into());
+    }
+
+    Ok(())
+}
+
+/// ATYP + DST.ADDR part of the connect request.
+async fn address(
+    target: &HostPort,
+    remote_dns: bool,
+    resolver: &Resolver,
+) -> Result<Vec<u8>, Error> {
+    let host = target.host().trim_start_matches('[').trim_end_matches(']');
+
+    let ip = match host.parse::<IpAddr>() {
+        Ok(ip) => ip,
+        Err(_) => {
+            if remote_dns {
+                if host.len() > 255 {
+                    return Err(Error::User(format!("Host name too long: {}", host)));
+                }
+                let mut addr = vec![ATYP_DOMAIN, host.len() as u8];
+                addr.extend_from_slice(host.as_bytes());
+                return Ok(addr);
+            }
+
+            // the resolver never returns an empty list.
+            let addrs = resolver.resolve(host, target.port()).await?;
+
+            addrs[0].ip()
+        }
+    };
+
+    Ok(match ip {
+        IpAddr::V4(v) => {
+            let mut addr = vec![ATYP_IPV4];
+            addr.extend_from_slice(&v.octets());
+            addr
+        }
+        IpAddr::V6(v) => {
+            let mut addr = vec![ATYP_IPV6];
+            addr.extend_from_slice(&v.octets());
+            addr
+        }
+    })
+}
@@ -0,0 +1,428 @@
+//! Client TLS configuration.
+
+use crate::proto::{ALPN_H1, ALPN_H2};
+use crate::tls::DisabledCertVerified;
+use crate::Error;
+use rustls::internal::pemfile;
+use rustls::{Certificate, ClientConfig, KeyLog, RootCertStore, ServerCertVerified};
+use rustls::{ServerCertVerifier, TLSError, WebPKIVerifier};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use webpki::DNSNameRef;
+use webpki_roots::TLS_SERVER_ROOTS;
+
+/// Configuration builder for client TLS.
+///
+/// Set on the [`Agent`] using [`Agent::tls_config`], or for a single request using
+/// [`RequestBuilderExt::tls_config`]. For the server side, see [`server::TlsConfig`].
+///
+/// Certificates and keys are PEM encoded. They are parsed when connecting, which
+/// means errors in them surface as errors of the request.
+///
+/// ```no_run
+/// use hreq::{Agent, TlsConfig};
+///
+/// let ca = std::fs::read("private-ca.pem").unwrap();
+/// let cert = std::fs::read("client-cert.pem").unwrap();
+/// let key = std::fs::read("client-key.pem").unwrap();
+///
+/// let config = TlsConfig::new()
+///     .root_certificates(ca)
+///     .webpki_roots(false)
+///     .client_certificate(cert, key);
+///
+/// let mut agent = Agent::new();
+/// agent.tls_config(config);
+/// ```
+///
+/// [`Agent`]: struct.Agent.html
+/// [`Agent::tls_config`]: struct.Agent.html#method.tls_config
+/// [`RequestBuilderExt::tls_config`]: trait.RequestBuilderExt.html#tymethod.tls_config
+/// [`server::TlsConfig`]: server/struct.TlsConfig.html
+#[derive(Clone)]
+pub struct TlsConfig {
+    webpki_roots: bool,
+    roots: Vec<Vec<u8>>,
+    client_cert: Option<(Vec<u8>, Vec<u8>)>,
+    pins: Vec<String>,
+    key_log: Option<PathBuf>,
+    rustls: Option<ClientConfig>,
+}
+
+impl TlsConfig {
+    /// Create a new TLS configuration builder.
+    ///
+    /// Without further configuration this is the same as the default, server
+    /// certificates are verified against the Mozilla root certificates.
+    pub fn new() -> Self {
+        TlsConfig {
+            webpki_roots: true,
+            roots: vec![],
+            client_cert: None,
+            pins: vec![],
+            key_log: None,
+            rustls: None,
+        }
+    }
+
+    /// Use a rustls `ClientConfig` built by hand.
+    ///
+    /// The config is used as is, apart from setting the ALPN protocols when it has
+    /// none. The other settings of this builder are ignored.
+    pub fn from_rustls(config: ClientConfig) -> Self {
+        TlsConfig {
+            rustls: Some(config),
+            ..TlsConfig::new()
+        }
+    }
+
+    /// Add root certificates to trust when verifying the server.
+    ///
+    /// The contents should be one or more PEM encoded certificates. Can be called
+    /// many times to add several files.
+    pub fn root_certificates(mut self, pem: impl AsRef<[u8]>) -> Self {
+        self.roots.push(pem.as_ref().to_vec());
+        self
+    }
+
+    /// Turns on or off trusting the Mozilla root certificates, as bundled by the
+    /// `webpki-roots` crate.
+    ///
+    /// Defaults to `true`. Turn off to only trust the certificates added
+    /// using [`root_certificates`].
+    ///
+    /// [`root_certificates`]: struct.TlsConfig.html#method.root_certificates
+    pub fn webpki_roots(mut self, enabled: bool) -> Self {
+        self.webpki_roots = enabled;
+        self
+    }
+
+    /// Client certificate for servers requiring mutual TLS.
+    ///
+    /// The `cert` should be a PEM encoded chain of certificates, starting with the
+    /// end-entity certificate. The `key` should be a PEM encoded PKCS8 or RSA private
+    /// key. The contents can be a mix of certificates and keys, in which case the
+    /// same contents can be passed for both.
+    pub fn client_certificate(mut self, cert: impl AsRef<[u8]>, key: impl AsRef<[u8]>) -> Self {
+        self.client_cert = Some((cert.as_ref().to_vec(), key.as_ref().to_vec()));
+        self
+    }
+
+    /// Pin the public key of the server.
+    ///
+    /// The `pin` is the base64 encoded SHA-256 hash of a DER encoded
+    /// SubjectPublicKeyInfo, optionally prefixed `sha256//` as for curl's
+    /// `--pinnedpubkey`. Connections are refused unless the end-entity certificate
+    /// of the server has a pinned public key. Can be called many times to allow
+    /// several keys, such as a backup key.
+    ///
+    /// The pin is checked in addition to the usual verification. With
+    /// [`tls_disable_server_cert_verify`], only the pin is checked.
+    ///
+    /// The pin of a certificate can be made with:
+    ///
+    /// ```text
+    /// openssl x509 -in cert.pem -noout -pubkey | openssl pkey -pubin -outform der \
+    ///   | openssl dgst -sha256 -binary | base64
+    /// ```
+    ///
+    /// [`tls_disable_server_cert_verify`]: trait.RequestBuilderExt.html#tymethod.tls_disable_server_cert_verify
+    pub fn pin_sha256(mut self, pin: &str) -> Self {
+        self.pins.push(pin.to_string());
+        self
+    }
+
+    /// Append TLS secrets to a file, for debugging with tools like Wireshark.
+    ///
+    /// The file is written in the NSS key log format, the same as `SSLKEYLOGFILE`
+    /// in browsers. Anyone holding the file can decrypt the traffic.
+    pub fn key_log_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.key_log = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub(crate) fn to_rustls_config(&self, tls_disable_verify: bool) -> Result<ClientConfig, Error> {
+        if let Some(config) = &self.rustls {
+            let mut config = config.clone();
+            if config.alpn_protocols.is_empty() {
+                config.alpn_protocols = vec![ALPN_H2.to_owned(), ALPN_H1.to_owned()];
+            }
+            if tls_disable_verify {
+                config
+                    .dangerous()
+                    .set_certificate_verifier(Arc::new(DisabledCertVerified));
+            }
+            return Ok(config);
+        }
+
+        let mut config = ClientConfig::new();
+
+        if self.webpki_roots {
+            config
+                .root_store
+                .add_server_trust_anchors(&TLS_SERVER_ROOTS);
+        }
+
+        for pem in &self.roots {
+            add_roots(&mut config.root_store, pem)?;
+        }
+
+        if let Some((cert, key)) = &self.client_cert {
+            let certs = pemfile::certs(&mut Cursor::new(cert))
+                .map_err(|_| Error::User("TlsConfig failed to extract certificates".into()))?;
+            if certs.is_empty() {
+                return Err(Error::User("No client certificates in TlsConfig".into()));
+            }
+
+            let mut keys = pemfile::pkcs8_private_keys(&mut Cursor::new(key))
+                .map_err(|_| Error::User("TlsConfig failed to extract private key".into()))?;
+            if keys.is_empty() {
+                keys = pemfile::rsa_private_keys(&mut Cursor::new(key))
+                    .map_err(|_| Error::User("TlsConfig failed to extract private key".into()))?;
+            }
+            let key = keys
+                .pop()
+                .ok_or_else(|| Error::User("Found no private key in TlsConfig".into()))?;
+
+            config.set_single_client_cert(certs, key)?;
+        }
+
+        if !self.pins.is_empty() {
+            let pins = self
+                .pins
+                .iter()
+                .map(|p| decode_pin(p))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(PinnedCertVerifier {
+                    verify_chain: !tls_disable_verify,
+                    pins,
+                }));
+        } else if tls_disable_verify {
+            config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(DisabledCertVerified));
+        }
+
+        if let Some(path) = &self.key_log {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            config.key_log = Arc::new(KeyLogFile(Mutex::new(file)));
+        }
+
+        config.alpn_protocols = vec![ALPN_H2.to_owned(), ALPN_H1.to_owned()];
+
+        Ok(config)
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig::new()
+    }
+}
+
+impl fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.rustls.is_some() {
+            return write!(f, "TlsConfig {{ rustls }}");
+        }
+        f.debug_struct("TlsConfig")
+            .field("webpki_roots", &self.webpki_roots)
+            .field("roots", &self.roots.len())
+            .field("client_cert", &self.client_cert.is_some())
+            .field("pins", &self.pins)
+            .field("key_log", &self.key_log)
+            .finish()
+    }
+}
+
+fn add_roots(store: &mut RootCertStore, pem: &[u8]) -> Result<(), Error> {
+    let certs = pemfile::certs(&mut Cursor::new(pem))
+        .map_err(|_| Error::User("TlsConfig failed to extract root certificates".into()))?;
+
+    if certs.is_empty() {
+        return Err(Error::User("No root certificates in TlsConfig".into()));
+    }
+
+    for cert in certs {
+        store
+            .add(&cert)
+            .map_err(|e| Error::User(format!("TlsConfig bad root certificate: {}", e)))?;
+    }
+
+    Ok(())
+}
+
+fn decode_pin(pin: &str) -> Result<Vec<u8>, Error> {
+    let b64 = pin.trim().trim_start_matches("sha256//");
+
+    let hash = base64::decode(b64)
+        .map_err(|e| Error::User(format!("TlsConfig bad pin ({}): {}", pin, e)))?;
+
+    if hash.len() != 32 {
+        return Err(Error::User(format!(
+            "TlsConfig pin is not a SHA-256 hash: {}",
+            pin
+        )));
+    }
+
+    Ok(hash)
+}
+
+/// Verifies the server end-entity certificate has one of the pinned public keys.
+struct PinnedCertVerifier {
+    verify_chain: bool,
+    pins: Vec<Vec<u8>>,
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        roots: &RootCertStore,
+        presented_certs: &[Certificate],
+        name: DNSNameRef,
+        ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        if self.verify_chain {
+            WebPKIVerifier::new().verify_server_cert(
+                roots,
+                presented_certs,
+                name,
+                ocsp_response,
+            )?;
+        }
+
+        // Only the end-entity certificate is proven by the handshake signature,
+        // anything later in the chain can be appended by the peer.
+        let pinned = presented_certs
+            .first()
+            .and_then(|c| subject_public_key_info(&c.0))
+            .map(|spki| {
+                let hash = Sha256::digest(spki);
+                self.pins.iter().any(|p| p[..] == hash[..])
+            })
+            .unwrap_or(false);
+
+        if pinned {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(TLSError::General(format!(
+                "No pinned public key in certificate for: {:?}",
+                name
+            )))
+        }
+    }
+}
+
+/// Extract the DER encoded SubjectPublicKeyInfo from a DER encoded certificate.
+fn subject_public_key_info(cert: &[u8]) -> Option<&[u8]> {
+    // Certificate ::= SEQUENCE { tbsCertificate, signatureAlgorithm, signature }
+    let (_, cert, _) = der_next(cert)?;
+    let (_, tbs, _) = der_next(cert)?;
+
+    let mut rest = tbs;
+
+    // version is optional and explicitly tagged [0]
+    if rest.first() == Some(&0xa0) {
+        rest = der_next(rest)?.2;
+    }
+
+    // serialNumber, signature, issuer, validity, subject
+    for _ in 0..5 {
+        rest = der_next(rest)?.2;
+    }
+
+    let (_, _, after) = der_next(rest)?;
+
+    Some(&rest[..rest.len() - after.len()])
+}
+
+/// Read one DER tag-length-value. Returns (tag, value, rest).
+fn der_next(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *input.first()?;
+    let first = *input.get(1)? as usize;
+
+    let (len, start) = if first < 0x80 {
+        (first, 2)
+    } else {
+        let n = first & 0x7f;
+        if n == 0 || n > 4 {
+            return None;
+        }
+        let bytes = input.get(2..2 + n)?;
+        let len = bytes.iter().fold(0, |acc, b| (acc << 8) | *b as usize);
+        (len, 2 + n)
+    };
+
+    let value = input.get(start..start.checked_add(len)?)?;
+
+    Some((tag, value, &input[start + len..]))
+}
+
+/// Writes secrets in the NSS key log format.
+struct KeyLogFile(Mutex<File>);
+
+impl KeyLog for KeyLogFile {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        let line = format!("{} {} {}\n", label, hex(client_random), hex(secret));
+        let mut file = self.0.lock().unwrap();
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            warn!("Failed to write TLS key log: {}", e);
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn spki_of_certificate() {
+        let pem = include_bytes!("../../tests/data/tls_server.pem");
+        let certs = pemfile::certs(&mut Cursor::new(&pem[..])).unwrap();
+        let spki = subject_public_key_info(&certs[0].0).unwrap();
+
+        // SubjectPublicKeyInfo is a SEQUENCE starting with the rsaEncryption OID.
+        assert_eq!(spki[0], 0x30);
+        assert_eq!(
+            &spki[4..17],
+            b"\x30\x0d\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x01\x01"
+        );
+
+        let hash = Sha256::digest(spki);
+        assert_eq!(
+            base64::encode(hash),
+            "InAKoIx2GoNqX+lsZkIvVmHvGmC1/eicTYi49eiuVdw="
+        );
+    }
+
+    #[test]
+    fn pin_decoding() {
+        let pin = "sha256//InAKoIx2GoNqX+lsZkIvVmHvGmC1/eicTYi49eiuVdw=";
+        assert_eq!(decode_pin(pin).unwrap().len(), 32);
+        assert!(decode_pin("not base64!").is_err());
+        assert!(decode_pin("aGVsbG8=").is_err());
+    }
+
+    #[test]
+    fn der_lengths() {
+        assert_eq!(
+            der_next(&[0x02, 0x01, 0x05, 0xff]),
+            Some((0x02, &[5][..], &[0xff][..]))
+        );
+        let mut long = vec![0x04, 0x81, 0x80];
+        long.extend(vec![0; 0x80]);
+        assert_eq!(der_next(&long).unwrap().1.len(), 0x80);
+        assert_eq!(der_next(&[0x04, 0x05, 0x00]), None);
+    }
+}
@@ -25,6 +25,8 @@ pub enum Error {
     Http(http::Error),
     /// JSON deserialization errors.
     Json(serde_json::Error),
+    /// Form (`application/x-www-form-urlencoded`) deserialization errors.
+    Form(serde_urlencoded::de::Error),
     /// TLS (https) errors.
     #[cfg(feature = "tls")]
     TlsError(TLSError),
@@ -36,6 +38,55 @@ pub enum Error {
     AddrParse(net::AddrParseError),
     /// Failure to convert a string to UTF8.
     Utf8(Utf8Error),
+    /// Failure to connect through a SOCKS5 proxy.
+    Socks(SocksError),
+}
+
+/// Errors when connecting through a SOCKS5 proxy.
+///
+/// Most variants correspond to the reply codes of a SOCKS5 server (RFC 1928).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocksError {
+    /// Reply `0x01`, general SOCKS server failure.
+    GeneralFailure,
+    /// Reply `0x02`, connection not allowed by ruleset.
+    NotAllowed,
+    /// Reply `0x03`, network unreachable.
+    NetworkUnreachable,
+    /// Reply `0x04`, host unreachable.
+    HostUnreachable,
+    /// Reply `0x05`, connection refused.
+    ConnectionRefused,
+    /// Reply `0x06`, TTL expired.
+    TtlExpired,
+    /// Reply `0x07`, command not supported.
+    CommandNotSupported,
+    /// Reply `0x08`, address type not supported.
+    AddressTypeNotSupported,
+    /// Some other reply code not defined by RFC 1928.
+    UnknownReply(u8),
+    /// The server did not accept any of the offered authentication methods.
+    NoAcceptableAuth,
+    /// The server rejected the username/password.
+    AuthFailed,
+    /// The server responded with something that isn't SOCKS5.
+    BadVersion(u8),
+}
+
+impl SocksError {
+    pub(crate) fn from_reply(code: u8) -> Self {
+        match code {
+            0x01 => SocksError::GeneralFailure,
+            0x02 => SocksError::NotAllowed,
+            0x03 => SocksError::NetworkUnreachable,
+            0x04 => SocksError::HostUnreachable,
+            0x05 => SocksError::ConnectionRefused,
+            0x06 => SocksError::TtlExpired,
+            0x07 => SocksError::CommandNotSupported,
+            0x08 => SocksError::AddressTypeNotSupported,
+            _ => SocksError::UnknownReply(code),
+        }
+    }
 }
 
 impl Error {
@@ -91,6 +142,7 @@ impl fmt::Display for Error {
             Error::H2(v) => write!(f, "http2: {}", v),
             Error::Http(v) => write!(f, "http api: {}", v),
             Error::Json(v) => write!(f, "json: {}", v),
+            Error::Form(v) => write!(f, "form: {}", v),
             #[cfg(feature = "tls")]
             Error::TlsError(v) => write!(f, "tls: {}", v),
             #[cfg(feature = "tls")]
@@ -98,10 +150,32 @@ impl fmt::Display for Error {
             #[cfg(feature = "server")]
             Error::AddrParse(v) => write!(f, "addr parse: {}", v),
             Error::Utf8(v) => write!(f, "utf-8: {}", v),
+            Error::Socks(v) => write!(f, "socks5: {}", v),
         }
     }
 }
 
+impl fmt::Display for SocksError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SocksError::GeneralFailure => write!(f, "general server failure"),
+            SocksError::NotAllowed => write!(f, "connection not allowed by ruleset"),
+            SocksError::NetworkUnreachable => write!(f, "network unreachable"),
+            SocksError::HostUnreachable => write!(f, "host unreachable"),
+            SocksError::ConnectionRefused => write!(f, "connection refused"),
+            SocksError::TtlExpired => write!(f, "TTL expired"),
+            SocksError::CommandNotSupported => write!(f, "command not supported"),
+            SocksError::AddressTypeNotSupported => write!(f, "address type not supported"),
+            SocksError::UnknownReply(v) => write!(f, "unknown reply code: {}", v),
+            SocksError::NoAcceptableAuth => write!(f, "no acceptable authentication method"),
+            SocksError::AuthFailed => write!(f, "authentication failed"),
+            SocksError::BadVersion(v) => write!(f, "unexpected protocol version: {}", v),
+        }
+    }
+}
+
+impl std::error::Error for SocksError {}
+
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
@@ -112,10 +186,12 @@ impl std::error::Error for Error {
             Error::H2(e) => Some(e),
             Error::Http(e) => Some(e),
             Error::Json(e) => Some(e),
+            Error::Form(e) => Some(e),
             Error::TlsError(e) => Some(e),
             Error::DnsName(e) => Some(e),
             Error::AddrParse(e) => Some(e),
             Error::Utf8(e) => Some(e),
+            Error::Socks(e) => Some(e),
         }
     }
 }
@@ -159,6 +235,12 @@ impl From<serde_json::Error> for Error {
     }
 }
 
+impl From<serde_urlencoded::de::Error> for Error {
+    fn from(e: serde_urlencoded::de::Error) -> Self {
+        Error::Form(e)
+    }
+}
+
 #[cfg(feature = "tls")]
 impl From<TLSError> for Error {
     fn from(e: TLSError) -> Self {
@@ -180,6 +262,12 @@ impl From<net::AddrParseError> for Error {
     }
 }
 
+impl From<SocksError> for Error {
+    fn from(e: SocksError) -> Self {
+        Error::Socks(e)
+    }
+}
+
 impl From<Utf8Error> for Error {
     fn from(e: Utf8Error) -> Self {
         Error::Utf8(e)
@@ -0,0 +1,115 @@
+//! application/x-www-form-urlencoded in other charsets than utf-8.
+//!
+//! A urlencoded body is plain ascii, but the percent encoded bytes are in some
+//! character set. serde_urlencoded only deals in utf-8, so we transcode the
+//! percent encoded bytes between the charset and utf-8.
+
+use encoding_rs::Encoding;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/// Serialize a form to urlencoded using the given charset.
+pub(crate) fn encode<B: Serialize + ?Sized>(form: &B, enc: &'static Encoding) -> String {
+    let utf8 = serde_urlencoded::to_string(form).expect("Failed to encode form");
+
+    if enc == encoding_rs::UTF_8 {
+        return utf8;
+    }
+
+    transcode(utf8.as_bytes(), |bytes| {
+        let s = String::from_utf8_lossy(bytes);
+        // unmappable chars become numeric character references, like browsers do.
+        let (encoded, _, _) = enc.encode(&s);
+        encoded.into_owned()
+    })
+}
+
+/// Convert a urlencoded body in the given charset to utf-8.
+pub(crate) fn decode<'a>(body: &'a [u8], enc: &'static Encoding) -> Cow<'a, [u8]> {
+    if enc == encoding_rs::UTF_8 {
+        return Cow::Borrowed(body);
+    }
+
+    let utf8 = transcode(body, |bytes| {
+        let (decoded, _) = enc.decode_without_bom_handling(bytes);
+        decoded.into_owned().into_bytes()
+    });
+
+    Cow::Owned(utf8.into_bytes())
+}
+
+/// Apply a conversion to every percent decoded key and value.
+fn transcode<F: Fn(&[u8]) -> Vec<u8>>(body: &[u8], convert: F) -> String {
+    let mut out = String::with_capacity(body.len());
+
+    for (idx, pair) in body.split(|c| *c == b'&').enumerate() {
+        if idx > 0 {
+            out.push('&');
+        }
+
+        let mut split = pair.splitn(2, |c| *c == b'=');
+
+        if let Some(key) = split.next() {
+            serialize(&convert(&deserialize(key)), &mut out);
+        }
+
+        if let Some(value) = split.next() {
+            out.push('=');
+            serialize(&convert(&deserialize(value)), &mut out);
+        }
+    }
+
+    out
+}
+
+fn deserialize(input: &[u8]) -> Vec<u8> {
+    let plus_as_space: Vec<u8> = input
+        .iter()
+        .map(|c| if *c == b'+' { b' ' } else { *c })
+        .collect();
+
+    percent_encoding::percent_decode(&plus_as_space).collect()
+}
+
+fn serialize(input: &[u8], out: &mut String) {
+    for c in input {
+        match c {
+            b' ' => out.push('+'),
+            b'*' | b'-' | b'.' | b'_' | b'0'..=b'9' | b'a'..=b'z' | b'A'..=b'Z' => {
+                out.push(*c as char)
+            }
+            _ => write!(out, "%{:02X}", c).unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_utf8() {
+        let form = [("name", "Åsa Öst"), ("city", "Malmö")];
+        assert_eq!(
+            encode(&form, encoding_rs::UTF_8),
+            "name=%C3%85sa+%C3%96st&city=Malm%C3%B6"
+        );
+    }
+
+    #[test]
+    fn encode_latin1() {
+        let form = [("name", "Åsa Öst"), ("snow", "☃")];
+        assert_eq!(
+            encode(&form, encoding_rs::WINDOWS_1252),
+            "name=%C5sa+%D6st&snow=%26%239731%3B"
+        );
+    }
+
+    #[test]
+    fn decode_latin1() {
+        let body = b"name=%C5sa+%D6st&empty=&flag";
+        let utf8 = decode(body, encoding_rs::WINDOWS_1252);
+        assert_eq!(&*utf8, &b"name=%C3%85sa+%C3%96st&empty=&flag"[..]);
+    }
+}
@@ -158,7 +158,7 @@
 //! use hreq::prelude::*;
 //! use hreq::Agent;
 //!
-//! let mut agent = Agent::new();
+//! let agent = Agent::new();
 //!
 //! let req1 = Request::get("https://httpbin.org/get")
 //!     .with_body(()).unwrap();
@@ -173,6 +173,9 @@
 //! let res2 = agent.send(req2).block();
 //! ```
 //!
+//! The agent is cheap to clone and can be shared between tasks. Clones use the
+//! same connection pool and cookies, and requests can be sent concurrently.
+//!
 //! ## Retries
 //!
 //! The internet is a dangerous place and http requests fail all the time.
@@ -184,6 +187,11 @@
 //! * Only for idempotent methods: GET, HEAD, OPTIONS, TRACE, PUT and DELETE.
 //! * Only when the  encountered error is retryable, such as BrokenPipe,
 //!   ConnectionAborted, ConnectionReset, Interrupted.
+//! * Only when a request body can be sent again, see `redirect_body_buffer`.
+//!
+//! A [`RetryPolicy`] changes this, for instance to retry on `503` while honouring
+//! `Retry-After`, add jitter to the backoff, or retry a `POST` with an
+//! `Idempotency-Key` header.
 //!
 //! To disable retries, one must use a configured agent:
 //!
@@ -200,16 +208,44 @@
 //! let res = agent.send(req).block();
 //! ```
 //!
+//! [`RetryPolicy`]: struct.RetryPolicy.html
+//!
 //! ## Redirects
 //!
 //! By default hreq follows up to 5 redirects. Redirects can be turned off
 //! by using an explicit agent in the same way as for retries.
 //!
+//! A `POST` redirected with `301`, `302` or `303` becomes a `GET`, redirects from
+//! `https` to `http` are not followed, and credentials are not sent to other origins.
+//! A [`RedirectPolicy`] can change this, and decide for each redirect whether to follow
+//! it. The response tells the redirects followed and where it ended up.
+//!
+//! ```no_run
+//! use hreq::prelude::*;
+//!
+//! let res = Request::get("http://my-api/old")
+//!     .call().block().unwrap();
+//!
+//! assert_eq!(res.redirect_history().len(), res.redirects());
+//! println!("Ended up at: {:?}", res.final_uri());
+//! ```
+//!
+//! [`RedirectPolicy`]: struct.RedirectPolicy.html
+//!
 //! # Compression
 //!
 //! hreq supports content compression both for requests and responses. The
 //! feature is enabled by receving or setting the `content-encoding` header
-//! to `gzip`. Currently hreq only supports `gzip`.
+//! to one of the supported algorithms. Each is behind a cargo feature:
+//!
+//!   * `gzip` (feature `gzip`, default)
+//!   * `br` (feature `brotli`)
+//!   * `deflate` (feature `deflate`)
+//!   * `zstd` (feature `zstd`)
+//!
+//! Stacked encodings, like `content-encoding: gzip, br`, are applied in order.
+//! Requests are sent with an `accept-encoding` header listing the compiled in
+//! algorithms, unless the header is set already.
 //!
 //! ## Example request with gzip body:
 //!
@@ -339,6 +375,7 @@
 //! * Connection pooling
 //! * JSON serialize/deserialize
 //! * Cookies
+//! * HTTP, HTTPS (CONNECT) and SOCKS5 proxy
 //!
 //! [http crate]: https://crates.io/crates/http
 //! [`rt-core`]: https://docs.rs/tokio/latest/tokio/runtime/index.html#basic-scheduler
@@ -360,6 +397,7 @@ extern crate log;
 mod async_impl;
 mod block_ext;
 mod body;
+mod body_channel;
 mod body_codec;
 mod body_send;
 mod bw;
@@ -368,15 +406,24 @@ mod client;
 mod deadline;
 mod either;
 mod error;
+mod form;
 mod from_utf8;
 mod head_ext;
+mod multipart;
 mod params;
 mod proto;
+mod rand;
 mod res_ext;
 mod uninit;
 mod uri_ext;
+pub mod ws;
 
-pub use client::{Agent, ResponseFuture};
+#[cfg(feature = "tls")]
+pub use client::TlsConfig;
+pub use client::{Agent, DnsCache, Middleware, Next, Proxy, Resolve, ResponseFuture, RetryPolicy};
+pub use client::{CacheEntry, CacheStatus, CacheStore, DiskCache, MemoryCache};
+pub use client::{CookieJar, CookieStore};
+pub use client::{RedirectHop, RedirectPolicy};
 
 #[cfg(feature = "server")]
 pub mod server;
@@ -393,17 +440,20 @@ use once_cell::sync::Lazy;
 
 // Can't have two slashes here apparently.
 // https://tools.ietf.org/html/rfc7230#section-3.2.6
-pub(crate) const AGENT_IDENT: Lazy<String> = Lazy::new(|| format!("hreq/{}", crate::VERSION));
+pub(crate) static AGENT_IDENT: Lazy<String> = Lazy::new(|| format!("hreq/{}", crate::VERSION));
 
 pub(crate) use futures_io::{AsyncBufRead, AsyncRead, AsyncSeek, AsyncWrite};
 
 pub use crate::async_impl::AsyncRuntime;
 pub use crate::block_ext::BlockExt;
 pub use crate::body::Body;
+pub use crate::body_channel::BodySender;
 pub use crate::client::RequestBuilderExt;
 pub use crate::client::RequestExt;
-pub use crate::error::Error;
+pub use crate::error::{Error, SocksError};
+pub use crate::multipart::{Multipart, Part};
 pub use crate::res_ext::ResponseExt;
+pub use crate::ws::WebSocket;
 pub use http;
 
 pub mod cookie {
@@ -0,0 +1,417 @@
+//! multipart/form-data request bodies.
+
+use crate::rand::random_u64;
+use crate::AsyncRead;
+use crate::AsyncRuntime;
+use crate::Body;
+use futures_util::ready;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+type BoxReader = Box<dyn AsyncRead + Unpin + Send + Sync>;
+
+/// Builder of a `multipart/form-data` body.
+///
+/// Each part of the form is either a text field or a file. The resulting body is
+/// streamed, which means files are read as they are sent and never buffered in
+/// memory.
+///
+/// If the length of every part is known, the body gets a `content-length`, otherwise
+/// it's sent using `transfer-encoding: chunked`.
+///
+/// # Example
+///
+/// ```no_run
+/// use hreq::prelude::*;
+/// use hreq::{Multipart, Part};
+///
+/// let form = Multipart::new()
+///     .text("name", "Martin")
+///     .file_path("avatar", "avatar.png").unwrap()
+///     .part(
+///         "notes",
+///         Part::bytes(b"some notes".to_vec())
+///             .file_name("notes.txt")
+///             .content_type("text/plain"),
+///     );
+///
+/// let res = Request::post("https://my-form-handler")
+///     .send_multipart(form)
+///     .block().unwrap();
+/// ```
+pub struct Multipart {
+    boundary: String,
+    parts: Vec<(String, Part)>,
+}
+
+/// A single part of a [`Multipart`] form.
+///
+/// [`Multipart`]: struct.Multipart.html
+pub struct Part {
+    source: Source,
+    length: Option<u64>,
+    file_name: Option<String>,
+    content_type: Option<String>,
+}
+
+enum Source {
+    Bytes(Vec<u8>),
+    Reader(BoxReader),
+}
+
+impl Multipart {
+    /// Creates a new form with a randomly generated boundary.
+    pub fn new() -> Self {
+        Multipart {
+            boundary: gen_boundary(),
+            parts: vec![],
+        }
+    }
+
+    /// The boundary that separates the parts.
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// The `content-type` header for this form, including the boundary.
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    /// Adds a text field.
+    pub fn text(self, name: &str, value: &str) -> Self {
+        self.part(name, Part::text(value))
+    }
+
+    /// Adds a file part.
+    ///
+    /// The part has no file name and `content-type` `application/octet-stream` unless
+    /// set using [`part`] instead.
+    ///
+    /// [`part`]: struct.Multipart.html#method.part
+    pub fn file(self, name: &str, file: std::fs::File) -> Self {
+        self.part(name, Part::file(file))
+    }
+
+    /// Adds a file part by opening the file at the path.
+    ///
+    /// The file name and `content-type` are taken from the path.
+    pub fn file_path<P: AsRef<Path>>(self, name: &str, path: P) -> io::Result<Self> {
+        Ok(self.part(name, Part::file_path(path)?))
+    }
+
+    /// Adds a configured part.
+    pub fn part(mut self, name: &str, part: Part) -> Self {
+        self.parts.push((name.to_string(), part));
+        self
+    }
+
+    /// The total length of the body, if the length of every part is known.
+    pub fn content_length(&self) -> Option<u64> {
+        let mut total = 0;
+
+        for (name, part) in &self.parts {
+            total += self.part_head(name, part).len() as u64;
+            total += part.length?;
+            total += 2; // \r\n
+        }
+
+        Some(total + self.tail().len() as u64)
+    }
+
+    fn part_head(&self, name: &str, part: &Part) -> String {
+        let mut head = format!(
+            "--{}\r\ncontent-disposition: form-data; name=\"{}\"",
+            self.boundary,
+            escape(name)
+        );
+
+        if let Some(file_name) = &part.file_name {
+            head.push_str(&format!("; filename=\"{}\"", escape(file_name)));
+        }
+
+        head.push_str("\r\n");
+
+        if let Some(ctype) = &part.content_type {
+            head.push_str(&format!("content-type: {}\r\n", ctype));
+        }
+
+        head.push_str("\r\n");
+
+        head
+    }
+
+    fn tail(&self) -> String {
+        format!("--{}--\r\n", self.boundary)
+    }
+
+    /// Turn this form into a streaming body.
+    pub(crate) fn into_body(self) -> Body {
+        let ctype = self.content_type();
+        let length = self.content_length();
+
+        let mut sources = vec![];
+
+        let tail = self.tail();
+        let heads: Vec<_> = self
+            .parts
+            .iter()
+            .map(|(name, part)| self.part_head(name, part))
+            .collect();
+
+        for (head, (_, part)) in heads.into_iter().zip(self.parts) {
+            sources.push(Source::Bytes(head.into_bytes()));
+            sources.push(part.source);
+            sources.push(Source::Bytes(b"\r\n".to_vec()));
+        }
+
+        sources.push(Source::Bytes(tail.into_bytes()));
+
+        let reader = MultipartReader {
+            sources: sources.into_iter().map(Some).collect(),
+            index: 0,
+            pos: 0,
+        };
+
+        Body::from_async_read(reader, length).with_content_type(ctype)
+    }
+}
+
+impl Default for Multipart {
+    fn default() -> Self {
+        Multipart::new()
+    }
+}
+
+impl Part {
+    /// Creates a text part.
+    pub fn text(value: &str) -> Self {
+        Part::bytes(value.as_bytes().to_vec())
+    }
+
+    /// Creates a part from bytes.
+    pub fn bytes(bytes: Vec<u8>) -> Self {
+        Part {
+            length: Some(bytes.len() as u64),
+            source: Source::Bytes(bytes),
+            file_name: None,
+            content_type: None,
+        }
+    }
+
+    /// Creates a part from a file.
+    ///
+    /// The file is read as the body is sent.
+    pub fn file(file: std::fs::File) -> Self {
+        let length = file.metadata().ok().map(|m| m.len());
+        let reader = AsyncRuntime::file_to_reader(file);
+        Part::reader(reader, length).content_type("application/octet-stream")
+    }
+
+    /// Creates a part by opening a file at the path.
+    ///
+    /// The file name is set from the path and the `content-type` is guessed from the
+    /// file extension.
+    pub fn file_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)?;
+
+        let mut part = Part::file(file);
+
+        if let Some(name) = path.file_name() {
+            part = part.file_name(&name.to_string_lossy());
+        }
+
+        if let Some(mime) = mime_guess::from_path(path).first() {
+            part = part.content_type(mime.as_ref());
+        }
+
+        Ok(part)
+    }
+
+    /// Creates a part from anything implementing `AsyncRead`.
+    ///
+    /// The `length` is needed for the form to have a `content-length`.
+    pub fn reader<R>(reader: R, length: Option<u64>) -> Self
+    where
+        R: AsyncRead + Unpin + Send + Sync + 'static,
+    {
+        Part {
+            source: Source::Reader(Box::new(reader)),
+            length,
+            file_name: None,
+            content_type: None,
+        }
+    }
+
+    /// Sets the file name of the part.
+    pub fn file_name(mut self, file_name: &str) -> Self {
+        self.file_name = Some(file_name.to_string());
+        self
+    }
+
+    /// Sets the `content-type` of the part.
+    ///
+    /// A value that isn't a valid header value, such as one with line breaks, is
+    /// ignored.
+    pub fn content_type(mut self, content_type: &str) -> Self {
+        if http::HeaderValue::from_str(content_type).is_ok() {
+            self.content_type = Some(content_type.to_string());
+        } else {
+            warn!("Ignoring bad part content-type: {:?}", content_type);
+        }
+        self
+    }
+}
+
+impl fmt::Debug for Multipart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Multipart")
+            .field("boundary", &self.boundary)
+            .field("parts", &self.parts)
+            .finish()
+    }
+}
+
+impl fmt::Debug for Part {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Part")
+            .field("length", &self.length)
+            .field("file_name", &self.file_name)
+            .field("content_type", &self.content_type)
+            .finish()
+    }
+}
+
+/// Reads the sources of a form one after another.
+struct MultipartReader {
+    sources: Vec<Option<Source>>,
+    index: usize,
+    /// Read position in a `Source::Bytes`.
+    pos: usize,
+}
+
+impl AsyncRead for MultipartReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        while this.index < this.sources.len() {
+            let amount = match &mut this.sources[this.index] {
+                Some(Source::Bytes(bytes)) => {
+                    let left = &bytes[this.pos..];
+                    let amount = left.len().min(buf.len());
+                    buf[0..amount].copy_from_slice(&left[0..amount]);
+                    this.pos += amount;
+                    amount
+                }
+                Some(Source::Reader(reader)) => ready!(Pin::new(reader).poll_read(cx, buf))?,
+                None => 0,
+            };
+
+            if amount > 0 {
+                return Ok(amount).into();
+            }
+
+            // source is exhausted, drop it to release file handles early.
+            this.sources[this.index] = None;
+            this.index += 1;
+            this.pos = 0;
+        }
+
+        Ok(0).into()
+    }
+}
+
+/// Quotes are percent encoded and line breaks removed, like browsers do.
+fn escape(s: &str) -> String {
+    s.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn gen_boundary() -> String {
+    let a = random_u64();
+    let b = random_u64();
+    format!("hreq-boundary-{:016x}{:016x}", a, b)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read(form: Multipart) -> (Option<u64>, String) {
+        let mut body = form.into_body();
+        body.set_codec_pass();
+        let length = body.content_encoded_length();
+        let s = AsyncRuntime::block_on(body.read_to_string()).unwrap();
+        (length, s)
+    }
+
+    #[test]
+    fn text_and_bytes() {
+        let mut form = Multipart::new().text("name", "Martin").part(
+            "file",
+            Part::bytes(b"abc".to_vec())
+                .file_name("a \"b\".txt")
+                .content_type("text/plain"),
+        );
+        form.boundary = "XyZ".into();
+
+        let (length, s) = read(form);
+
+        let expected = "--XyZ\r\n\
+            content-disposition: form-data; name=\"name\"\r\n\r\n\
+            Martin\r\n\
+            --XyZ\r\n\
+            content-disposition: form-data; name=\"file\"; filename=\"a %22b%22.txt\"\r\n\
+            content-type: text/plain\r\n\r\n\
+            abc\r\n\
+            --XyZ--\r\n";
+
+        assert_eq!(s, expected);
+        assert_eq!(length, Some(expected.len() as u64));
+    }
+
+    #[test]
+    fn unknown_length() {
+        let form = Multipart::new()
+            .text("a", "b")
+            .part("c", Part::reader(&b"data"[..], None));
+
+        assert_eq!(form.content_length(), None);
+
+        let (length, s) = read(form);
+        assert_eq!(length, None);
+        assert!(s.contains("\r\n\r\ndata\r\n"));
+    }
+
+    #[test]
+    fn bad_content_type() {
+        let mut form = Multipart::new().part(
+            "a",
+            Part::text("b").content_type("text/plain\r\n\r\n--XyZ\r\nx: y"),
+        );
+        form.boundary = "XyZ".into();
+
+        let (_, s) = read(form);
+
+        let expected = "--XyZ\r\n\
+            content-disposition: form-data; name=\"a\"\r\n\r\n\
+            b\r\n\
+            --XyZ--\r\n";
+
+        assert_eq!(s, expected);
+    }
+
+    #[test]
+    fn unique_boundary() {
+        assert_ne!(Multipart::new().boundary(), Multipart::new().boundary());
+    }
+}
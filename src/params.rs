@@ -1,3 +1,8 @@
+use crate::client::Proxy;
+use crate::client::RedirectPolicy;
+use crate::client::RetryPolicy;
+#[cfg(feature = "tls")]
+use crate::client::TlsConfig;
 use crate::deadline::Deadline;
 use crate::head_ext::HeaderMapExt;
 use crate::uri_ext::HostPort;
@@ -24,7 +29,14 @@ pub(crate) struct HReqParams {
     pub redirect_body_buffer: usize,
     pub with_override: Option<Arc<HostPort>>,
     pub tls_disable_verify: bool,
+    #[cfg(feature = "tls")]
+    pub tls: Option<Arc<TlsConfig>>,
     pub prebuffer: bool,
+    pub proxy: Option<Arc<Proxy>>,
+    pub retry: Option<Arc<RetryPolicy>>,
+    pub redirect: Option<Arc<RedirectPolicy>>,
+    pub expect_continue: Option<u64>,
+    pub expect_continue_timeout: Duration,
 }
 
 #[derive(Clone, Debug)]
@@ -93,6 +105,37 @@ impl CharsetConfig {
 
         Some((s_enc, t_enc))
     }
+
+    /// Resolve the charset of the values in an `application/x-www-form-urlencoded` body.
+    ///
+    /// The body itself is ascii, but the percent encoded bytes are in this charset.
+    /// if `is_incoming`, this is the source encoding, otherwise the target.
+    pub fn resolve_form(
+        &self,
+        is_incoming: bool,
+        headers: &http::header::HeaderMap,
+    ) -> &'static Encoding {
+        let charset = if is_incoming {
+            &self.source
+        } else {
+            &self.target
+        };
+
+        if charset.is_off() {
+            return encoding_rs::UTF_8;
+        }
+
+        let header_charset = headers
+            .get_str("content-type")
+            .filter(|s| s.starts_with("application/x-www-form-urlencoded"))
+            .and_then(|s| s.split(';').nth(1))
+            .and_then(|s| s.split('=').nth(1))
+            .map(|s| s.trim().as_bytes())
+            .and_then(Encoding::for_label)
+            .unwrap_or(encoding_rs::UTF_8);
+
+        charset.resolve(header_charset)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -147,7 +190,14 @@ impl HReqParams {
             redirect_body_buffer: 0,
             with_override: None,
             tls_disable_verify: false,
+            #[cfg(feature = "tls")]
+            tls: None,
             prebuffer: true,
+            proxy: None,
+            retry: None,
+            redirect: None,
+            expect_continue: None,
+            expect_continue_timeout: Duration::from_secs(1),
         }
     }
 
@@ -161,6 +211,16 @@ impl HReqParams {
         Deadline::new(self.req_start, self.timeout)
     }
 
+    /// Whether to send `expect: 100-continue` for a body of the given length.
+    /// Bodies of unknown length are considered above any threshold.
+    pub fn use_expect_continue(&self, body_length: Option<u64>) -> bool {
+        match (self.expect_continue, body_length) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(threshold), Some(len)) => len > threshold,
+        }
+    }
+
     #[cfg(feature = "server")]
     pub fn copy_from_request(&mut self, req_params: &HReqParams) {
         self.req_start = req_params.req_start;
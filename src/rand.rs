@@ -0,0 +1,13 @@
+//! Random numbers from the operating system.
+
+/// Fill `buf` with random bytes.
+pub(crate) fn fill(buf: &mut [u8]) {
+    getrandom::getrandom(buf).expect("OS random number generator");
+}
+
+/// A random `u64`.
+pub(crate) fn random_u64() -> u64 {
+    let mut b = [0; 8];
+    fill(&mut b);
+    u64::from_be_bytes(b)
+}
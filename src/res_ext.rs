@@ -1,6 +1,9 @@
+use crate::client::{CacheStatus, RedirectHop, ResponseMeta};
 use crate::head_ext::HeaderMapExt;
-use http::Response;
+use http::{Response, Uri};
+use std::net::SocketAddr;
 use std::str::FromStr;
+use std::time::Duration;
 
 /// Extends [`http::request::Response`] with ergonomic extras for hreq.
 ///
@@ -61,6 +64,243 @@ pub trait ResponseExt {
     /// assert_eq!(res.status().as_u16(), 200);
     /// ```
     fn status_code(&self) -> u16;
+
+    /// Time spent resolving the host name to addresses.
+    ///
+    /// The connection phase timings (`dns_time`, `connect_time` and `tls_time`) are only
+    /// available for requests that opened a new connection. They are `None` for a
+    /// connection reused from the pool, and for responses not received over a
+    /// connection, such as ones made up by middleware.
+    ///
+    /// When connecting via a proxy, this is the time to resolve the proxy.
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    ///
+    /// let res = Request::get("https://my-api")
+    ///     .call().block().unwrap();
+    ///
+    /// println!("DNS: {:?}", res.dns_time());
+    /// ```
+    fn dns_time(&self) -> Option<Duration>;
+
+    /// Time spent opening the TCP connection, including any proxy handshake.
+    ///
+    /// See [`dns_time`](#tymethod.dns_time) for when this is available.
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    ///
+    /// let res = Request::get("https://my-api")
+    ///     .call().block().unwrap();
+    ///
+    /// println!("Connect: {:?}", res.connect_time());
+    /// ```
+    fn connect_time(&self) -> Option<Duration>;
+
+    /// Time spent in the TLS handshake. `None` for plain `http`.
+    ///
+    /// See [`dns_time`](#tymethod.dns_time) for when this is available.
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    ///
+    /// let res = Request::get("https://my-api")
+    ///     .call().block().unwrap();
+    ///
+    /// println!("TLS: {:?}", res.tls_time());
+    /// ```
+    fn tls_time(&self) -> Option<Duration>;
+
+    /// Time from starting to send the request until the response head arrived.
+    ///
+    /// This includes sending the request body. For redirects and retries, this is the
+    /// time of the last request.
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    ///
+    /// let res = Request::get("https://my-api")
+    ///     .call().block().unwrap();
+    ///
+    /// println!("Time to first byte: {:?}", res.first_byte_time());
+    /// ```
+    fn first_byte_time(&self) -> Option<Duration>;
+
+    /// Time from the start of the request until the response head arrived.
+    ///
+    /// This covers all phases, including retries and redirects, but not reading
+    /// the response body.
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    ///
+    /// let res = Request::get("https://my-api")
+    ///     .call().block().unwrap();
+    ///
+    /// println!("Total: {:?}", res.total_time());
+    /// ```
+    fn total_time(&self) -> Option<Duration>;
+
+    /// Whether the request was sent over a connection reused from the pool.
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    ///
+    /// let res = Request::get("https://my-api")
+    ///     .call().block().unwrap();
+    ///
+    /// assert!(!res.is_connection_reused());
+    /// ```
+    fn is_connection_reused(&self) -> bool;
+
+    /// The HTTP version spoken on the connection.
+    ///
+    /// This is the version negotiated for the connection, which might differ from the
+    /// version the server states in the response, such as a `HTTP/1.0` reply on a
+    /// `HTTP/1.1` connection.
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    ///
+    /// let res = Request::get("https://my-api")
+    ///     .call().block().unwrap();
+    ///
+    /// assert_eq!(res.connection_version(), Some(http::Version::HTTP_2));
+    /// ```
+    fn connection_version(&self) -> Option<http::Version>;
+
+    /// The protocol negotiated with ALPN during the TLS handshake, such as `"h2"`.
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    ///
+    /// let res = Request::get("https://my-api")
+    ///     .call().block().unwrap();
+    ///
+    /// assert_eq!(res.alpn_protocol(), Some("h2"));
+    /// ```
+    fn alpn_protocol(&self) -> Option<&str>;
+
+    /// The local address of the connection.
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    ///
+    /// let res = Request::get("https://my-api")
+    ///     .call().block().unwrap();
+    ///
+    /// println!("From: {:?}", res.local_addr());
+    /// ```
+    fn local_addr(&self) -> Option<SocketAddr>;
+
+    /// The remote address of the connection. When connecting via a proxy, this is the
+    /// address of the proxy.
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    ///
+    /// let res = Request::get("https://my-api")
+    ///     .call().block().unwrap();
+    ///
+    /// println!("To: {:?}", res.remote_addr());
+    /// ```
+    fn remote_addr(&self) -> Option<SocketAddr>;
+
+    /// The negotiated TLS protocol version, such as `"TLSv1_3"`.
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    ///
+    /// let res = Request::get("https://my-api")
+    ///     .call().block().unwrap();
+    ///
+    /// assert_eq!(res.tls_protocol(), Some("TLSv1_3"));
+    /// ```
+    fn tls_protocol(&self) -> Option<&str>;
+
+    /// The negotiated TLS cipher suite, such as `"TLS13_AES_256_GCM_SHA384"`.
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    ///
+    /// let res = Request::get("https://my-api")
+    ///     .call().block().unwrap();
+    ///
+    /// println!("Cipher: {:?}", res.tls_cipher());
+    /// ```
+    fn tls_cipher(&self) -> Option<&str>;
+
+    /// Number of times the request was retried, because of errors or response statuses.
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    ///
+    /// let res = Request::get("https://my-api")
+    ///     .call().block().unwrap();
+    ///
+    /// assert_eq!(res.retries(), 0);
+    /// ```
+    fn retries(&self) -> usize;
+
+    /// Number of redirects followed to get to this response.
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    ///
+    /// let res = Request::get("https://my-api")
+    ///     .call().block().unwrap();
+    ///
+    /// assert_eq!(res.redirects(), 0);
+    /// ```
+    fn redirects(&self) -> usize;
+
+    /// The redirects followed to get to this response, in order.
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    ///
+    /// let res = Request::get("http://my-api/old")
+    ///     .call().block().unwrap();
+    ///
+    /// for hop in res.redirect_history() {
+    ///     println!("{} {} -> {}", hop.status(), hop.from(), hop.to());
+    /// }
+    /// ```
+    fn redirect_history(&self) -> &[RedirectHop];
+
+    /// The URI of the request that got this response, after following redirects.
+    ///
+    /// `None` for responses not from a server, such as those made up by middleware.
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    ///
+    /// let res = Request::get("http://my-api/old")
+    ///     .call().block().unwrap();
+    ///
+    /// println!("Final: {:?}", res.final_uri());
+    /// ```
+    fn final_uri(&self) -> Option<&Uri>;
+
+    /// Whether the response came from the [`Agent`] cache. `None` when the agent has
+    /// no cache.
+    ///
+    /// ```no_run
+    /// use hreq::prelude::*;
+    /// use hreq::{Agent, CacheStatus, MemoryCache};
+    ///
+    /// let mut agent = Agent::new();
+    /// agent.cache(MemoryCache::default());
+    ///
+    /// let req = Request::get("https://my-api").body(()).unwrap();
+    /// let res = agent.send(req).block().unwrap();
+    ///
+    /// assert_eq!(res.cache_status(), Some(CacheStatus::Miss));
+    /// ```
+    ///
+    /// [`Agent`]: struct.Agent.html
+    fn cache_status(&self) -> Option<CacheStatus>;
 }
 
 impl<B> ResponseExt for Response<B> {
@@ -75,4 +315,76 @@ impl<B> ResponseExt for Response<B> {
     fn status_code(&self) -> u16 {
         self.status().as_u16()
     }
+
+    fn dns_time(&self) -> Option<Duration> {
+        meta(self).and_then(|m| m.dns())
+    }
+
+    fn connect_time(&self) -> Option<Duration> {
+        meta(self).and_then(|m| m.connect())
+    }
+
+    fn tls_time(&self) -> Option<Duration> {
+        meta(self).and_then(|m| m.tls())
+    }
+
+    fn first_byte_time(&self) -> Option<Duration> {
+        meta(self).map(|m| m.first_byte)
+    }
+
+    fn total_time(&self) -> Option<Duration> {
+        meta(self).and_then(|m| m.total)
+    }
+
+    fn is_connection_reused(&self) -> bool {
+        meta(self).map(|m| m.reused).unwrap_or(false)
+    }
+
+    fn connection_version(&self) -> Option<http::Version> {
+        meta(self).and_then(|m| m.conn.version)
+    }
+
+    fn alpn_protocol(&self) -> Option<&str> {
+        meta(self).and_then(|m| m.conn.alpn.as_deref())
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        meta(self).and_then(|m| m.conn.local_addr)
+    }
+
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        meta(self).and_then(|m| m.conn.remote_addr)
+    }
+
+    fn tls_protocol(&self) -> Option<&str> {
+        meta(self).and_then(|m| m.conn.tls_protocol.as_deref())
+    }
+
+    fn tls_cipher(&self) -> Option<&str> {
+        meta(self).and_then(|m| m.conn.tls_cipher.as_deref())
+    }
+
+    fn retries(&self) -> usize {
+        meta(self).map(|m| m.retries).unwrap_or(0)
+    }
+
+    fn redirects(&self) -> usize {
+        meta(self).map(|m| m.redirects).unwrap_or(0)
+    }
+
+    fn redirect_history(&self) -> &[RedirectHop] {
+        meta(self).map(|m| &m.history[..]).unwrap_or(&[])
+    }
+
+    fn final_uri(&self) -> Option<&Uri> {
+        meta(self).and_then(|m| m.uri.as_ref())
+    }
+
+    fn cache_status(&self) -> Option<CacheStatus> {
+        self.extensions().get::<CacheStatus>().copied()
+    }
+}
+
+fn meta<B>(res: &Response<B>) -> Option<&ResponseMeta> {
+    res.extensions().get::<ResponseMeta>()
 }
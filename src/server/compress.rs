@@ -0,0 +1,305 @@
+//! Response compression middleware.
+
+use super::Next;
+use super::Reply;
+use crate::body_codec::ENCODINGS;
+use crate::head_ext::HeaderMapExt;
+use crate::params::HReqParams;
+use crate::server::Middleware;
+use crate::Body;
+use http::{Method, Request, Response};
+use std::future::Future;
+use std::pin::Pin;
+
+/// Content types compressed by default. Entries ending with `/` are prefixes.
+const DEFAULT_CONTENT_TYPES: &[&str] = &[
+    "text/",
+    "application/json",
+    "application/javascript",
+    "application/xml",
+    "application/xhtml+xml",
+    "application/wasm",
+    "application/x-www-form-urlencoded",
+    "image/svg+xml",
+];
+
+/// Middleware that compresses responses according to the request `accept-encoding`.
+///
+/// The encoding is negotiated from the `accept-encoding` q-values among the compiled
+/// in encodings (see the crate features `gzip`, `brotli`, `deflate` and `zstd`).
+///
+/// * Responses smaller than [`min_size`] are not compressed.
+/// * Only the [`content_types`] in the allow-list are compressed. The default list
+///   holds text formats, which means images and archives are left alone.
+/// * Responses that already have a `content-encoding` are left alone.
+/// * `HEAD` requests and `206`, `204` and `304` responses are not compressed.
+/// * Compressible responses get a `vary: accept-encoding` header.
+///
+/// # Example
+///
+/// ```no_run
+/// use hreq::prelude::*;
+/// use hreq::server::Compress;
+///
+/// async fn start_server() {
+///    let mut server = Server::new();
+///
+///    server.at("/*any")
+///        .middleware(Compress::new().min_size(512))
+///        .get(|_req| async { "Hello there" });
+///
+///    let (handle, addr) = server.listen(3000).await.unwrap();
+///
+///    handle.keep_alive().await;
+/// }
+/// ```
+///
+/// [`min_size`]: struct.Compress.html#method.min_size
+/// [`content_types`]: struct.Compress.html#method.content_types
+#[derive(Debug, Clone)]
+pub struct Compress {
+    min_size: u64,
+    content_types: Vec<String>,
+}
+
+impl Compress {
+    /// Creates a compression middleware with default settings.
+    ///
+    /// * Minimum size: 1024 bytes.
+    /// * Content types: `text/*`, JSON, JavaScript, XML, SVG, WASM and forms.
+    pub fn new() -> Self {
+        Compress {
+            min_size: 1024,
+            content_types: DEFAULT_CONTENT_TYPES
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+
+    /// Minimum size of the response body to compress.
+    ///
+    /// Bodies of unknown size, such as streamed from a reader, are always compressed.
+    pub fn min_size(mut self, size: u64) -> Self {
+        self.min_size = size;
+        self
+    }
+
+    /// Replace the content types to compress.
+    ///
+    /// An entry ending with `/`, like `text/`, matches all subtypes. Parameters such
+    /// as `; charset=utf-8` are ignored when matching.
+    ///
+    /// ```
+    /// use hreq::server::Compress;
+    ///
+    /// let compress = Compress::new()
+    ///     .content_types(&["text/", "application/json", "application/vnd.my-format"]);
+    /// ```
+    pub fn content_types(mut self, types: &[&str]) -> Self {
+        self.content_types = types.iter().map(|s| s.to_ascii_lowercase()).collect();
+        self
+    }
+
+    fn is_allowed_type(&self, ctype: &str) -> bool {
+        let ctype = ctype
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+
+        self.content_types.iter().any(|t| {
+            if t.ends_with('/') {
+                ctype.starts_with(t.as_str())
+            } else {
+                ctype == *t
+            }
+        })
+    }
+
+    /// Whether the response is something we would compress, given an accepting client.
+    fn is_compressible(&self, res: &Response<Body>) -> bool {
+        let status = res.status().as_u16();
+        if status < 200 || status == 204 || status == 206 || status == 304 {
+            return false;
+        }
+
+        if res.headers().contains_key("content-encoding") {
+            return false;
+        }
+
+        let content_encode = res
+            .extensions()
+            .get::<HReqParams>()
+            .map(|p| p.content_encode)
+            .unwrap_or(true);
+
+        if !content_encode {
+            return false;
+        }
+
+        let ctype = res
+            .headers()
+            .get_str("content-type")
+            .or_else(|| res.body().content_type());
+
+        if !ctype.map(|c| self.is_allowed_type(c)).unwrap_or(false) {
+            return false;
+        }
+
+        let length = res
+            .headers()
+            .get_as::<u64>("content-length")
+            .or_else(|| res.body().content_encoded_length());
+
+        length.map(|l| l >= self.min_size).unwrap_or(true)
+    }
+
+    async fn handle(&self, req: Request<Body>, next: Next) -> Reply {
+        let is_head = req.method() == Method::HEAD;
+
+        let encoding = req.headers().get_str("accept-encoding").and_then(negotiate);
+
+        let mut res = match next.run(req).await {
+            Ok(v) => v,
+            Err(e) => return Err::<Response<Body>, _>(e).into(),
+        };
+
+        if is_head || !self.is_compressible(&res) {
+            return res.into();
+        }
+
+        add_vary(res.headers_mut());
+
+        if let Some(encoding) = encoding {
+            trace!("Compress response with: {}", encoding);
+            res.headers_mut().set("content-encoding", encoding);
+        }
+
+        res.into()
+    }
+}
+
+impl Default for Compress {
+    fn default() -> Self {
+        Compress::new()
+    }
+}
+
+impl Middleware for Compress {
+    fn call<'a>(
+        &'a self,
+        req: Request<Body>,
+        next: Next,
+    ) -> Pin<Box<dyn Future<Output = Reply> + Send + 'a>> {
+        Box::pin(self.handle(req, next))
+    }
+}
+
+/// Add `accept-encoding` to the `vary` header, unless already there.
+fn add_vary(headers: &mut http::HeaderMap) {
+    let already = headers
+        .get_all("vary")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim())
+        .any(|v| v == "*" || v.eq_ignore_ascii_case("accept-encoding"));
+
+    if !already {
+        headers.append("vary", http::HeaderValue::from_static("accept-encoding"));
+    }
+}
+
+/// Pick the encoding to use from an `accept-encoding` header.
+///
+/// The highest q-value wins. Ties are resolved by our own order of preference.
+fn negotiate(accept: &str) -> Option<&'static str> {
+    let mut wildcard = None;
+    let mut explicit = vec![];
+
+    for item in accept.split(',') {
+        let mut parts = item.split(';');
+        let coding = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+
+        if coding.is_empty() {
+            continue;
+        }
+
+        let q = parts
+            .filter_map(|p| {
+                let p = p.trim();
+                if p.starts_with("q=") || p.starts_with("Q=") {
+                    p[2..].trim().parse::<f32>().ok()
+                } else {
+                    None
+                }
+            })
+            .next()
+            .unwrap_or(1.0);
+
+        if coding == "*" {
+            wildcard = Some(q);
+        } else {
+            explicit.push((coding, q));
+        }
+    }
+
+    let mut best: Option<(&'static str, f32)> = None;
+
+    for enc in ENCODINGS {
+        let q = explicit
+            .iter()
+            .find(|(c, _)| c == enc)
+            .map(|(_, q)| *q)
+            .or(wildcard)
+            .unwrap_or(0.0);
+
+        if q > 0.0 && best.map(|(_, b)| q > b).unwrap_or(true) {
+            best = Some((enc, q));
+        }
+    }
+
+    best.map(|(enc, _)| enc)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn negotiate_qvalues() {
+        assert_eq!(negotiate(""), None);
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(negotiate("magic"), None);
+
+        #[cfg(feature = "gzip")]
+        {
+            assert_eq!(negotiate("gzip"), Some("gzip"));
+            assert_eq!(negotiate("GZIP;q=0.5, magic"), Some("gzip"));
+            assert_eq!(negotiate("gzip;q=0, *;q=0"), None);
+        }
+
+        #[cfg(all(feature = "gzip", feature = "brotli"))]
+        {
+            assert_eq!(negotiate("gzip, br"), Some("br"));
+            assert_eq!(negotiate("gzip;q=1.0, br;q=0.8"), Some("gzip"));
+            assert_eq!(negotiate("*"), Some("br"));
+            assert_eq!(negotiate("br;q=0, *"), Some(ENCODINGS[1]));
+        }
+    }
+
+    #[test]
+    fn allowed_types() {
+        let c = Compress::new();
+        assert!(c.is_allowed_type("text/html; charset=utf-8"));
+        assert!(c.is_allowed_type("application/json"));
+        assert!(!c.is_allowed_type("image/png"));
+        assert!(!c.is_allowed_type("application/zip"));
+
+        let c = Compress::new().content_types(&["image/"]);
+        assert!(c.is_allowed_type("image/png"));
+        assert!(!c.is_allowed_type("text/html"));
+    }
+}
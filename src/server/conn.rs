@@ -1,15 +1,18 @@
 use crate::body::Body;
 use crate::body_codec::BodyImpl;
-use crate::body_send::BodySender;
+use crate::body_send::BodySend;
 use crate::bw::BandwidthMonitor;
 use crate::head_ext::HeaderMapExt;
 use crate::params::HReqParams;
+use crate::server::expect::Continue;
+use crate::server::upgrade::{OnUpgrade, Upgrade};
 use crate::uninit::UninitBuf;
 use crate::Error;
 use crate::AGENT_IDENT;
 use crate::{AsyncRead, AsyncWrite};
 use bytes::Bytes;
 use futures_util::future::poll_fn;
+use futures_util::io::AsyncWriteExt;
 use h2::server::Connection as H2Connection;
 use h2::server::SendResponse as H2SendResponse;
 use hreq_h1::server::Connection as H1Connection;
@@ -27,6 +30,8 @@ const MAX_BUF_SIZE: usize = 2 * 1024 * 1024;
 pub(crate) struct Connection<Stream> {
     inner: Inner<Stream>,
     bw: Option<BandwidthMonitor>,
+    cont: Option<Continue>,
+    upgrade: Option<Upgrade>,
 }
 
 enum Inner<Stream> {
@@ -38,10 +43,12 @@ impl<Stream> Connection<Stream>
 where
     Stream: AsyncRead + AsyncWrite + Unpin,
 {
-    pub fn new_h1(conn: H1Connection<Stream>) -> Self {
+    pub fn new_h1(conn: H1Connection<Stream>, cont: Continue, upgrade: Upgrade) -> Self {
         Connection {
             inner: Inner::H1(conn),
             bw: None,
+            cont: Some(cont),
+            upgrade: Some(upgrade),
         }
     }
 
@@ -49,6 +56,8 @@ where
         Connection {
             inner: Inner::H2(conn),
             bw: Some(bw),
+            cont: None,
+            upgrade: None,
         }
     }
 
@@ -68,10 +77,38 @@ where
                         Ok(v) => {
                             let (req, send) = v;
 
-                            let (parts, recv) = req.into_parts();
+                            let (mut parts, recv) = req.into_parts();
+
+                            absolute_form_uri(&mut parts);
+
+                            let mut body = Body::new(BodyImpl::Http1(recv), None, false);
+
+                            let expects_continue = parts.version == http::Version::HTTP_11
+                                && parts
+                                    .headers
+                                    .get_str("expect")
+                                    .map(|v| v.eq_ignore_ascii_case("100-continue"))
+                                    .unwrap_or(false);
 
-                            let body = Body::new(BodyImpl::Http1(recv), None, false);
-                            let send = SendResponse::H1(send);
+                            let cont = self.cont.clone().expect("h1 continue");
+
+                            let is_chunked = parts
+                                .headers
+                                .get_str("transfer-encoding")
+                                .map(|v| v.contains("chunked"))
+                                .unwrap_or(false);
+                            let has_body = is_chunked
+                                || parts.headers.get_as::<u64>("content-length").unwrap_or(0) > 0;
+
+                            if expects_continue && has_body {
+                                // the client waits for 100 Continue, which we send once
+                                // the handler starts reading the body.
+                                cont.arm();
+                                let cont = cont.clone();
+                                body.set_on_first_read(move || cont.request());
+                            }
+                            let upgrade = self.upgrade.clone().expect("h1 upgrade");
+                            let send = SendResponse::H1(send, upgrade, cont);
 
                             return Some(Ok(Self::configure(
                                 parts,
@@ -152,8 +189,24 @@ where
     }
 }
 
+/// The http1.1 layer gives us the request target as a path. A request in absolute-form,
+/// such as requests sent to a proxy, `GET http://host/path HTTP/1.1`, is turned into
+/// a proper absolute URI.
+fn absolute_form_uri(parts: &mut http::request::Parts) {
+    let target = match parts.uri.path_and_query() {
+        Some(pq) if !pq.as_str().starts_with('/') => pq.as_str(),
+        _ => return,
+    };
+
+    if let Ok(uri) = target.parse::<http::Uri>() {
+        if uri.scheme().is_some() && uri.authority().is_some() {
+            parts.uri = uri;
+        }
+    }
+}
+
 pub(crate) enum SendResponse {
-    H1(H1SendResponse),
+    H1(H1SendResponse, Upgrade, Continue),
     H2(H2SendResponse<Bytes>),
 }
 
@@ -191,8 +244,26 @@ impl SendResponse {
         // merge parameters together
         params.copy_from_request(&req_params);
 
+        // a WebSocket handler waiting for the upgrade, unless the response was
+        // changed to something else by middleware.
+        let upgrade_status = if self.is_http2() { 200 } else { 101 };
+        let on_upgrade = res
+            .extensions_mut()
+            .remove::<OnUpgrade>()
+            .filter(|_| res.status() == upgrade_status);
+
         let (mut parts, mut body) = res.into_parts();
 
+        if let Some(on_upgrade) = on_upgrade {
+            configure_response(&mut parts, &body, self.is_http2());
+            // the connection carries the WebSocket, not a body.
+            parts.headers.remove("content-length");
+            parts.headers.remove("transfer-encoding");
+
+            let res = http::Response::from_parts(parts, ());
+            return self.upgrade(res, on_upgrade).await;
+        }
+
         body.configure(&params, &parts.headers, false);
 
         // for small response bodies, we try to fully buffer the data.
@@ -202,6 +273,12 @@ impl SendResponse {
 
         configure_response(&mut parts, &body, self.is_http2());
 
+        if let SendResponse::H1(_, _, cont) = &self {
+            if cont.reject() {
+                return self.send_closing(parts, body).await;
+            }
+        }
+
         let res = http::Response::from_parts(parts, ());
         let mut body_send = self.do_send(res).await?;
 
@@ -212,7 +289,15 @@ impl SendResponse {
             loop {
                 buf.clear();
 
-                let amount_read = buf.read_from_async(&mut body).await?;
+                let amount_read = match buf.read_from_async(&mut body).await {
+                    Ok(n) => n,
+                    Err(e) => {
+                        // the client can't tell a cut short body from a complete one.
+                        trace!("Body read failed, abort sending: {}", e);
+                        body_send.abort();
+                        return Err(e.into());
+                    }
+                };
 
                 // Ship it to they underlying http1.1/http2 layer.
                 body_send.send_data(&buf[0..amount_read]).await?;
@@ -223,20 +308,115 @@ impl SendResponse {
             }
         }
 
-        body_send.send_end().await?;
+        body_send.send_end(body.take_trailers()).await?;
+
+        Ok(())
+    }
+
+    async fn upgrade(self, res: http::Response<()>, on_upgrade: OnUpgrade) -> Result<(), Error> {
+        match self {
+            SendResponse::H1(send, upgrade, _) => {
+                // hreq-h1 drives the connection in send_response until nothing is left
+                // pending to write, so the 101 (no body) is handed to the stream when
+                // this returns. the http1.1 layer waits for the send stream, so it
+                // can't read past the response before the stream is taken.
+                let send_body = send.send_response(res, true).await?;
+                let mut stream = upgrade
+                    .take()
+                    .ok_or_else(|| Error::Proto("Connection already upgraded".into()))?;
+                drop(send_body);
+
+                // make sure the 101 is out before the handler takes over.
+                stream.flush().await?;
+
+                on_upgrade.run_h1(stream);
+            }
+            SendResponse::H2(mut send) => {
+                let send_body = send.send_response(res, false)?;
+
+                on_upgrade.run_h2(send_body)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Respond to a request whose body is held back for a `100 Continue` that will
+    /// never be sent. The http1.1 layer would wait for the body, so the response is
+    /// written directly on the connection, which is then closed.
+    async fn send_closing(
+        self,
+        mut parts: http::response::Parts,
+        mut body: Body,
+    ) -> Result<(), Error> {
+        let upgrade = match self {
+            SendResponse::H1(_, upgrade, _) => upgrade,
+            SendResponse::H2(_) => unreachable!("send_closing for http2"),
+        };
+
+        let mut stream = upgrade
+            .take()
+            .ok_or_else(|| Error::Proto("Connection already upgraded".into()))?;
+
+        parts.headers.set("connection", "close");
+
+        let is_chunked = parts
+            .headers
+            .get_str("transfer-encoding")
+            .map(|v| v.contains("chunked"))
+            .unwrap_or(false);
+
+        let status = parts.status;
+        let reason = status.canonical_reason().unwrap_or("");
+        let mut head = format!("HTTP/1.1 {} {}\r\n", status.as_str(), reason).into_bytes();
+        for (name, value) in parts.headers.iter() {
+            head.extend_from_slice(name.as_str().as_bytes());
+            head.extend_from_slice(b": ");
+            head.extend_from_slice(value.as_bytes());
+            head.extend_from_slice(b"\r\n");
+        }
+        head.extend_from_slice(b"\r\n");
+
+        stream.write_all(&head).await?;
+
+        let mut buf = UninitBuf::with_capacity(START_BUF_SIZE, MAX_BUF_SIZE);
+
+        if !body.is_definitely_no_body() {
+            loop {
+                buf.clear();
+
+                let amount_read = buf.read_from_async(&mut body).await?;
+
+                if is_chunked {
+                    let size = format!("{:x}\r\n", amount_read);
+                    stream.write_all(size.as_bytes()).await?;
+                    stream.write_all(&buf[0..amount_read]).await?;
+                    stream.write_all(b"\r\n").await?;
+                } else {
+                    stream.write_all(&buf[0..amount_read]).await?;
+                }
+
+                if amount_read == 0 {
+                    break;
+                }
+            }
+        }
+
+        stream.flush().await?;
+        stream.close().await?;
 
         Ok(())
     }
 
-    async fn do_send(self, res: http::Response<()>) -> Result<BodySender, Error> {
+    async fn do_send(self, res: http::Response<()>) -> Result<BodySend, Error> {
         Ok(match self {
-            SendResponse::H1(send) => {
+            SendResponse::H1(send, _, _) => {
                 let send_body = send.send_response(res, false).await?;
-                BodySender::H1(send_body)
+                BodySend::H1(send_body)
             }
             SendResponse::H2(mut send) => {
                 let send_body = send.send_response(res, false)?;
-                BodySender::H2(send_body)
+                BodySend::H2(send_body)
             }
         })
     }
@@ -248,7 +428,7 @@ impl SendResponse {
 
         let mut body_send = self.do_send(res).await?;
 
-        body_send.send_end().await?;
+        body_send.send_end(None).await?;
 
         Ok(())
     }
@@ -266,19 +446,28 @@ pub(crate) fn configure_response(parts: &mut http::response::Parts, body: &Body,
     // guiding cache updates (e.g., Last-Modified might be useful if the
     // response does not have an ETag field).
     if !is304 {
+        // a user set content-length is for the uncompressed body.
+        let is_compressed = body.has_content_codec();
+
         if let Some(len) = body.content_encoded_length() {
             // the body indicates a length (for sure).
-            let user_set_length = parts.headers.get("content-length").is_some();
+            let user_set_length = parts.headers.get("content-length").is_some() && !is_compressed;
 
             if !user_set_length && (len > 0 || !parts.status.is_redirection()) {
                 parts.headers.set("content-length", len.to_string());
             }
-        } else if !is_http2 && !parts.status.is_redirection() {
-            // body does not indicate a length (like from a reader),
-            // and status indicates there really is one.
-            // we chose chunked.
-            if parts.headers.get("transfer-encoding").is_none() {
-                parts.headers.set("transfer-encoding", "chunked");
+        } else {
+            if is_compressed && parts.headers.remove("content-length").is_some() {
+                trace!("Remove content-length for compressed body");
+            }
+
+            if !is_http2 && !parts.status.is_redirection() {
+                // body does not indicate a length (like from a reader),
+                // and status indicates there really is one.
+                // we chose chunked.
+                if parts.headers.get("transfer-encoding").is_none() {
+                    parts.headers.set("transfer-encoding", "chunked");
+                }
             }
         }
 
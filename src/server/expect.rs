@@ -0,0 +1,168 @@
+//! Server side of `Expect: 100-continue`.
+//!
+//! A client sending `Expect: 100-continue` holds back the request body until the
+//! server asks for it. We send `100 Continue` when the handler starts reading the
+//! body, which means a handler that responds without reading the body (like a
+//! 401 or 413) saves the client from uploading it.
+//!
+//! The http1.1 layer will not send a response body until the request body is
+//! complete. When a response starts without the client having been asked for the
+//! body, the body will never arrive. Such a response is written directly on the
+//! connection with `connection: close`, after which the connection is closed
+//! (RFC 9110 section 10.1.1).
+
+use crate::{AsyncRead, AsyncWrite};
+use futures_util::ready;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+const CONTINUE: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n";
+
+/// Handle shared between a connection and its `ContinueStream`.
+#[derive(Clone, Default)]
+pub(crate) struct Continue(Arc<Mutex<State>>);
+
+#[derive(Default)]
+struct State {
+    /// The current request expects 100-continue, and no response is sent yet.
+    armed: bool,
+    /// The handler started reading the body.
+    requested: bool,
+    /// Whether the client started sending the body without waiting.
+    received: bool,
+}
+
+impl Continue {
+    /// The current request was received with `expect: 100-continue`.
+    pub fn arm(&self) {
+        let mut state = self.0.lock().unwrap();
+        *state = State {
+            armed: true,
+            ..Default::default()
+        };
+    }
+
+    /// The body is about to be read. Sends the `100 Continue` if the request
+    /// is armed.
+    pub fn request(&self) {
+        let mut state = self.0.lock().unwrap();
+        if state.armed {
+            state.requested = true;
+        }
+    }
+
+    /// A response is about to start. Tells whether the client is still holding
+    /// back the body, in which case the connection can't be reused.
+    pub fn reject(&self) -> bool {
+        let mut state = self.0.lock().unwrap();
+        if state.armed && !state.requested && !state.received {
+            state.armed = false;
+            trace!("Respond without 100 Continue");
+            return true;
+        }
+        false
+    }
+}
+
+/// Stream wrapper that writes `100 Continue` when requested.
+pub(crate) struct ContinueStream<S> {
+    inner: S,
+    cont: Continue,
+    /// Bytes of CONTINUE written so far.
+    written: usize,
+}
+
+impl<S> ContinueStream<S> {
+    pub fn new(inner: S, cont: Continue) -> Self {
+        ContinueStream {
+            inner,
+            cont,
+            written: 0,
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> ContinueStream<S> {
+    /// Writes a requested (or partially written) `100 Continue`.
+    fn poll_continue(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        {
+            let state = self.cont.0.lock().unwrap();
+            if !state.armed || !state.requested {
+                return Ok(()).into();
+            }
+        }
+
+        while self.written < CONTINUE.len() {
+            let amount =
+                ready!(Pin::new(&mut self.inner).poll_write(cx, &CONTINUE[self.written..]))?;
+            if amount == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    "Write 100 Continue",
+                ))
+                .into();
+            }
+            self.written += amount;
+        }
+
+        ready!(Pin::new(&mut self.inner).poll_flush(cx))?;
+
+        trace!("Sent 100 Continue");
+        self.cont.0.lock().unwrap().armed = false;
+        self.written = 0;
+
+        Ok(()).into()
+    }
+
+    /// The response is starting, it's too late for a 100 Continue.
+    fn disarm(&mut self) {
+        self.cont.0.lock().unwrap().armed = false;
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for ContinueStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        // reading the body means we must let the client know to send it.
+        ready!(this.poll_continue(cx))?;
+
+        let amount = ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+
+        if amount > 0 {
+            let mut state = this.cont.0.lock().unwrap();
+            if state.armed {
+                state.received = true;
+            }
+        }
+
+        Ok(amount).into()
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ContinueStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        // finish any started 100 Continue before the response.
+        ready!(this.poll_continue(cx))?;
+
+        this.disarm();
+
+        Pin::new(&mut this.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
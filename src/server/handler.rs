@@ -1,5 +1,6 @@
 use super::Reply;
 use crate::Body;
+use crate::WebSocket;
 use http::Request;
 use std::future::Future;
 use std::pin::Pin;
@@ -125,3 +126,66 @@ where
         })
     }
 }
+
+/// Trait for a handler of WebSocket connections.
+///
+/// Typically this trait is not used directly since there is a blanket implementation
+/// for any function that matches this signature:
+///
+/// ```ignore
+/// async fn my_handler(req: Request<Body>, ws: WebSocket) {
+///    ...
+/// }
+/// ```
+///
+/// The request is the one that was upgraded, without its body. The handler is
+/// called once the upgrade response is sent, and the connection is closed when
+/// the handler returns.
+///
+/// # Examples
+///
+/// ```
+/// use hreq::prelude::*;
+/// use hreq::ws::Message;
+/// use hreq::WebSocket;
+///
+/// async fn start_server() {
+///    let mut server = Server::new();
+///
+///    server.at("/echo").websocket(echo);
+///
+///    server.listen(3000).await.unwrap();
+/// }
+///
+/// async fn echo(req: http::Request<Body>, mut ws: WebSocket) {
+///    while let Some(Ok(msg)) = ws.recv().await {
+///        if let Message::Text(_) | Message::Binary(_) = msg {
+///            if ws.send(msg).await.is_err() {
+///                break;
+///            }
+///        }
+///    }
+/// }
+/// ```
+pub trait WebSocketHandler: Send + Sync + 'static {
+    /// Call the handler.
+    fn call<'a>(
+        &'a self,
+        req: Request<Body>,
+        ws: WebSocket,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+}
+
+impl<F: Send + Sync + 'static, Fut> WebSocketHandler for F
+where
+    F: Fn(Request<Body>, WebSocket) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    fn call<'a>(
+        &'a self,
+        req: Request<Body>,
+        ws: WebSocket,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        Box::pin((self)(req, ws))
+    }
+}
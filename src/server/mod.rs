@@ -92,6 +92,17 @@
 //! }
 //! ```
 //!
+//! # Query parameters
+//!
+//! The query string is not part of routing. Its values are available using
+//! [`query_param()`], or deserialized into a struct using [`query_as()`].
+//!
+//! # Compression
+//!
+//! Responses are compressed when the handler sets a `content-encoding` header. To
+//! instead negotiate compression from the request `accept-encoding`, use the
+//! [`Compress`] middleware.
+//!
 //! # State
 //!
 //! Many servers needs to work over some shared mutable state to function.
@@ -147,6 +158,9 @@
 //! [`Sync`]: https://doc.rust-lang.org/std/marker/trait.Sync.html
 //! [`Clone`]: https://doc.rust-lang.org/std/clone/trait.Clone.html
 //! [`path_param()`]: trait.ServerRequestExt.html#tymethod.path_param
+//! [`query_param()`]: trait.ServerRequestExt.html#tymethod.query_param
+//! [`query_as()`]: trait.ServerRequestExt.html#tymethod.query_as
+//! [`Compress`]: struct.Compress.html
 
 use crate::bw::BandwidthMonitor;
 use crate::params::resolve_hreq_params;
@@ -164,10 +178,13 @@ use std::time::Duration;
 use tokio_util::compat::FuturesAsyncReadCompatExt;
 
 mod chain;
+mod compress;
 mod conn;
+mod expect;
 mod handler;
 mod limit;
 mod middle;
+mod multipart;
 mod path;
 mod peek;
 mod reply;
@@ -177,16 +194,21 @@ mod router;
 mod serv_handle;
 mod serv_req_ext;
 mod statik;
+mod upgrade;
 
 #[cfg(feature = "tls")]
 mod tls_config;
 
 use conn::Connection;
+use expect::{Continue, ContinueStream};
 use serv_handle::EndFut;
+use upgrade::UpgradeStream;
 
 pub use chain::Next;
-pub use handler::{Handler, StateHandler};
+pub use compress::Compress;
+pub use handler::{Handler, StateHandler, WebSocketHandler};
 pub use middle::{Middleware, StateMiddleware};
+pub use multipart::{MultipartPart, MultipartReader};
 pub use reply::Reply;
 pub use resb_ext::ResponseBuilderExt;
 pub use route::{Route, StateRoute};
@@ -579,7 +601,7 @@ where
         //
 
         // Make h1 or h2 abstraction over the connection.
-        let mut conn = if proto == Protocol::Http2 {
+        if proto == Protocol::Http2 {
             const DEFAULT_CONN_WINDOW: u32 = 1024 * 1024;
             const DEFAULT_STREAM_WINDOW: u32 = 1024 * 1024;
             const DEFAULT_MAX_FRAME_SIZE: u32 = 16 * 1024;
@@ -588,19 +610,43 @@ where
             builder
                 .initial_window_size(DEFAULT_STREAM_WINDOW)
                 .initial_connection_window_size(DEFAULT_CONN_WINDOW)
-                .max_frame_size(DEFAULT_MAX_FRAME_SIZE);
+                .max_frame_size(DEFAULT_MAX_FRAME_SIZE)
+                // WebSockets over http2 (RFC 8441)
+                .enable_connect_protocol();
 
             let mut h2conn = builder.handshake(stream.compat()).await?;
 
             let pinger = h2conn.ping_pong().expect("ping_pong of h2 conn");
             let bw = BandwidthMonitor::new(pinger);
 
-            Connection::new_h2(h2conn, bw)
+            let conn = Connection::new_h2(h2conn, bw);
+
+            self.serve(conn, local_addr, remote_addr).await
         } else {
+            // Sends 100 Continue for requests with expect.
+            let cont = Continue::default();
+            let stream = ContinueStream::new(stream, cont.clone());
+
+            // WebSocket upgrades take the stream from the http1.1 layer.
+            let (stream, upgrade) = UpgradeStream::new(stream);
+
             let h1conn = hreq_h1::server::handshake(stream);
-            Connection::new_h1(h1conn)
-        };
+            let conn = Connection::new_h1(h1conn, cont, upgrade);
+
+            self.serve(conn, local_addr, remote_addr).await
+        }
+    }
 
+    /// Serve the requests of a connection.
+    async fn serve<S>(
+        self: Arc<Self>,
+        mut conn: Connection<S>,
+        local_addr: SocketAddr,
+        remote_addr: SocketAddr,
+    ) -> Result<(), Error>
+    where
+        S: Stream,
+    {
         debug!("Handshake done, waiting for requests: {}", remote_addr);
 
         loop {
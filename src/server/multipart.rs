@@ -0,0 +1,649 @@
+//! Streaming multipart/form-data parser.
+
+use crate::head_ext::HeaderMapExt;
+use crate::AsyncRead;
+use crate::Body;
+use crate::Error;
+use futures_util::future::poll_fn;
+use futures_util::io::AsyncReadExt;
+use futures_util::ready;
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+use http::Request;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+const DEFAULT_MAX_PARTS: usize = 100;
+const DEFAULT_MAX_HEADER_SIZE: usize = 8 * 1024;
+const MAX_HEADERS: usize = 32;
+const READ_SIZE: usize = 16 * 1024;
+const MAX_STRING_SIZE: usize = 10 * 1024 * 1024;
+
+/// Reader of incoming `multipart/form-data` request bodies.
+///
+/// The parts are read one by one, each part being an `AsyncRead` over its content.
+/// Nothing is buffered beyond what is needed to find the boundaries between parts,
+/// which means large files can be streamed straight to disk.
+///
+/// A part must be read (or dropped) before moving on to the next. Any part content
+/// left unread is skipped by the next call to [`next_part`].
+///
+/// # Limits
+///
+/// To protect against misbehaving clients, there are limits to the number of parts
+/// (default 100), the size of the headers of each part (default 8kb) and the total size
+/// of the body (default no limit).
+///
+/// # Example
+///
+/// ```
+/// use hreq::prelude::*;
+/// use hreq::server::MultipartReader;
+/// use futures_util::io::AsyncReadExt;
+///
+/// async fn upload(req: http::Request<Body>) -> Result<String, hreq::Error> {
+///     let mut form = MultipartReader::new(req)?
+///         .max_parts(10)
+///         .max_size(100 * 1024 * 1024);
+///
+///     let mut total = 0;
+///
+///     while let Some(mut part) = form.next_part().await? {
+///         if part.file_name().is_some() {
+///             // stream file contents somewhere.
+///             let mut buf = [0_u8; 8192];
+///             loop {
+///                 let amount = part.read(&mut buf).await?;
+///                 if amount == 0 {
+///                     break;
+///                 }
+///                 total += amount;
+///             }
+///         } else {
+///             let value = part.read_to_string().await?;
+///             println!("{:?}: {}", part.name(), value);
+///         }
+///     }
+///
+///     Ok(format!("Received {} bytes", total))
+/// }
+/// ```
+///
+/// [`next_part`]: struct.MultipartReader.html#method.next_part
+pub struct MultipartReader {
+    body: Body,
+    /// `\r\n--boundary`
+    delimiter: Vec<u8>,
+    buf: Vec<u8>,
+    state: State,
+    parts: usize,
+    total: u64,
+    max_parts: usize,
+    max_header_size: usize,
+    max_size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Reading content (or the preamble) until the next delimiter.
+    Content,
+    /// Just after a delimiter.
+    Delimiter,
+    /// Reading the head of a part.
+    Head,
+    /// Closing delimiter found.
+    End,
+}
+
+/// A part in a [`MultipartReader`].
+///
+/// Implements `AsyncRead` over the part content.
+///
+/// [`MultipartReader`]: struct.MultipartReader.html
+pub struct MultipartPart<'a> {
+    reader: &'a mut MultipartReader,
+    headers: HeaderMap,
+    name: Option<String>,
+    file_name: Option<String>,
+}
+
+impl MultipartReader {
+    /// Creates a reader from a request with a `multipart/*` content type.
+    ///
+    /// Errors if the `content-type` is not multipart or lacks a boundary.
+    pub fn new(req: Request<Body>) -> Result<Self, Error> {
+        let boundary = req
+            .headers()
+            .get_str("content-type")
+            .and_then(boundary_from_content_type)
+            .ok_or_else(|| Error::Proto("Request is not multipart with boundary".into()))?;
+
+        Ok(Self::from_body(req.into_body(), &boundary))
+    }
+
+    /// Creates a reader from a body and a boundary.
+    pub fn from_body(body: Body, boundary: &str) -> Self {
+        let mut delimiter = b"\r\n--".to_vec();
+        delimiter.extend_from_slice(boundary.as_bytes());
+
+        MultipartReader {
+            body,
+            delimiter,
+            // the first delimiter is not preceded by a line break, pretending it is
+            // means we can treat the preamble as content of a part.
+            buf: b"\r\n".to_vec(),
+            state: State::Content,
+            parts: 0,
+            total: 0,
+            max_parts: DEFAULT_MAX_PARTS,
+            max_header_size: DEFAULT_MAX_HEADER_SIZE,
+            max_size: None,
+        }
+    }
+
+    /// Max number of parts. Defaults to 100.
+    pub fn max_parts(mut self, amount: usize) -> Self {
+        self.max_parts = amount;
+        self
+    }
+
+    /// Max size of the headers of each part. Defaults to 8kb.
+    pub fn max_header_size(mut self, size: usize) -> Self {
+        self.max_header_size = size;
+        self
+    }
+
+    /// Max size of the entire body. Defaults to no limit.
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Read the next part. Returns `None` when there are no more parts.
+    pub async fn next_part(&mut self) -> Result<Option<MultipartPart<'_>>, Error> {
+        // skip unread content of the previous part (or the preamble).
+        let mut skip = vec![0; READ_SIZE];
+        while self.state == State::Content {
+            let reader = &mut *self;
+            poll_fn(|cx| reader.poll_content(cx, &mut skip)).await?;
+        }
+
+        if self.state == State::End {
+            return Ok(None);
+        }
+
+        let headers = poll_fn(|cx| self.poll_head(cx)).await?;
+
+        let headers = match headers {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        self.parts += 1;
+        if self.parts > self.max_parts {
+            return Err(Error::Proto(format!(
+                "multipart exceeds max parts: {}",
+                self.max_parts
+            )));
+        }
+
+        let disposition = headers.get_str("content-disposition").unwrap_or("");
+        let name = disposition_param(disposition, "name");
+        let file_name = disposition_param(disposition, "filename*")
+            .and_then(|v| decode_ext_value(&v))
+            .or_else(|| disposition_param(disposition, "filename"));
+
+        trace!("Multipart part: {:?} {:?}", name, file_name);
+
+        self.state = State::Content;
+
+        Ok(Some(MultipartPart {
+            reader: self,
+            headers,
+            name,
+            file_name,
+        }))
+    }
+
+    /// Read more of the body into the buffer.
+    fn poll_fill(&mut self, cx: &mut Context) -> Poll<io::Result<usize>> {
+        let old_len = self.buf.len();
+        self.buf.resize(old_len + READ_SIZE, 0);
+
+        let res = Pin::new(&mut self.body).poll_read(cx, &mut self.buf[old_len..]);
+
+        let amount = match res {
+            Poll::Ready(Ok(v)) => v,
+            r => {
+                self.buf.truncate(old_len);
+                return r;
+            }
+        };
+
+        self.buf.truncate(old_len + amount);
+        self.total += amount as u64;
+
+        if let Some(max) = self.max_size {
+            if self.total > max {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("multipart exceeds max size: {}", max),
+                ))
+                .into();
+            }
+        }
+
+        Ok(amount).into()
+    }
+
+    /// Finds the next delimiter in the buffer. If there is none (yet), tells how much
+    /// of the buffer is certainly content.
+    fn scan(&self) -> Result<usize, usize> {
+        let len = self.delimiter.len();
+        let mut from = 0;
+
+        while let Some(pos) = find(&self.buf[from..], &self.delimiter) {
+            let idx = from + pos;
+            let after = &self.buf[idx + len..];
+
+            if after.len() < 2 {
+                // can't tell until we read more.
+                return Err(idx);
+            }
+
+            let is_delimiter = after.starts_with(b"--")
+                || after.starts_with(b"\r\n")
+                || after[0] == b' '
+                || after[0] == b'\t';
+
+            if is_delimiter {
+                return Ok(idx);
+            }
+
+            // the boundary as prefix of a longer line is content.
+            from = idx + 1;
+        }
+
+        // keep enough to match a delimiter split over reads.
+        Err(self.buf.len().saturating_sub(len - 1))
+    }
+
+    /// Read content up to the next delimiter.
+    fn poll_content(&mut self, cx: &mut Context, out: &mut [u8]) -> Poll<io::Result<usize>> {
+        if self.state != State::Content || out.is_empty() {
+            return Ok(0).into();
+        }
+
+        loop {
+            // bytes we know are content.
+            let avail = match self.scan() {
+                Ok(0) => {
+                    self.buf.drain(0..self.delimiter.len());
+                    self.state = State::Delimiter;
+                    return Ok(0).into();
+                }
+                Ok(idx) => idx,
+                Err(safe) => safe,
+            };
+
+            if avail > 0 {
+                let amount = avail.min(out.len());
+                out[0..amount].copy_from_slice(&self.buf[0..amount]);
+                self.buf.drain(0..amount);
+                return Ok(amount).into();
+            }
+
+            if ready!(self.poll_fill(cx))? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "multipart body ended before boundary",
+                ))
+                .into();
+            }
+        }
+    }
+
+    /// Read the rest of the delimiter line and the following part head.
+    fn poll_head(&mut self, cx: &mut Context) -> Poll<Result<Option<HeaderMap>, Error>> {
+        loop {
+            if self.state == State::Delimiter {
+                if self.buf.starts_with(b"--") {
+                    // close delimiter, the rest is epilogue.
+                    trace!("Multipart end");
+                    self.state = State::End;
+                    return Ok(None).into();
+                }
+
+                if let Some(idx) = find(&self.buf, b"\r\n") {
+                    // transport padding is allowed before the line break.
+                    if !self.buf[0..idx].iter().all(|c| *c == b' ' || *c == b'\t') {
+                        return Err(Error::Proto("multipart malformed boundary".into())).into();
+                    }
+                    // keep the line break to find an empty head.
+                    self.buf.drain(0..idx);
+                    self.state = State::Head;
+                }
+            }
+
+            if self.state == State::Head {
+                if let Some(idx) = find(&self.buf, b"\r\n\r\n") {
+                    if idx > self.max_header_size {
+                        break;
+                    }
+                    let head = &self.buf[2..idx + 4];
+                    let headers = parse_headers(head)?;
+                    self.buf.drain(0..idx + 4);
+                    return Ok(Some(headers)).into();
+                }
+            }
+
+            if self.buf.len() > self.max_header_size {
+                break;
+            }
+
+            if ready!(self.poll_fill(cx))? == 0 {
+                return Err(Error::Proto("multipart body ended in part head".into())).into();
+            }
+        }
+
+        Err(Error::Proto(format!(
+            "multipart header exceeds max size: {}",
+            self.max_header_size
+        )))
+        .into()
+    }
+}
+
+impl<'a> MultipartPart<'a> {
+    /// The headers of the part.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The `name` of the part from the `content-disposition` header.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The `filename` of the part from the `content-disposition` header.
+    ///
+    /// This is the name on the client side and should not be trusted as a path.
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    /// The `content-type` of the part.
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers.get_str("content-type")
+    }
+
+    /// Reads the part content into a new `Vec` limited by `limit`.
+    pub async fn read_to_vec(&mut self, limit: usize) -> Result<Vec<u8>, Error> {
+        let mut vec = vec![];
+        self.take((limit as u64).saturating_add(1))
+            .read_to_end(&mut vec)
+            .await?;
+
+        if vec.len() > limit {
+            return Err(Error::Proto(format!(
+                "part exceeds limit of {} bytes",
+                limit
+            )));
+        }
+
+        Ok(vec)
+    }
+
+    /// Reads the part content into a new `String`. Limited to 10MB.
+    pub async fn read_to_string(&mut self) -> Result<String, Error> {
+        let vec = self.read_to_vec(MAX_STRING_SIZE).await?;
+        Ok(String::from_utf8(vec).map_err(|e| e.utf8_error())?)
+    }
+}
+
+impl<'a> AsyncRead for MultipartPart<'a> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().reader.poll_content(cx, buf)
+    }
+}
+
+impl fmt::Debug for MultipartReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultipartReader")
+            .field("state", &self.state)
+            .field("parts", &self.parts)
+            .field("total", &self.total)
+            .finish()
+    }
+}
+
+impl<'a> fmt::Debug for MultipartPart<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultipartPart")
+            .field("name", &self.name)
+            .field("file_name", &self.file_name)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn parse_headers(head: &[u8]) -> Result<HeaderMap, Error> {
+    let mut parsed = [httparse::EMPTY_HEADER; MAX_HEADERS];
+
+    let headers = match httparse::parse_headers(head, &mut parsed).map_err(Error::Http11Parser)? {
+        httparse::Status::Complete((_, headers)) => headers,
+        httparse::Status::Partial => {
+            return Err(Error::Proto("multipart partial part head".into()));
+        }
+    };
+
+    let mut map = HeaderMap::with_capacity(headers.len());
+
+    for h in headers {
+        let name = HeaderName::from_bytes(h.name.as_bytes())
+            .map_err(|_| Error::Proto(format!("multipart bad header name: {}", h.name)))?;
+        let value = HeaderValue::from_bytes(h.value)
+            .map_err(|_| Error::Proto(format!("multipart bad header value: {}", h.name)))?;
+        map.append(name, value);
+    }
+
+    Ok(map)
+}
+
+fn boundary_from_content_type(ctype: &str) -> Option<String> {
+    let mime = ctype.split(';').next()?.trim();
+    if !mime.to_ascii_lowercase().starts_with("multipart/") {
+        return None;
+    }
+    disposition_param(ctype, "boundary").filter(|b| !b.is_empty())
+}
+
+/// Find a parameter like `name="foo"` in a header value such as
+/// `form-data; name="foo"; filename="bar.txt"`.
+fn disposition_param(value: &str, key: &str) -> Option<String> {
+    let mut rest = value.split_once(';')?.1;
+
+    loop {
+        rest = rest.trim_start_matches(&[' ', '\t', ';'][..]);
+        if rest.is_empty() {
+            return None;
+        }
+
+        let eq = rest.find('=')?;
+        let name = rest[0..eq].trim();
+        rest = &rest[eq + 1..];
+
+        let val = if let Some(quoted) = rest.strip_prefix('"') {
+            // quoted string with backslash escapes.
+            let mut val = String::new();
+            let mut chars = quoted.char_indices();
+            let mut end = quoted.len();
+            while let Some((idx, c)) = chars.next() {
+                match c {
+                    '\\' => {
+                        if let Some((_, c)) = chars.next() {
+                            val.push(c);
+                        }
+                    }
+                    '"' => {
+                        end = idx + 1;
+                        break;
+                    }
+                    _ => val.push(c),
+                }
+            }
+            rest = &quoted[end..];
+            val
+        } else {
+            let end = rest.find(';').unwrap_or(rest.len());
+            let val = rest[0..end].trim().to_string();
+            rest = &rest[end..];
+            val
+        };
+
+        if name.eq_ignore_ascii_case(key) {
+            return Some(val);
+        }
+    }
+}
+
+/// Decode RFC 5987 `UTF-8''percent%20encoded`.
+fn decode_ext_value(value: &str) -> Option<String> {
+    let mut split = value.splitn(3, '\'');
+    let charset = split.next()?;
+    let _lang = split.next()?;
+    let encoded = split.next()?;
+
+    if !charset.eq_ignore_ascii_case("utf-8") {
+        return None;
+    }
+
+    percent_encoding::percent_decode_str(encoded)
+        .decode_utf8()
+        .ok()
+        .map(|s| s.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::AsyncRuntime;
+
+    fn reader(s: &str) -> MultipartReader {
+        let mut body = Body::from_str(s);
+        body.set_codec_pass();
+        MultipartReader::from_body(body, "XyZ")
+    }
+
+    async fn read_all(mut form: MultipartReader) -> Result<Vec<(String, String)>, Error> {
+        let mut res = vec![];
+        while let Some(mut part) = form.next_part().await? {
+            let name = part.name().unwrap_or("").to_string();
+            res.push((name, part.read_to_string().await?));
+        }
+        Ok(res)
+    }
+
+    #[test]
+    fn parse_parts() {
+        let form = reader(
+            "preamble\r\n--XyZ\r\n\
+            content-disposition: form-data; name=\"a\"\r\n\r\n\
+            one\r\n\
+            --XyZ  \r\n\
+            content-disposition: form-data; name=\"b\"; filename=\"b.txt\"\r\n\
+            content-type: text/plain\r\n\r\n\
+            two\r\n--XyZx\r\n\
+            --XyZ--\r\nepilogue",
+        );
+
+        let parts = AsyncRuntime::block_on(read_all(form)).unwrap();
+
+        assert_eq!(
+            parts,
+            vec![
+                ("a".to_string(), "one".to_string()),
+                ("b".to_string(), "two\r\n--XyZx".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn skip_unread_part() {
+        let mut form = reader(
+            "--XyZ\r\n\
+            content-disposition: form-data; name=\"a\"; filename*=UTF-8''%C3%A5.txt\r\n\r\n\
+            skipped\r\n\
+            --XyZ\r\n\r\n\
+            no headers\r\n\
+            --XyZ--\r\n",
+        );
+
+        AsyncRuntime::block_on(async {
+            let part = form.next_part().await.unwrap().unwrap();
+            assert_eq!(part.file_name(), Some("å.txt"));
+
+            let mut part = form.next_part().await.unwrap().unwrap();
+            assert_eq!(part.name(), None);
+            assert_eq!(part.read_to_string().await.unwrap(), "no headers");
+
+            assert!(form.next_part().await.unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn limit_parts() {
+        let form = reader(
+            "--XyZ\r\n\r\na\r\n\
+            --XyZ\r\n\r\nb\r\n\
+            --XyZ--\r\n",
+        )
+        .max_parts(1);
+
+        let res = AsyncRuntime::block_on(read_all(form));
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn limit_header_size() {
+        let long = "x".repeat(100);
+        let form = reader(&format!(
+            "--XyZ\r\nx-long: {}\r\n\r\na\r\n--XyZ--\r\n",
+            long
+        ))
+        .max_header_size(50);
+
+        let res = AsyncRuntime::block_on(read_all(form));
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn unexpected_end() {
+        let form = reader("--XyZ\r\n\r\nno end");
+
+        let res = AsyncRuntime::block_on(read_all(form));
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn content_type_boundary() {
+        assert_eq!(
+            boundary_from_content_type("multipart/form-data; boundary=\"a b\""),
+            Some("a b".to_string())
+        );
+        assert_eq!(
+            boundary_from_content_type("Multipart/Form-Data;boundary=abc"),
+            Some("abc".to_string())
+        );
+        assert_eq!(boundary_from_content_type("text/plain; boundary=abc"), None);
+    }
+}
@@ -1,9 +1,11 @@
 use super::chain::Mid;
 use super::path::ParsedPath;
 use super::router::RouteMethod;
+use super::upgrade::WebSocketRoute;
 use super::Handler;
 use super::Router;
 use super::StateHandler;
+use super::WebSocketHandler;
 use super::{Middleware, StateMiddleware};
 use http::Method;
 use std::fmt;
@@ -145,6 +147,43 @@ where
     pub fn trace<H: Handler>(self, handler: H) -> Self {
         self.method(Method::TRACE, handler)
     }
+
+    /// WebSocket handler.
+    ///
+    /// Answers http1.1 upgrade requests (`GET` with `Upgrade: websocket`) with
+    /// `101 Switching Protocols`, and http2 extended `CONNECT` requests (RFC 8441)
+    /// with `200`. The handler is then given the connection as a [`WebSocket`].
+    ///
+    /// Other requests are rejected with `426 Upgrade Required`, or `400 Bad Request`
+    /// if the handshake is malformed. permessage-deflate is accepted when offered
+    /// and hreq is built with the `deflate` feature. Subprotocols
+    /// (`Sec-WebSocket-Protocol`) are not negotiated.
+    ///
+    /// ```
+    /// use hreq::prelude::*;
+    /// use hreq::ws::Message;
+    /// use hreq::WebSocket;
+    ///
+    /// async fn start_server() {
+    ///    let mut server = Server::new();
+    ///
+    ///    server.at("/chat").websocket(chat);
+    ///
+    ///    server.listen(3000).await.unwrap();
+    /// }
+    ///
+    /// async fn chat(req: http::Request<Body>, mut ws: WebSocket) {
+    ///    ws.send(Message::Text("Welcome".into())).await.ok();
+    ///    ws.close(None).await.ok();
+    /// }
+    /// ```
+    ///
+    /// [`WebSocket`]: ../struct.WebSocket.html
+    pub fn websocket<H: WebSocketHandler>(self, handler: H) -> Self {
+        let route = WebSocketRoute::new(handler);
+        self.method(Method::GET, route.clone())
+            .method(Method::CONNECT, route)
+    }
 }
 
 /// A state route as obtained by [`with_state`].
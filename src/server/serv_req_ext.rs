@@ -1,8 +1,11 @@
 use super::path::PathMatch;
 use crate::params::{AutoCharset, HReqParams};
 use crate::Body;
+use crate::Error;
 use encoding_rs::Encoding;
 use http::Request;
+use qstring::QString;
+use serde::de::DeserializeOwned;
 use std::str::FromStr;
 
 /// Extends [`http::Request`] with ergonomic extras for server requests to hreq.
@@ -78,6 +81,117 @@ pub trait ServerRequestExt {
     ///  ```
     fn path_params(&self) -> Vec<(&str, &str)>;
 
+    /// Get the first value of a query parameter.
+    ///
+    /// The value is percent decoded the same way [`RequestBuilderExt::query`] encodes it.
+    ///
+    /// # Example
+    ///
+    ///  ```
+    ///  use hreq::prelude::*;
+    ///
+    ///  async fn start_server() {
+    ///     let mut server = Server::new();
+    ///
+    ///     server.at("/search").get(search);
+    ///
+    ///     server.listen(3000).await.unwrap();
+    ///  }
+    ///
+    ///  async fn search(req: http::Request<Body>) -> String {
+    ///     // Called with `/search?q=hello%20world`
+    ///     format!("Searching for {}", req.query_param("q").unwrap())
+    ///  }
+    ///  ```
+    ///
+    /// [`RequestBuilderExt::query`]: ../trait.RequestBuilderExt.html#tymethod.query
+    fn query_param(&self, key: &str) -> Option<String>;
+
+    /// Get the first value of a query parameter coerced to type.
+    ///
+    /// # Example
+    ///
+    ///  ```
+    ///  use hreq::prelude::*;
+    ///
+    ///  async fn start_server() {
+    ///     let mut server = Server::new();
+    ///
+    ///     server.at("/list").get(list);
+    ///
+    ///     server.listen(3000).await.unwrap();
+    ///  }
+    ///
+    ///  async fn list(req: http::Request<Body>) -> String {
+    ///      let page: usize = req.query_param_as("page").unwrap_or(1);
+    ///      format!("Page {}", page)
+    ///  }
+    ///  ```
+    fn query_param_as<T: FromStr>(&self, key: &str) -> Option<T>;
+
+    /// Enumerate all query parameters with their values.
+    ///
+    /// Parameters are in the order of the query string. A parameter repeated
+    /// several times, like `?tag=a&tag=b`, has one entry per value. A parameter
+    /// without value, like `?debug`, has an empty value.
+    ///
+    /// # Example
+    ///
+    ///  ```
+    ///  use hreq::prelude::*;
+    ///
+    ///  async fn start_server() {
+    ///     let mut server = Server::new();
+    ///
+    ///     server.at("/tagged").get(tagged);
+    ///
+    ///     server.listen(3000).await.unwrap();
+    ///  }
+    ///
+    ///  async fn tagged(req: http::Request<Body>) -> String {
+    ///      let tags: Vec<_> = req
+    ///          .query_params()
+    ///          .into_iter()
+    ///          .filter(|(k, _)| k == "tag")
+    ///          .map(|(_, v)| v)
+    ///          .collect();
+    ///      tags.join(", ")
+    ///  }
+    ///  ```
+    fn query_params(&self) -> Vec<(String, String)>;
+
+    /// Deserialize the entire query string into a type.
+    ///
+    /// An absent query string is treated as an empty one, which means all fields
+    /// must be optional for it to succeed.
+    ///
+    /// # Example
+    ///
+    ///  ```
+    ///  use hreq::prelude::*;
+    ///  use serde_derive::Deserialize;
+    ///
+    ///  #[derive(Deserialize)]
+    ///  struct Paging {
+    ///      page: usize,
+    ///      per_page: Option<usize>,
+    ///  }
+    ///
+    ///  async fn start_server() {
+    ///     let mut server = Server::new();
+    ///
+    ///     server.at("/list").get(list);
+    ///
+    ///     server.listen(3000).await.unwrap();
+    ///  }
+    ///
+    ///  async fn list(req: http::Request<Body>) -> Result<String, hreq::Error> {
+    ///      let paging: Paging = req.query_as()?;
+    ///      Ok(format!("Page {} of size {}", paging.page, paging.per_page.unwrap_or(20)))
+    ///  }
+    ///  ```
+    fn query_as<T: DeserializeOwned>(&self) -> Result<T, Error>;
+
     /// Toggle automatic response body charset decoding. Defaults to `true`.
     ///
     /// hreq decodes the response body of text MIME types according to the `charset` in
@@ -137,6 +251,32 @@ impl ServerRequestExt for Request<Body> {
             .unwrap_or_else(|| vec![])
     }
 
+    fn query_param(&self, key: &str) -> Option<String> {
+        self.query_params()
+            .into_iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    fn query_param_as<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.query_param(key).and_then(|v| v.parse().ok())
+    }
+
+    fn query_params(&self) -> Vec<(String, String)> {
+        self.uri()
+            .query()
+            .map(|q| QString::from(q).into_pairs())
+            .unwrap_or_default()
+    }
+
+    fn query_as<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        // the query is decoded like the client encodes it, and then handed to serde
+        // in the form encoding it understands.
+        let form = serde_urlencoded::to_string(self.query_params())
+            .expect("Failed to encode query params");
+        Ok(serde_urlencoded::from_str(&form)?)
+    }
+
     fn charset_decode(self, enable: bool) -> Self {
         let (mut parts, body) = self.into_parts();
         let params = parts.extensions.get_mut::<HReqParams>().expect("");
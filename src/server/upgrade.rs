@@ -0,0 +1,287 @@
+//! Taking over connections for WebSockets.
+//!
+//! An http1.1 upgrade takes the whole connection once the `101 Switching Protocols`
+//! is sent. The http1.1 layer owns the connection, so it's given an `UpgradeStream`
+//! that reads as EOF once the real stream is taken, which makes it end cleanly.
+//!
+//! An http2 extended `CONNECT` (RFC 8441) keeps the connection, and the stream
+//! of the request becomes the upgraded stream.
+
+use super::{Handler, Reply, WebSocketHandler};
+use crate::params::HReqParams;
+use crate::ws::handshake::{self, Accept};
+use crate::AsyncRuntime;
+use crate::Body;
+use crate::Error;
+use crate::Stream;
+use crate::{AsyncRead, AsyncWrite};
+use bytes::Bytes;
+use futures_util::ready;
+use h2::SendStream as H2SendStream;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Handle shared between a connection and its `UpgradeStream`.
+#[derive(Clone)]
+pub(crate) struct Upgrade(Arc<Mutex<State>>);
+
+struct State {
+    /// The stream until it is taken.
+    stream: Option<Box<dyn Stream>>,
+    /// The http1.1 layer waiting to read.
+    read_waker: Option<Waker>,
+}
+
+impl Upgrade {
+    /// Take the stream away from the http1.1 layer.
+    pub fn take(&self) -> Option<Box<dyn Stream>> {
+        let mut state = self.0.lock().unwrap();
+        let stream = state.stream.take();
+        // the connection will read EOF instead of waiting forever.
+        if let Some(waker) = state.read_waker.take() {
+            waker.wake();
+        }
+        stream
+    }
+}
+
+/// Stream wrapper for the http1.1 layer that can be taken over.
+pub(crate) struct UpgradeStream(Upgrade);
+
+impl UpgradeStream {
+    pub fn new(stream: impl Stream) -> (Self, Upgrade) {
+        let state = State {
+            stream: Some(Box::new(stream)),
+            read_waker: None,
+        };
+        let upgrade = Upgrade(Arc::new(Mutex::new(state)));
+        (UpgradeStream(upgrade.clone()), upgrade)
+    }
+}
+
+fn taken() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "Connection upgraded")
+}
+
+impl AsyncRead for UpgradeStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut state = self.0 .0.lock().unwrap();
+        let poll = match &mut state.stream {
+            Some(stream) => Pin::new(stream).poll_read(cx, buf),
+            None => return Ok(0).into(),
+        };
+        if poll.is_pending() {
+            state.read_waker = Some(cx.waker().clone());
+        }
+        poll
+    }
+}
+
+impl AsyncWrite for UpgradeStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match &mut self.0 .0.lock().unwrap().stream {
+            Some(stream) => Pin::new(stream).poll_write(cx, buf),
+            None => Err(taken()).into(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        match &mut self.0 .0.lock().unwrap().stream {
+            Some(stream) => Pin::new(stream).poll_flush(cx),
+            None => Ok(()).into(),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        match &mut self.0 .0.lock().unwrap().stream {
+            Some(stream) => Pin::new(stream).poll_close(cx),
+            // closing is up to whoever took the stream.
+            None => Ok(()).into(),
+        }
+    }
+}
+
+/// Response extension that starts a WebSocket handler once the response is sent.
+pub(crate) struct OnUpgrade(Mutex<Option<Pending>>);
+
+struct Pending {
+    accept: Accept,
+    /// The request body of an http2 `CONNECT`, which is the incoming half of the stream.
+    h2_recv: Option<Body>,
+    run: Box<dyn FnOnce(crate::WebSocket) + Send>,
+}
+
+impl OnUpgrade {
+    pub fn new(
+        accept: Accept,
+        h2_recv: Option<Body>,
+        run: impl FnOnce(crate::WebSocket) + Send + 'static,
+    ) -> Self {
+        OnUpgrade(Mutex::new(Some(Pending {
+            accept,
+            h2_recv,
+            run: Box::new(run),
+        })))
+    }
+
+    /// Start the handler on the upgraded http1.1 connection.
+    pub fn run_h1(self, stream: Box<dyn Stream>) {
+        if let Some(p) = self.0.into_inner().unwrap() {
+            let ws = p.accept.into_websocket(stream);
+            (p.run)(ws);
+        }
+    }
+
+    /// Start the handler on the http2 stream of the `CONNECT`.
+    pub fn run_h2(self, send: H2SendStream<Bytes>) -> Result<(), Error> {
+        let p = self.0.into_inner().unwrap().expect("Pending upgrade");
+        let recv = p
+            .h2_recv
+            .ok_or_else(|| Error::Proto("No http2 stream".into()))?;
+
+        let stream = H2Stream {
+            recv,
+            send,
+            ended: false,
+        };
+
+        let ws = p.accept.into_websocket(stream);
+        (p.run)(ws);
+
+        Ok(())
+    }
+}
+
+/// Handler answering upgrade requests for a [`WebSocketHandler`].
+///
+/// Requests that aren't WebSocket upgrades get the error response from the
+/// handshake. The handler is run in its own task once the upgrade is done.
+pub(crate) struct WebSocketRoute<H>(Arc<H>);
+
+impl<H> WebSocketRoute<H> {
+    pub fn new(handler: H) -> Self {
+        WebSocketRoute(Arc::new(handler))
+    }
+}
+
+impl<H> Clone for WebSocketRoute<H> {
+    fn clone(&self) -> Self {
+        WebSocketRoute(self.0.clone())
+    }
+}
+
+impl<H: WebSocketHandler> Handler for WebSocketRoute<H> {
+    fn call<'a>(
+        &'a self,
+        req: http::Request<Body>,
+    ) -> Pin<Box<dyn Future<Output = Reply> + Send + 'a>> {
+        let res = self.upgrade(req);
+        Box::pin(async move { res.into() })
+    }
+}
+
+impl<H: WebSocketHandler> WebSocketRoute<H> {
+    fn upgrade(&self, req: http::Request<Body>) -> http::Response<Body> {
+        let (accept, mut res) = match handshake::server(&req) {
+            Ok(v) => v,
+            Err(res) => return *res,
+        };
+
+        let (parts, body) = req.into_parts();
+
+        let h2_recv = if parts.method == http::Method::CONNECT {
+            Some(body)
+        } else {
+            None
+        };
+
+        // the handler gets the request without the body, which is the WebSocket.
+        let mut body = Body::empty();
+        if let Some(params) = parts.extensions.get::<HReqParams>() {
+            body.configure(params, &http::HeaderMap::new(), true);
+        }
+        let req = http::Request::from_parts(parts, body);
+
+        let handler = self.0.clone();
+
+        let on_upgrade = OnUpgrade::new(accept, h2_recv, move |ws| {
+            AsyncRuntime::spawn(async move { handler.call(req, ws).await });
+        });
+
+        res.extensions_mut().insert(on_upgrade);
+
+        res
+    }
+}
+
+/// An http2 stream as a byte stream.
+struct H2Stream {
+    recv: Body,
+    send: H2SendStream<Bytes>,
+    ended: bool,
+}
+
+fn h2_err(e: h2::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+impl AsyncRead for H2Stream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        Pin::new(&mut this.recv).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for H2Stream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        if buf.is_empty() {
+            return Ok(0).into();
+        }
+
+        this.send.reserve_capacity(buf.len());
+
+        let capacity = loop {
+            let capacity = this.send.capacity();
+            if capacity > 0 {
+                break capacity;
+            }
+            match ready!(this.send.poll_capacity(cx)) {
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(h2_err(e)).into(),
+                None => return Err(io::ErrorKind::BrokenPipe.into()).into(),
+            }
+        };
+
+        let amount = capacity.min(buf.len());
+        let data = Bytes::copy_from_slice(&buf[..amount]);
+        this.send.send_data(data, false).map_err(h2_err)?;
+
+        Ok(amount).into()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Ok(()).into()
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.ended {
+            this.ended = true;
+            this.send.send_data(Bytes::new(), true).map_err(h2_err)?;
+        }
+        Ok(()).into()
+    }
+}
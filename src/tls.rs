@@ -1,49 +1,50 @@
 //! TLS stream conversion.
 
+use crate::client::{ConnMeta, TlsConfig};
 use crate::proto::Protocol;
-use crate::proto::{ALPN_H1, ALPN_H2};
+use crate::proto::ALPN_H1;
+#[cfg(feature = "server")]
+use crate::proto::ALPN_H2;
 use crate::Error;
 use crate::Stream;
 use crate::{AsyncRead, AsyncWrite};
 use futures_util::future::poll_fn;
 use futures_util::ready;
+use rustls::ClientSession;
 use rustls::Session;
-use rustls::{ClientConfig, ClientSession};
 use std::io;
 use std::io::Read;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use webpki::DNSNameRef;
-use webpki_roots::TLS_SERVER_ROOTS;
 
 /// Creates a TLS stream from any underlying stream.
 ///
 /// The TLS certificate will be validated against the (DNS) domain name provided.
-/// Negotiates ALPN and we prefer http2 over http11. The [`protocol`] resulting from
-/// the negotiation is returned with the wrapped stream.
+/// Negotiates ALPN and we prefer http2 over http11, unless a user provided rustls config
+/// says otherwise, or `http11_only` is set. The [`protocol`] resulting from the negotiation is returned with the
+/// wrapped stream, and the negotiated TLS details are noted in `meta`.
 ///
 /// [`protocol`]: ../proto/enum.Protocol.html
 pub(crate) async fn wrap_tls_client(
     stream: impl Stream,
     domain: &str,
     tls_disable_verify: bool,
+    tls: Option<&TlsConfig>,
+    http11_only: bool,
+    meta: &mut ConnMeta,
 ) -> Result<(impl Stream, Protocol), Error> {
-    //
-    let mut config = ClientConfig::new();
-
-    config
-        .root_store
-        .add_server_trust_anchors(&TLS_SERVER_ROOTS);
-
-    if tls_disable_verify {
-        config
-            .dangerous()
-            .set_certificate_verifier(Arc::new(DisabledCertVerified));
+    let mut config = match tls {
+        Some(tls) => tls.to_rustls_config(tls_disable_verify)?,
+        None => TlsConfig::new().to_rustls_config(tls_disable_verify)?,
+    };
+
+    // connections to be upgraded can't be http2.
+    if http11_only {
+        config.alpn_protocols = vec![ALPN_H1.to_owned()];
     }
 
-    config.alpn_protocols = vec![ALPN_H2.to_owned(), ALPN_H1.to_owned()];
-
     let config = Arc::new(config);
     let dnsname = DNSNameRef::try_from_ascii_str(domain)?;
 
@@ -55,12 +56,20 @@ pub(crate) async fn wrap_tls_client(
     trace!("tls handshake: {:?}", ret);
     ret?;
 
-    let proto = Protocol::from_alpn(tls.tls.get_alpn_protocol());
+    let alpn = tls.tls.get_alpn_protocol();
+    let proto = Protocol::from_alpn(alpn);
+
+    meta.alpn = alpn.map(|a| String::from_utf8_lossy(a).into_owned());
+    meta.tls_protocol = tls.tls.get_protocol_version().map(|v| format!("{:?}", v));
+    meta.tls_cipher = tls
+        .tls
+        .get_negotiated_ciphersuite()
+        .map(|c| format!("{:?}", c.suite));
 
     Ok((tls, proto))
 }
 
-struct DisabledCertVerified;
+pub(crate) struct DisabledCertVerified;
 
 impl rustls::ServerCertVerifier for DisabledCertVerified {
     fn verify_server_cert(
@@ -164,7 +173,20 @@ impl<S: Stream, E: Session + Unpin + 'static> TlsStream<S, E> {
                 && (poll_for_read && self.plaintext_left() == 0 || self.tls.is_handshaking())
             {
                 // we want to read something new
-                let _ = self.try_read_buf(cx);
+                let eof = matches!(self.try_read_buf(cx), Poll::Ready(Ok(0)));
+
+                if eof {
+                    if self.tls.is_handshaking() {
+                        // the other side gave up, for instance by rejecting our certificate.
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "Connection closed during TLS handshake",
+                        )));
+                    } else {
+                        // no more plaintext coming, poll_read gives the caller the EOF.
+                        return Poll::Ready(Ok(()));
+                    }
+                }
             }
 
             let mut did_tls_read_or_write = false;
@@ -233,11 +255,13 @@ impl<S: Stream, E: Session + Unpin + 'static> TlsStream<S, E> {
     }
 
     /// Attempt to read some more bytes into read_buf from the underlying stream.
-    fn try_read_buf(&mut self, cx: &mut Context) -> Poll<Result<(), io::Error>> {
+    ///
+    /// Returns the amount read, where 0 means the stream is at its end.
+    fn try_read_buf(&mut self, cx: &mut Context) -> Poll<Result<usize, io::Error>> {
         let mut tmp = [0; 8_192];
         let amount = ready!(Pin::new(&mut self.stream).poll_read(cx, &mut tmp[..]))?;
         self.read_buf.extend_from_slice(&tmp[0..amount]);
-        Ok(()).into()
+        Ok(amount).into()
     }
 
     /// Poll for handshake to finish.
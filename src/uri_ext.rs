@@ -146,11 +146,14 @@ impl HostPort {
         Ok(hostport)
     }
 
-    #[cfg(feature = "tls")]
     pub fn host(&self) -> &str {
         &self.host
     }
 
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
     pub fn is_tls(&self) -> bool {
         self.is_tls
     }
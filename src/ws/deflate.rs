@@ -0,0 +1,309 @@
+//! The permessage-deflate extension (RFC 7692).
+
+use super::Role;
+use crate::Error;
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+
+/// The extension as offered by clients.
+pub(crate) const OFFER: &str = "permessage-deflate";
+
+/// Every compressed message ends with this, which is left out on the wire.
+const TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Negotiated extension parameters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct DeflateConfig {
+    pub server_no_context_takeover: bool,
+    pub client_no_context_takeover: bool,
+}
+
+impl DeflateConfig {
+    /// Read the server's answer to our offer.
+    ///
+    /// `None` if the server declined. Parameters we can't honour are an error, since
+    /// the server must not send them unless we offered them.
+    pub fn from_response(extensions: &str) -> Result<Option<Self>, Error> {
+        let mut ret = None;
+
+        for (name, params) in parse_extensions(extensions) {
+            if name != OFFER {
+                return Err(Error::Proto(format!(
+                    "WebSocket extension not offered: {}",
+                    name
+                )));
+            }
+            if ret.is_some() {
+                return Err(Error::Proto("WebSocket extension accepted twice".into()));
+            }
+
+            let mut config = DeflateConfig::default();
+
+            for (key, value) in params {
+                match (key.as_str(), value.as_deref()) {
+                    ("server_no_context_takeover", None) => {
+                        config.server_no_context_takeover = true
+                    }
+                    ("client_no_context_takeover", None) => {
+                        config.client_no_context_takeover = true
+                    }
+                    // a smaller window than ours is fine to decompress.
+                    ("server_max_window_bits", Some(v)) if is_window_bits(v) => {}
+                    _ => {
+                        return Err(Error::Proto(format!(
+                            "Unsupported permessage-deflate parameter: {}",
+                            key
+                        )))
+                    }
+                }
+            }
+
+            ret = Some(config);
+        }
+
+        Ok(ret)
+    }
+
+    /// Pick an offer from a client, and the answer to send back.
+    ///
+    /// Offers we can't honour are skipped, which is the client's cue to go without.
+    pub fn accept_offer(extensions: &str) -> Option<(Self, String)> {
+        'offers: for (name, params) in parse_extensions(extensions) {
+            if name != OFFER {
+                continue;
+            }
+
+            let mut config = DeflateConfig::default();
+
+            for (key, value) in params {
+                match (key.as_str(), value.as_deref()) {
+                    ("server_no_context_takeover", None) => {
+                        config.server_no_context_takeover = true
+                    }
+                    ("client_no_context_takeover", None) => {
+                        config.client_no_context_takeover = true
+                    }
+                    // we only compress with the full window.
+                    ("server_max_window_bits", Some("15")) => {}
+                    // the client may use any window, and we don't limit it.
+                    ("client_max_window_bits", None) => {}
+                    ("client_max_window_bits", Some(v)) if is_window_bits(v) => {}
+                    _ => continue 'offers,
+                }
+            }
+
+            let mut answer = OFFER.to_string();
+            if config.server_no_context_takeover {
+                answer.push_str("; server_no_context_takeover");
+            }
+            if config.client_no_context_takeover {
+                answer.push_str("; client_no_context_takeover");
+            }
+
+            return Some((config, answer));
+        }
+
+        None
+    }
+}
+
+fn is_window_bits(v: &str) -> bool {
+    matches!(v.parse::<u8>(), Ok(8..=15))
+}
+
+/// Extension name and its parameters.
+type Extension = (String, Vec<(String, Option<String>)>);
+
+/// Split a `Sec-WebSocket-Extensions` value into extensions and their parameters.
+fn parse_extensions(v: &str) -> Vec<Extension> {
+    v.split(',')
+        .filter_map(|ext| {
+            let mut parts = ext.split(';').map(str::trim);
+            let name = parts.next().filter(|n| !n.is_empty())?.to_ascii_lowercase();
+            let params = parts
+                .filter(|p| !p.is_empty())
+                .map(|p| match p.find('=') {
+                    Some(i) => (
+                        p[..i].trim().to_ascii_lowercase(),
+                        Some(p[i + 1..].trim().trim_matches('"').to_string()),
+                    ),
+                    None => (p.to_ascii_lowercase(), None),
+                })
+                .collect();
+            Some((name, params))
+        })
+        .collect()
+}
+
+/// Compression state of one connection.
+pub(crate) struct Deflate {
+    compress: Compress,
+    decompress: Decompress,
+    reset_compress: bool,
+    reset_decompress: bool,
+}
+
+impl Deflate {
+    pub fn new(config: DeflateConfig, role: Role) -> Self {
+        let (reset_compress, reset_decompress) = match role {
+            Role::Client => (
+                config.client_no_context_takeover,
+                config.server_no_context_takeover,
+            ),
+            Role::Server => (
+                config.server_no_context_takeover,
+                config.client_no_context_takeover,
+            ),
+        };
+
+        Deflate {
+            compress: Compress::new(Compression::default(), false),
+            decompress: Decompress::new(false),
+            reset_compress,
+            reset_decompress,
+        }
+    }
+
+    /// Compress an entire message payload.
+    pub fn compress(&mut self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::with_capacity(data.len() / 2 + 64);
+        let start = self.compress.total_in();
+
+        loop {
+            let used = (self.compress.total_in() - start) as usize;
+
+            if out.len() == out.capacity() {
+                out.reserve(out.capacity());
+            }
+
+            self.compress
+                .compress_vec(&data[used..], &mut out, FlushCompress::Sync)
+                .map_err(|e| Error::Proto(format!("Deflate: {}", e)))?;
+
+            let used = (self.compress.total_in() - start) as usize;
+
+            // the flush is done when there is room left in the output.
+            if used == data.len() && out.len() < out.capacity() {
+                break;
+            }
+        }
+
+        if out.ends_with(&TAIL) {
+            out.truncate(out.len() - TAIL.len());
+        }
+
+        if self.reset_compress {
+            self.compress.reset();
+        }
+
+        Ok(out)
+    }
+
+    /// Decompress an entire message payload, failing if it exceeds `max` bytes.
+    pub fn decompress(&mut self, data: &[u8], max: usize) -> Result<Vec<u8>, Error> {
+        let mut input = Vec::with_capacity(data.len() + TAIL.len());
+        input.extend_from_slice(data);
+        input.extend_from_slice(&TAIL);
+
+        let mut out = Vec::with_capacity(data.len() * 2 + 64);
+        let start = self.decompress.total_in();
+
+        loop {
+            let used = (self.decompress.total_in() - start) as usize;
+
+            if out.len() == out.capacity() {
+                if out.len() > max {
+                    return Err(Error::Proto(
+                        "WebSocket message larger than max message size".into(),
+                    ));
+                }
+                out.reserve(out.capacity());
+            }
+
+            let status = self
+                .decompress
+                .decompress_vec(&input[used..], &mut out, FlushDecompress::Sync)
+                .map_err(|e| Error::Proto(format!("Inflate: {}", e)))?;
+
+            let used = (self.decompress.total_in() - start) as usize;
+
+            if status == Status::StreamEnd || used == input.len() && out.len() < out.capacity() {
+                break;
+            }
+        }
+
+        if out.len() > max {
+            return Err(Error::Proto(
+                "WebSocket message larger than max message size".into(),
+            ));
+        }
+
+        if self.reset_decompress {
+            self.decompress.reset(false);
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn negotiate() {
+        let c = DeflateConfig::from_response("permessage-deflate; client_no_context_takeover")
+            .unwrap()
+            .unwrap();
+        assert!(c.client_no_context_takeover);
+        assert!(!c.server_no_context_takeover);
+
+        assert!(DeflateConfig::from_response("x-webkit-deflate-frame").is_err());
+        assert!(
+            DeflateConfig::from_response("permessage-deflate; client_max_window_bits=9").is_err()
+        );
+        assert!(DeflateConfig::from_response("").unwrap().is_none());
+
+        let (c, answer) = DeflateConfig::accept_offer(
+            "permessage-deflate; server_max_window_bits=10, \
+             permessage-deflate; client_max_window_bits; server_no_context_takeover",
+        )
+        .unwrap();
+        assert!(c.server_no_context_takeover);
+        assert_eq!(answer, "permessage-deflate; server_no_context_takeover");
+
+        assert!(DeflateConfig::accept_offer("permessage-deflate; foo").is_none());
+    }
+
+    #[test]
+    fn rfc_example() {
+        // "Hello" compressed, from RFC 7692 section 7.2.3.2
+        let wire = [0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00];
+        let mut d = Deflate::new(DeflateConfig::default(), Role::Client);
+        assert_eq!(d.decompress(&wire, 1024).unwrap(), b"Hello");
+        // same again, referring back to the first message.
+        let wire = [0xf2, 0x00, 0x11, 0x00, 0x01, 0x00];
+        assert_eq!(d.decompress(&wire, 1024).unwrap(), b"Hello");
+    }
+
+    #[test]
+    fn round_trip() {
+        for reset in &[false, true] {
+            let config = DeflateConfig {
+                server_no_context_takeover: *reset,
+                client_no_context_takeover: *reset,
+            };
+            let mut client = Deflate::new(config, Role::Client);
+            let mut server = Deflate::new(config, Role::Server);
+
+            let data: Vec<u8> = (0..100_000).map(|i| (i % 13) as u8).collect();
+            for _ in 0..3 {
+                let c = client.compress(&data).unwrap();
+                assert!(c.len() < data.len() / 10);
+                assert_eq!(server.decompress(&c, 200_000).unwrap(), data);
+            }
+
+            let c = client.compress(&data).unwrap();
+            assert!(server.decompress(&c, 50_000).is_err());
+        }
+    }
+}
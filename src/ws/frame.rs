@@ -0,0 +1,268 @@
+//! WebSocket framing (RFC 6455 section 5).
+
+use crate::Error;
+
+/// Frame opcodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OpCode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl OpCode {
+    fn from_u8(v: u8) -> Option<Self> {
+        Some(match v {
+            0x0 => OpCode::Continuation,
+            0x1 => OpCode::Text,
+            0x2 => OpCode::Binary,
+            0x8 => OpCode::Close,
+            0x9 => OpCode::Ping,
+            0xa => OpCode::Pong,
+            _ => return None,
+        })
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            OpCode::Continuation => 0x0,
+            OpCode::Text => 0x1,
+            OpCode::Binary => 0x2,
+            OpCode::Close => 0x8,
+            OpCode::Ping => 0x9,
+            OpCode::Pong => 0xa,
+        }
+    }
+
+    pub fn is_control(self) -> bool {
+        matches!(self, OpCode::Close | OpCode::Ping | OpCode::Pong)
+    }
+}
+
+/// A single frame, with the payload unmasked.
+#[derive(Debug)]
+pub(crate) struct Frame {
+    pub fin: bool,
+    /// Set on the first frame of a compressed message (permessage-deflate).
+    pub rsv1: bool,
+    pub opcode: OpCode,
+    /// Whether the frame was masked on the wire.
+    pub masked: bool,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(opcode: OpCode, payload: Vec<u8>) -> Self {
+        Frame {
+            fin: true,
+            rsv1: false,
+            opcode,
+            masked: false,
+            payload,
+        }
+    }
+
+    /// Parse a frame from the start of `buf`.
+    ///
+    /// Returns the frame and how many bytes it used, or `None` if `buf` doesn't hold
+    /// an entire frame yet. Payloads larger than `max_payload` are an error, to not
+    /// wait for, or buffer, something we won't accept anyway.
+    pub fn parse(buf: &[u8], max_payload: usize) -> Result<Option<(Frame, usize)>, Error> {
+        if buf.len() < 2 {
+            return Ok(None);
+        }
+
+        let fin = buf[0] & 0x80 > 0;
+        let rsv1 = buf[0] & 0x40 > 0;
+
+        if buf[0] & 0x30 > 0 {
+            return Err(Error::Proto("WebSocket frame with RSV2/RSV3 set".into()));
+        }
+
+        let opcode = OpCode::from_u8(buf[0] & 0x0f)
+            .ok_or_else(|| Error::Proto(format!("WebSocket frame opcode: {:#x}", buf[0] & 0x0f)))?;
+
+        let masked = buf[1] & 0x80 > 0;
+
+        let (len, mut pos) = match buf[1] & 0x7f {
+            126 => {
+                if buf.len() < 4 {
+                    return Ok(None);
+                }
+                (u16::from_be_bytes([buf[2], buf[3]]) as u64, 4)
+            }
+            127 => {
+                if buf.len() < 10 {
+                    return Ok(None);
+                }
+                let mut b = [0; 8];
+                b.copy_from_slice(&buf[2..10]);
+                (u64::from_be_bytes(b), 10)
+            }
+            n => (n as u64, 2),
+        };
+
+        if opcode.is_control() && (len > 125 || !fin) {
+            return Err(Error::Proto(
+                "WebSocket control frame fragmented or too long".into(),
+            ));
+        }
+
+        if len > max_payload as u64 {
+            return Err(Error::Proto(format!(
+                "WebSocket frame larger than max message size: {}",
+                len
+            )));
+        }
+        let len = len as usize;
+
+        let mask = if masked {
+            if buf.len() < pos + 4 {
+                return Ok(None);
+            }
+            let mut m = [0; 4];
+            m.copy_from_slice(&buf[pos..pos + 4]);
+            pos += 4;
+            Some(m)
+        } else {
+            None
+        };
+
+        if buf.len() < pos + len {
+            return Ok(None);
+        }
+
+        let mut payload = buf[pos..pos + len].to_vec();
+        if let Some(mask) = mask {
+            apply_mask(&mut payload, mask);
+        }
+
+        let frame = Frame {
+            fin,
+            rsv1,
+            opcode,
+            masked,
+            payload,
+        };
+
+        Ok(Some((frame, pos + len)))
+    }
+
+    /// Append the frame to `out`, masking the payload if `mask` is given.
+    pub fn encode(&self, mask: Option<[u8; 4]>, out: &mut Vec<u8>) {
+        let mut b0 = self.opcode.as_u8();
+        if self.fin {
+            b0 |= 0x80;
+        }
+        if self.rsv1 {
+            b0 |= 0x40;
+        }
+        out.push(b0);
+
+        let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+        let len = self.payload.len();
+
+        if len < 126 {
+            out.push(mask_bit | len as u8);
+        } else if len <= u16::MAX as usize {
+            out.push(mask_bit | 126);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        } else {
+            out.push(mask_bit | 127);
+            out.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+
+        let start = out.len();
+        if let Some(mask) = mask {
+            out.extend_from_slice(&mask);
+        }
+        out.extend_from_slice(&self.payload);
+
+        if let Some(mask) = mask {
+            apply_mask(&mut out[start + 4..], mask);
+        }
+    }
+}
+
+/// Masking is an xor with the key, and so is unmasking.
+fn apply_mask(buf: &mut [u8], mask: [u8; 4]) {
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b ^= mask[i % 4];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rfc_examples() {
+        // single-frame unmasked text "Hello"
+        let buf = [0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f];
+        let (frame, used) = Frame::parse(&buf, 1024).unwrap().unwrap();
+        assert_eq!(used, 7);
+        assert!(frame.fin && !frame.masked);
+        assert_eq!(frame.opcode, OpCode::Text);
+        assert_eq!(frame.payload, b"Hello");
+
+        // single-frame masked text "Hello"
+        let buf = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        let (frame, _) = Frame::parse(&buf, 1024).unwrap().unwrap();
+        assert!(frame.masked);
+        assert_eq!(frame.payload, b"Hello");
+
+        let mut out = vec![];
+        frame.encode(Some([0x37, 0xfa, 0x21, 0x3d]), &mut out);
+        assert_eq!(out, buf);
+
+        // fragmented unmasked text
+        let (frame, _) = Frame::parse(&[0x01, 0x03, 0x48, 0x65, 0x6c], 1024)
+            .unwrap()
+            .unwrap();
+        assert!(!frame.fin);
+        assert_eq!(frame.opcode, OpCode::Text);
+        let (frame, _) = Frame::parse(&[0x80, 0x02, 0x6c, 0x6f], 1024)
+            .unwrap()
+            .unwrap();
+        assert!(frame.fin);
+        assert_eq!(frame.opcode, OpCode::Continuation);
+    }
+
+    #[test]
+    fn lengths_round_trip() {
+        for len in &[0, 125, 126, 65535, 65536] {
+            let frame = Frame::new(OpCode::Binary, vec![7; *len]);
+            let mut out = vec![];
+            frame.encode(Some([1, 2, 3, 4]), &mut out);
+
+            // incomplete until the last byte.
+            assert!(Frame::parse(&out[..out.len() - 1], 100_000)
+                .unwrap()
+                .is_none());
+
+            let (parsed, used) = Frame::parse(&out, 100_000).unwrap().unwrap();
+            assert_eq!(used, out.len());
+            assert_eq!(parsed.payload.len(), *len);
+            assert!(parsed.payload.iter().all(|b| *b == 7));
+        }
+    }
+
+    #[test]
+    fn bad_frames() {
+        // reserved opcode
+        assert!(Frame::parse(&[0x83, 0x00], 1024).is_err());
+        // RSV2
+        assert!(Frame::parse(&[0xa1, 0x00], 1024).is_err());
+        // fragmented ping
+        assert!(Frame::parse(&[0x09, 0x00], 1024).is_err());
+        // too long ping
+        assert!(Frame::parse(&[0x89, 0x7e, 0x00, 0x80], 1024).is_err());
+        // over max payload
+        assert!(Frame::parse(&[0x82, 0x7e, 0x04, 0x01], 1024).is_err());
+    }
+}
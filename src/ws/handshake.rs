@@ -0,0 +1,371 @@
+//! The opening handshake (RFC 6455 section 4).
+
+#[cfg(feature = "deflate")]
+use super::deflate::{self, DeflateConfig};
+use super::{Role, WebSocket};
+use crate::head_ext::HeaderMapExt;
+use crate::rand;
+use crate::Error;
+use crate::Stream;
+use crate::AGENT_IDENT;
+use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+use http::header::HeaderMap;
+use sha1::{Digest, Sha1};
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Max size of the response head, to not read forever from a confused server.
+const MAX_HEAD: usize = 64 * 1024;
+
+/// A random `Sec-WebSocket-Key`.
+fn new_key() -> String {
+    let mut key = [0; 16];
+    rand::fill(&mut key);
+    base64::encode(key)
+}
+
+/// The `Sec-WebSocket-Accept` for a key.
+pub(crate) fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.as_bytes());
+    hasher.update(GUID.as_bytes());
+    base64::encode(hasher.finalize())
+}
+
+/// Whether a comma separated header has the token, ignoring case.
+pub(crate) fn has_token(headers: &HeaderMap, name: &str, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|t| t.trim().eq_ignore_ascii_case(token))
+}
+
+/// `ws` and `wss` URIs are connected to like `http` and `https`.
+pub(crate) fn http_uri(uri: http::Uri) -> Result<http::Uri, Error> {
+    let scheme = match uri.scheme_str() {
+        Some("ws") => "http",
+        Some("wss") => "https",
+        _ => return Ok(uri),
+    };
+
+    let mut parts = uri.into_parts();
+    parts.scheme = Some(scheme.parse().expect("Scheme"));
+
+    Ok(http::Uri::from_parts(parts).map_err(http::Error::from)?)
+}
+
+/// Upgrade a connected stream as a client.
+///
+/// Writes the upgrade request in `parts`, and checks the response. The response head
+/// is returned for cookies.
+pub(crate) async fn client(
+    mut stream: impl Stream,
+    parts: &mut http::request::Parts,
+) -> Result<(WebSocket, http::Response<()>), Error> {
+    let key = new_key();
+
+    let authority = parts
+        .uri
+        .authority()
+        .ok_or_else(|| Error::User(format!("URI without host: {}", parts.uri)))?
+        .to_string();
+
+    let headers = &mut parts.headers;
+
+    if !headers.contains_key("host") {
+        headers.set("host", authority);
+    }
+    if !headers.contains_key("user-agent") {
+        headers.set("user-agent", &*AGENT_IDENT);
+    }
+    headers.set("upgrade", "websocket");
+    headers.set("connection", "Upgrade");
+    headers.set("sec-websocket-key", &key);
+    headers.set("sec-websocket-version", "13");
+
+    #[cfg(feature = "deflate")]
+    headers.set("sec-websocket-extensions", deflate::OFFER);
+    #[cfg(not(feature = "deflate"))]
+    headers.remove("sec-websocket-extensions");
+
+    let target = parts
+        .uri
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+
+    let mut head = format!("GET {} HTTP/1.1\r\n", target).into_bytes();
+    for (name, value) in headers.iter() {
+        head.extend_from_slice(name.as_str().as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(value.as_bytes());
+        head.extend_from_slice(b"\r\n");
+    }
+    head.extend_from_slice(b"\r\n");
+
+    trace!("WebSocket upgrade: {}", parts.uri);
+
+    stream.write_all(&head).await?;
+    stream.flush().await?;
+
+    let (res, rest) = read_response(&mut stream).await?;
+
+    if res.status() != http::StatusCode::SWITCHING_PROTOCOLS {
+        return Err(Error::Proto(format!(
+            "WebSocket upgrade refused: {}",
+            res.status()
+        )));
+    }
+
+    let rh = res.headers();
+
+    if !has_token(rh, "upgrade", "websocket") || !has_token(rh, "connection", "upgrade") {
+        return Err(Error::Proto(
+            "WebSocket upgrade without upgrade headers".into(),
+        ));
+    }
+
+    if rh.get_str("sec-websocket-accept") != Some(&accept_key(&key)) {
+        return Err(Error::Proto(
+            "WebSocket upgrade with wrong accept key".into(),
+        ));
+    }
+
+    let protocol = rh.get_str("sec-websocket-protocol").map(|p| p.to_string());
+    if let Some(p) = &protocol {
+        if !has_token(&parts.headers, "sec-websocket-protocol", p) {
+            return Err(Error::Proto(format!(
+                "WebSocket protocol not offered: {}",
+                p
+            )));
+        }
+    }
+
+    let extensions = rh.get_str("sec-websocket-extensions").unwrap_or("");
+
+    #[cfg(feature = "deflate")]
+    let deflate = DeflateConfig::from_response(extensions)?;
+
+    #[cfg(not(feature = "deflate"))]
+    {
+        if !extensions.is_empty() {
+            return Err(Error::Proto(format!(
+                "WebSocket extension not offered: {}",
+                extensions
+            )));
+        }
+    }
+
+    let ws = WebSocket::new(
+        stream,
+        Role::Client,
+        rest,
+        #[cfg(feature = "deflate")]
+        deflate,
+        protocol,
+    );
+
+    Ok((ws, res))
+}
+
+/// An accepted upgrade request, which becomes a [`WebSocket`] once the response is sent.
+#[cfg(feature = "server")]
+pub(crate) struct Accept {
+    #[cfg(feature = "deflate")]
+    deflate: Option<DeflateConfig>,
+}
+
+#[cfg(feature = "server")]
+impl Accept {
+    pub fn into_websocket(self, stream: impl Stream) -> WebSocket {
+        WebSocket::new(
+            stream,
+            Role::Server,
+            vec![],
+            #[cfg(feature = "deflate")]
+            self.deflate,
+            None,
+        )
+    }
+}
+
+/// Check an upgrade request as a server.
+///
+/// Requests are either an http1.1 `GET` with upgrade headers, or an http2 extended
+/// `CONNECT` with the `websocket` protocol (RFC 8441). The `Ok` response is `101`
+/// for the former and `200` for the latter. The `Err` response rejects the request.
+#[cfg(feature = "server")]
+pub(crate) fn server<B>(
+    req: &http::Request<B>,
+) -> Result<(Accept, http::Response<crate::Body>), Box<http::Response<crate::Body>>> {
+    use http::StatusCode;
+
+    let reject = |status: StatusCode, version: bool| {
+        let mut res = http::Response::builder().status(status);
+        if status == StatusCode::UPGRADE_REQUIRED {
+            res = res
+                .header("upgrade", "websocket")
+                .header("connection", "Upgrade");
+        }
+        if version {
+            res = res.header("sec-websocket-version", "13");
+        }
+        Box::new(res.body(crate::Body::empty()).unwrap())
+    };
+
+    let headers = req.headers();
+    let is_connect = req.method() == http::Method::CONNECT;
+
+    if is_connect {
+        let protocol = req.extensions().get::<h2::ext::Protocol>();
+        if protocol.map(|p| p.as_str()) != Some("websocket") {
+            return Err(reject(StatusCode::BAD_REQUEST, false));
+        }
+    } else if req.method() != http::Method::GET
+        || !has_token(headers, "upgrade", "websocket")
+        || !has_token(headers, "connection", "upgrade")
+    {
+        return Err(reject(StatusCode::UPGRADE_REQUIRED, false));
+    }
+
+    if headers.get_str("sec-websocket-version") != Some("13") {
+        return Err(reject(StatusCode::UPGRADE_REQUIRED, true));
+    }
+
+    #[cfg_attr(not(feature = "deflate"), allow(unused_mut))]
+    let mut res = if is_connect {
+        // http2 has no key to answer.
+        http::Response::builder().status(StatusCode::OK)
+    } else {
+        let key = headers
+            .get_str("sec-websocket-key")
+            .filter(|k| matches!(base64::decode(k), Ok(v) if v.len() == 16));
+
+        let key = match key {
+            Some(k) => k,
+            None => return Err(reject(StatusCode::BAD_REQUEST, false)),
+        };
+
+        http::Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header("upgrade", "websocket")
+            .header("connection", "Upgrade")
+            .header("sec-websocket-accept", accept_key(key))
+    };
+
+    #[cfg(feature = "deflate")]
+    let deflate = {
+        let offers = headers
+            .get_all("sec-websocket-extensions")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        match DeflateConfig::accept_offer(&offers) {
+            Some((config, answer)) => {
+                res = res.header("sec-websocket-extensions", answer);
+                Some(config)
+            }
+            None => None,
+        }
+    };
+
+    let accept = Accept {
+        #[cfg(feature = "deflate")]
+        deflate,
+    };
+
+    Ok((accept, res.body(crate::Body::empty()).unwrap()))
+}
+
+/// Read a response head, returning it with what was read past it.
+async fn read_response(stream: &mut impl Stream) -> Result<(http::Response<()>, Vec<u8>), Error> {
+    let mut buf = vec![];
+    let mut chunk = [0; 4096];
+
+    loop {
+        let amt = stream.read(&mut chunk).await?;
+        if amt == 0 {
+            return Err(Error::Proto("EOF before WebSocket upgrade response".into()));
+        }
+        buf.extend_from_slice(&chunk[..amt]);
+
+        let mut headers = [httparse::EMPTY_HEADER; 128];
+        let mut parsed = httparse::Response::new(&mut headers);
+
+        if let httparse::Status::Complete(len) = parsed.parse(&buf).map_err(Error::Http11Parser)? {
+            let mut res = http::Response::builder().status(parsed.code.unwrap_or(0));
+            for h in parsed.headers.iter() {
+                res = res.header(h.name, h.value);
+            }
+            let res = res.body(())?;
+
+            return Ok((res, buf[len..].to_vec()));
+        }
+
+        if buf.len() > MAX_HEAD {
+            return Err(Error::Proto(
+                "WebSocket upgrade response head too big".into(),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rfc_accept_key() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[cfg(feature = "server")]
+    #[test]
+    fn server_answers() {
+        let req = |key: &str| {
+            http::Request::get("/")
+                .header("upgrade", "websocket")
+                .header("connection", "keep-alive, Upgrade")
+                .header("sec-websocket-key", key)
+                .header("sec-websocket-version", "13")
+                .header("sec-websocket-extensions", "permessage-deflate")
+                .body(())
+                .unwrap()
+        };
+
+        let (_, res) = server(&req("dGhlIHNhbXBsZSBub25jZQ==")).ok().unwrap();
+        assert_eq!(res.status(), 101);
+        assert_eq!(
+            res.headers().get_str("sec-websocket-accept"),
+            Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=")
+        );
+        #[cfg(feature = "deflate")]
+        assert_eq!(
+            res.headers().get_str("sec-websocket-extensions"),
+            Some("permessage-deflate")
+        );
+
+        let res = server(&req("short")).err().unwrap();
+        assert_eq!(res.status(), 400);
+
+        let res = server(&http::Request::get("/").body(()).unwrap())
+            .err()
+            .unwrap();
+        assert_eq!(res.status(), 426);
+    }
+
+    #[test]
+    fn ws_schemes() {
+        let uri = http_uri("wss://a.test:9000/x?y".parse().unwrap()).unwrap();
+        assert_eq!(uri, "https://a.test:9000/x?y");
+        let uri = http_uri("ws://a.test/".parse().unwrap()).unwrap();
+        assert_eq!(uri, "http://a.test/");
+    }
+}
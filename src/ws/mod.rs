@@ -0,0 +1,829 @@
+//! WebSocket messages over an upgraded connection (RFC 6455).
+
+use crate::rand;
+use crate::Error;
+use crate::Stream;
+use futures_io::{AsyncRead, AsyncWrite};
+use futures_util::future::poll_fn;
+use futures_util::ready;
+use futures_util::sink::Sink;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+#[cfg(feature = "deflate")]
+mod deflate;
+mod frame;
+pub(crate) mod handshake;
+
+#[cfg(feature = "deflate")]
+use deflate::{Deflate, DeflateConfig};
+use frame::{Frame, OpCode};
+
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+const READ_CHUNK: usize = 16 * 1024;
+
+/// Which end of the connection we are. Clients mask what they send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Role {
+    Client,
+    Server,
+}
+
+/// A WebSocket message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// A UTF-8 text message.
+    Text(String),
+    /// A binary message.
+    Binary(Vec<u8>),
+    /// A ping. Received pings are answered automatically.
+    Ping(Vec<u8>),
+    /// A pong, the answer to a ping.
+    Pong(Vec<u8>),
+    /// A close, optionally with a code and reason.
+    Close(Option<CloseFrame>),
+}
+
+/// Status code and reason of a close message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    code: u16,
+    reason: String,
+}
+
+/// A WebSocket connection.
+///
+/// Messages are read as a [`Stream`] (or with [`recv`]) and written as a [`Sink`]
+/// (or with [`send`]).
+///
+///   * Pings are answered with pongs.
+///   * A close from the peer is answered, after which the stream ends.
+///   * Fragmented messages are put together before they are read.
+///   * With permessage-deflate negotiated, messages are compressed.
+///   * Breaches of the protocol close the connection with code `1002`, and text
+///     messages that aren't UTF-8 with `1007`.
+///
+/// Clients are created using [`Agent::websocket`] or [`RequestBuilderExt::websocket`],
+/// and servers hand them to handlers attached with [`Route::websocket`].
+///
+/// ```no_run
+/// use hreq::prelude::*;
+/// use hreq::ws::Message;
+///
+/// let mut ws = Request::get("wss://echo.websocket.events")
+///     .websocket()
+///     .block()
+///     .unwrap();
+///
+/// ws.send(Message::Text("Hello".into())).block().unwrap();
+///
+/// while let Some(msg) = ws.recv().block() {
+///     if let Message::Text(text) = msg.unwrap() {
+///         println!("{}", text);
+///         break;
+///     }
+/// }
+///
+/// ws.close(None).block().unwrap();
+/// ```
+///
+/// [`Stream`]: https://docs.rs/futures/latest/futures/stream/trait.Stream.html
+/// [`Sink`]: https://docs.rs/futures/latest/futures/sink/trait.Sink.html
+/// [`recv`]: struct.WebSocket.html#method.recv
+/// [`send`]: struct.WebSocket.html#method.send
+/// [`Agent::websocket`]: ../struct.Agent.html#method.websocket
+/// [`RequestBuilderExt::websocket`]: ../trait.RequestBuilderExt.html#tymethod.websocket
+/// [`Route::websocket`]: ../server/struct.Route.html#method.websocket
+pub struct WebSocket {
+    io: Box<dyn Stream>,
+    role: Role,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+    write_pos: usize,
+    partial: Option<Partial>,
+    #[cfg(feature = "deflate")]
+    deflate: Option<Deflate>,
+    protocol: Option<String>,
+    max_message_size: usize,
+    max_frame_size: Option<usize>,
+    close_sent: bool,
+    close_received: bool,
+    done: bool,
+}
+
+/// A message being read in fragments.
+struct Partial {
+    opcode: OpCode,
+    compressed: bool,
+    data: Vec<u8>,
+}
+
+impl WebSocket {
+    /// Take over an upgraded stream. `read_buf` holds bytes already read past the
+    /// handshake.
+    pub(crate) fn new(
+        io: impl Stream,
+        role: Role,
+        read_buf: Vec<u8>,
+        #[cfg(feature = "deflate")] deflate: Option<DeflateConfig>,
+        protocol: Option<String>,
+    ) -> Self {
+        WebSocket {
+            io: Box::new(io),
+            role,
+            read_buf,
+            write_buf: vec![],
+            write_pos: 0,
+            partial: None,
+            #[cfg(feature = "deflate")]
+            deflate: deflate.map(|c| Deflate::new(c, role)),
+            protocol,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            max_frame_size: None,
+            close_sent: false,
+            close_received: false,
+            done: false,
+        }
+    }
+
+    /// The subprotocol (`Sec-WebSocket-Protocol`) agreed in the handshake.
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Whether messages are compressed using permessage-deflate.
+    ///
+    /// The extension is offered by clients and accepted by servers when hreq is
+    /// built with the `deflate` feature.
+    pub fn is_deflate(&self) -> bool {
+        #[cfg(feature = "deflate")]
+        return self.deflate.is_some();
+
+        #[cfg(not(feature = "deflate"))]
+        false
+    }
+
+    /// Max size of received messages, after decompression. Defaults to 64MB.
+    ///
+    /// Bigger messages close the connection with code `1009`.
+    pub fn max_message_size(&mut self, size: usize) {
+        self.max_message_size = size;
+    }
+
+    /// Send messages bigger than this in fragments. Defaults to `None`, which sends
+    /// every message as one frame.
+    pub fn max_frame_size(&mut self, size: Option<usize>) {
+        self.max_frame_size = size.map(|s| s.max(1));
+    }
+
+    /// Send a message.
+    ///
+    /// Returns when the message is written to the connection.
+    pub async fn send(&mut self, msg: Message) -> Result<(), Error> {
+        poll_fn(|cx| self.poll_write_buf(cx)).await?;
+        self.queue_message(msg)?;
+        poll_fn(|cx| self.poll_flush_io(cx)).await
+    }
+
+    /// Receive the next message.
+    ///
+    /// `None` once the connection is closed.
+    pub async fn recv(&mut self) -> Option<Result<Message, Error>> {
+        poll_fn(|cx| self.poll_message(cx)).await
+    }
+
+    /// Close the connection.
+    ///
+    /// Sends a close message, defaulting to code `1000`, and waits for the peer to
+    /// answer it. Messages received in the meantime are dropped.
+    pub async fn close(&mut self, frame: Option<CloseFrame>) -> Result<(), Error> {
+        if !self.close_sent {
+            let frame = frame.unwrap_or_else(|| CloseFrame::new(1000, ""));
+            self.queue_message(Message::Close(Some(frame)))?;
+        }
+
+        poll_fn(|cx| self.poll_flush_io(cx)).await?;
+
+        while let Some(msg) = self.recv().await {
+            msg?;
+        }
+
+        // the peer might have closed the connection already.
+        let _ = poll_fn(|cx| Pin::new(&mut self.io).poll_close(cx)).await;
+
+        Ok(())
+    }
+
+    fn poll_message(&mut self, cx: &mut Context) -> Poll<Option<Result<Message, Error>>> {
+        loop {
+            if self.done {
+                return None.into();
+            }
+
+            // send pongs and close replies on the side. no more frames are read until
+            // they are out, or a peer that doesn't read would make the buffer grow.
+            match self.poll_write_buf(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => {
+                    self.done = true;
+                    return Some(Err(e)).into();
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+
+            if self.close_received {
+                // the closing handshake is complete.
+                if self.role == Role::Server {
+                    // the server is supposed to close the TCP connection first.
+                    let _ = ready!(Pin::new(&mut self.io).poll_close(cx));
+                }
+                self.done = true;
+                return None.into();
+            }
+
+            let parsed = match Frame::parse(&self.read_buf, self.max_message_size) {
+                Ok(v) => v,
+                Err(e) => return Some(Err(self.fail(cx, 1002, e))).into(),
+            };
+
+            if let Some((frame, used)) = parsed {
+                self.read_buf.drain(..used);
+
+                match self.handle_frame(frame) {
+                    Ok(Some(msg)) => return Some(Ok(msg)).into(),
+                    Ok(None) => continue,
+                    Err((code, e)) => return Some(Err(self.fail(cx, code, e))).into(),
+                }
+            }
+
+            let len = self.read_buf.len();
+            self.read_buf.resize(len + READ_CHUNK, 0);
+
+            let res = Pin::new(&mut self.io).poll_read(cx, &mut self.read_buf[len..]);
+
+            let amt = match res {
+                Poll::Pending => {
+                    self.read_buf.truncate(len);
+                    return Poll::Pending;
+                }
+                Poll::Ready(Err(e)) => {
+                    self.read_buf.truncate(len);
+                    self.done = true;
+                    return Some(Err(e.into())).into();
+                }
+                Poll::Ready(Ok(amt)) => amt,
+            };
+
+            self.read_buf.truncate(len + amt);
+
+            if amt == 0 {
+                self.done = true;
+
+                // a peer that doesn't answer our close is not worth an error.
+                if self.close_sent {
+                    return None.into();
+                }
+
+                let err = io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "WebSocket closed without close message",
+                );
+                return Some(Err(err.into())).into();
+            }
+        }
+    }
+
+    /// Deal with a received frame. Errors have the close code to send.
+    fn handle_frame(&mut self, frame: Frame) -> Result<Option<Message>, (u16, Error)> {
+        let proto = |msg: &str| (1002, Error::Proto(msg.into()));
+
+        // clients mask, servers don't.
+        if frame.masked != (self.role == Role::Server) {
+            return Err(proto("WebSocket frame masking is wrong"));
+        }
+
+        if frame.rsv1
+            && !(self.is_deflate() && matches!(frame.opcode, OpCode::Text | OpCode::Binary))
+        {
+            return Err(proto("WebSocket frame with unexpected RSV1"));
+        }
+
+        match frame.opcode {
+            OpCode::Ping => {
+                if !self.close_sent {
+                    self.queue(Frame::new(OpCode::Pong, frame.payload.clone()));
+                }
+                Ok(Some(Message::Ping(frame.payload)))
+            }
+
+            OpCode::Pong => Ok(Some(Message::Pong(frame.payload))),
+
+            OpCode::Close => {
+                let close = CloseFrame::parse(&frame.payload)?;
+                self.close_received = true;
+
+                if !self.close_sent {
+                    // echo the code back.
+                    let payload = frame.payload.get(..2).unwrap_or(&[]).to_vec();
+                    self.queue(Frame::new(OpCode::Close, payload));
+                    self.close_sent = true;
+                }
+
+                Ok(Some(Message::Close(close)))
+            }
+
+            OpCode::Text | OpCode::Binary => {
+                if self.partial.is_some() {
+                    return Err(proto(
+                        "WebSocket message started within a fragmented message",
+                    ));
+                }
+
+                self.partial = Some(Partial {
+                    opcode: frame.opcode,
+                    compressed: frame.rsv1,
+                    data: frame.payload,
+                });
+
+                self.finish_partial(frame.fin)
+            }
+
+            OpCode::Continuation => {
+                let max = self.max_message_size;
+                let partial = self
+                    .partial
+                    .as_mut()
+                    .ok_or_else(|| proto("WebSocket continuation without a message"))?;
+
+                if partial.data.len() + frame.payload.len() > max {
+                    return Err((1009, Error::Proto("WebSocket message too big".into())));
+                }
+                partial.data.extend_from_slice(&frame.payload);
+
+                self.finish_partial(frame.fin)
+            }
+        }
+    }
+
+    fn finish_partial(&mut self, fin: bool) -> Result<Option<Message>, (u16, Error)> {
+        if !fin {
+            return Ok(None);
+        }
+
+        let partial = self.partial.take().expect("Partial message");
+
+        #[cfg(feature = "deflate")]
+        let data = if partial.compressed {
+            let max = self.max_message_size;
+            let deflate = self.deflate.as_mut().expect("Negotiated deflate");
+            deflate
+                .decompress(&partial.data, max)
+                .map_err(|e| (1009, e))?
+        } else {
+            partial.data
+        };
+
+        #[cfg(not(feature = "deflate"))]
+        let data = {
+            // RSV1 is refused without the extension.
+            debug_assert!(!partial.compressed);
+            partial.data
+        };
+
+        Ok(Some(if partial.opcode == OpCode::Text {
+            let text = String::from_utf8(data)
+                .map_err(|_| (1007, Error::Proto("WebSocket text is not UTF-8".into())))?;
+            Message::Text(text)
+        } else {
+            Message::Binary(data)
+        }))
+    }
+
+    /// Give up on the connection after a protocol error, telling the peer why.
+    fn fail(&mut self, cx: &mut Context, code: u16, err: Error) -> Error {
+        debug!("WebSocket failed ({}): {}", code, err);
+
+        if !self.close_sent {
+            self.queue(Frame::new(OpCode::Close, code.to_be_bytes().to_vec()));
+            self.close_sent = true;
+            let _ = self.poll_write_buf(cx);
+        }
+
+        self.done = true;
+        err
+    }
+
+    fn queue_message(&mut self, msg: Message) -> Result<(), Error> {
+        if self.close_sent {
+            return Err(Error::User("WebSocket message sent after close".into()));
+        }
+
+        let (opcode, payload) = match msg {
+            Message::Text(v) => (OpCode::Text, v.into_bytes()),
+            Message::Binary(v) => (OpCode::Binary, v),
+            Message::Ping(v) => (OpCode::Ping, v),
+            Message::Pong(v) => (OpCode::Pong, v),
+            Message::Close(v) => {
+                self.close_sent = true;
+                (OpCode::Close, v.map(|c| c.to_payload()).unwrap_or_default())
+            }
+        };
+
+        if opcode.is_control() {
+            if payload.len() > 125 {
+                return Err(Error::User(
+                    "WebSocket ping, pong or close payload over 125 bytes".into(),
+                ));
+            }
+            self.queue(Frame::new(opcode, payload));
+            return Ok(());
+        }
+
+        #[cfg(feature = "deflate")]
+        let (compressed, payload) = match &mut self.deflate {
+            Some(deflate) => (true, deflate.compress(&payload)?),
+            None => (false, payload),
+        };
+
+        #[cfg(not(feature = "deflate"))]
+        let compressed = false;
+
+        let size = self.max_frame_size.unwrap_or(usize::MAX);
+
+        if payload.len() <= size {
+            let mut frame = Frame::new(opcode, payload);
+            frame.rsv1 = compressed;
+            self.queue(frame);
+            return Ok(());
+        }
+
+        let count = payload.chunks(size).len();
+        for (i, chunk) in payload.chunks(size).enumerate() {
+            let first = i == 0;
+            let mut frame = Frame::new(
+                if first { opcode } else { OpCode::Continuation },
+                chunk.to_vec(),
+            );
+            frame.rsv1 = first && compressed;
+            frame.fin = i == count - 1;
+            self.queue(frame);
+        }
+
+        Ok(())
+    }
+
+    fn queue(&mut self, frame: Frame) {
+        let mask = if self.role == Role::Client {
+            let mut mask = [0; 4];
+            rand::fill(&mut mask);
+            Some(mask)
+        } else {
+            None
+        };
+        frame.encode(mask, &mut self.write_buf);
+    }
+
+    /// Write everything queued.
+    fn poll_write_buf(&mut self, cx: &mut Context) -> Poll<Result<(), Error>> {
+        while self.write_pos < self.write_buf.len() {
+            let buf = &self.write_buf[self.write_pos..];
+            let amt = ready!(Pin::new(&mut self.io).poll_write(cx, buf))?;
+
+            if amt == 0 {
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    "WebSocket write zero",
+                )))
+                .into();
+            }
+
+            self.write_pos += amt;
+        }
+
+        self.write_buf.clear();
+        self.write_pos = 0;
+
+        Ok(()).into()
+    }
+
+    fn poll_flush_io(&mut self, cx: &mut Context) -> Poll<Result<(), Error>> {
+        ready!(self.poll_write_buf(cx))?;
+        ready!(Pin::new(&mut self.io).poll_flush(cx))?;
+        Ok(()).into()
+    }
+}
+
+impl futures_core::Stream for WebSocket {
+    type Item = Result<Message, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_message(cx)
+    }
+}
+
+impl Sink<Message> for WebSocket {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Error>> {
+        self.get_mut().poll_write_buf(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Message) -> Result<(), Error> {
+        self.get_mut().queue_message(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Error>> {
+        self.get_mut().poll_flush_io(cx)
+    }
+
+    /// Sends a close message with code `1000` unless one is sent already. Doesn't wait
+    /// for the peer to answer.
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Error>> {
+        let this = self.get_mut();
+
+        if !this.close_sent {
+            this.queue_message(Message::Close(Some(CloseFrame::new(1000, ""))))?;
+        }
+
+        ready!(this.poll_flush_io(cx))?;
+        ready!(Pin::new(&mut this.io).poll_close(cx))?;
+
+        Ok(()).into()
+    }
+}
+
+/// A future WebSocket.
+///
+/// Instances should be `.await` or `.block()`.
+pub struct WebSocketFuture {
+    fut: Pin<Box<dyn Future<Output = Result<WebSocket, Error>> + Send>>,
+}
+
+impl WebSocketFuture {
+    pub(crate) fn new(t: impl Future<Output = Result<WebSocket, Error>> + Send + 'static) -> Self {
+        WebSocketFuture { fut: Box::pin(t) }
+    }
+}
+
+impl Future for WebSocketFuture {
+    type Output = Result<WebSocket, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.get_mut().fut.as_mut().poll(cx)
+    }
+}
+
+impl fmt::Debug for WebSocketFuture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WebSocketFuture")
+    }
+}
+
+impl fmt::Debug for WebSocket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WebSocket")
+            .field("role", &self.role)
+            .field("protocol", &self.protocol)
+            .field("deflate", &self.is_deflate())
+            .field("close_sent", &self.close_sent)
+            .field("close_received", &self.close_received)
+            .finish()
+    }
+}
+
+impl CloseFrame {
+    /// Create a close frame.
+    ///
+    /// The reason is truncated to fit the 123 bytes a close message has room for.
+    pub fn new(code: u16, reason: &str) -> Self {
+        let mut end = reason.len().min(123);
+        while !reason.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        CloseFrame {
+            code,
+            reason: reason[..end].to_string(),
+        }
+    }
+
+    /// The status code, such as `1000` for a normal closure.
+    pub fn code(&self) -> u16 {
+        self.code
+    }
+
+    /// The reason, which might be empty.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    fn parse(payload: &[u8]) -> Result<Option<Self>, (u16, Error)> {
+        if payload.is_empty() {
+            return Ok(None);
+        }
+
+        if payload.len() == 1 {
+            return Err((
+                1002,
+                Error::Proto("WebSocket close payload of 1 byte".into()),
+            ));
+        }
+
+        let code = u16::from_be_bytes([payload[0], payload[1]]);
+
+        // codes that may be sent in a close frame.
+        let valid = matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999);
+        if !valid {
+            return Err((
+                1002,
+                Error::Proto(format!("WebSocket close code: {}", code)),
+            ));
+        }
+
+        let reason = std::str::from_utf8(&payload[2..])
+            .map_err(|_| {
+                (
+                    1007,
+                    Error::Proto("WebSocket close reason is not UTF-8".into()),
+                )
+            })?
+            .to_string();
+
+        Ok(Some(CloseFrame { code, reason }))
+    }
+
+    fn to_payload(&self) -> Vec<u8> {
+        let mut v = self.code.to_be_bytes().to_vec();
+        v.extend_from_slice(self.reason.as_bytes());
+        v
+    }
+}
+
+impl From<String> for Message {
+    fn from(v: String) -> Self {
+        Message::Text(v)
+    }
+}
+
+impl<'a> From<&'a str> for Message {
+    fn from(v: &'a str) -> Self {
+        Message::Text(v.to_string())
+    }
+}
+
+impl From<Vec<u8>> for Message {
+    fn from(v: Vec<u8>) -> Self {
+        Message::Binary(v)
+    }
+}
+
+impl<'a> From<&'a [u8]> for Message {
+    fn from(v: &'a [u8]) -> Self {
+        Message::Binary(v.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::AsyncRuntime;
+    use std::sync::{Arc, Mutex};
+
+    /// Reads the input, and keeps what is written.
+    struct TestIo {
+        input: io::Cursor<Vec<u8>>,
+        output: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl AsyncRead for TestIo {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _: &mut Context,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            Ok(io::Read::read(&mut self.get_mut().input, buf)?).into()
+        }
+    }
+
+    impl AsyncWrite for TestIo {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _: &mut Context,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.output.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len()).into()
+        }
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+            Ok(()).into()
+        }
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+            Ok(()).into()
+        }
+    }
+
+    fn server_ws(input: Vec<u8>) -> (WebSocket, Arc<Mutex<Vec<u8>>>) {
+        let output = Arc::new(Mutex::new(vec![]));
+        let io = TestIo {
+            input: io::Cursor::new(input),
+            output: output.clone(),
+        };
+        let ws = WebSocket::new(
+            io,
+            Role::Server,
+            vec![],
+            #[cfg(feature = "deflate")]
+            None,
+            None,
+        );
+        (ws, output)
+    }
+
+    fn masked(fin: bool, opcode: OpCode, payload: &[u8]) -> Vec<u8> {
+        let mut frame = Frame::new(opcode, payload.to_vec());
+        frame.fin = fin;
+        let mut out = vec![];
+        frame.encode(Some([9, 8, 7, 6]), &mut out);
+        out
+    }
+
+    fn unmasked(opcode: OpCode, payload: &[u8]) -> Vec<u8> {
+        let mut out = vec![];
+        Frame::new(opcode, payload.to_vec()).encode(None, &mut out);
+        out
+    }
+
+    #[test]
+    fn fragments_and_ping() {
+        let mut input = masked(false, OpCode::Text, b"Hel");
+        input.extend(masked(true, OpCode::Ping, b"p"));
+        input.extend(masked(true, OpCode::Continuation, b"lo"));
+        input.extend(masked(true, OpCode::Close, &[0x03, 0xe8, b'b', b'y', b'e']));
+
+        let (mut ws, output) = server_ws(input);
+
+        let msgs: Vec<_> = AsyncRuntime::block_on(async {
+            let mut v = vec![];
+            while let Some(m) = ws.recv().await {
+                v.push(m.unwrap());
+            }
+            v
+        });
+
+        assert_eq!(
+            msgs,
+            vec![
+                Message::Ping(b"p".to_vec()),
+                Message::Text("Hello".into()),
+                Message::Close(Some(CloseFrame::new(1000, "bye"))),
+            ]
+        );
+
+        // pong and close echo
+        let mut expect = unmasked(OpCode::Pong, b"p");
+        expect.extend(unmasked(OpCode::Close, &[0x03, 0xe8]));
+        assert_eq!(*output.lock().unwrap(), expect);
+    }
+
+    #[test]
+    fn protocol_errors() {
+        let cases = vec![
+            (unmasked(OpCode::Text, b"x"), 1002_u16),
+            (masked(true, OpCode::Text, &[0xff, 0xfe]), 1007),
+            (masked(true, OpCode::Continuation, b"x"), 1002),
+            (masked(true, OpCode::Close, &[0x03, 0xed]), 1002),
+        ];
+
+        for (input, code) in cases {
+            let (mut ws, output) = server_ws(input);
+            let res = AsyncRuntime::block_on(ws.recv()).unwrap();
+            assert!(res.is_err());
+            assert!(AsyncRuntime::block_on(ws.recv()).is_none());
+
+            let expect = unmasked(OpCode::Close, &code.to_be_bytes());
+            assert_eq!(*output.lock().unwrap(), expect, "code {}", code);
+        }
+    }
+
+    #[test]
+    fn send_fragmented() {
+        let (mut ws, output) = server_ws(vec![]);
+        ws.max_frame_size(Some(2));
+
+        AsyncRuntime::block_on(ws.send(Message::Binary(b"abcde".to_vec()))).unwrap();
+
+        let mut frame = Frame::new(OpCode::Binary, b"ab".to_vec());
+        frame.fin = false;
+        let mut expect = vec![];
+        frame.encode(None, &mut expect);
+        frame = Frame::new(OpCode::Continuation, b"cd".to_vec());
+        frame.fin = false;
+        frame.encode(None, &mut expect);
+        expect.extend(unmasked(OpCode::Continuation, b"e"));
+
+        assert_eq!(*output.lock().unwrap(), expect);
+    }
+}
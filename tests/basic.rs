@@ -45,6 +45,93 @@ fn query_params_doubled() -> Result<(), Error> {
     Ok(())
 }
 
+#[test]
+fn server_query_param() -> Result<(), Error> {
+    common::setup_logger();
+
+    let bld = http::Request::builder();
+    let req = bld
+        .uri("/path?page=2")
+        .query("q", "a b&c=d+e")
+        .query("tag", "x")
+        .query("tag", "y")
+        .body(())?;
+
+    let mut server = Server::new();
+    server
+        .at("/path")
+        .all(|req: http::Request<Body>| async move {
+            assert_eq!(req.query_param("q").as_deref(), Some("a b&c=d+e"));
+            assert_eq!(req.query_param("tag").as_deref(), Some("x"));
+            assert_eq!(req.query_param("missing"), None);
+            assert_eq!(req.query_param_as::<u32>("page"), Some(2));
+            assert_eq!(req.query_param_as::<u32>("tag"), None);
+            let params = req.query_params();
+            assert_eq!(params.len(), 4);
+            assert_eq!(params[2], ("tag".to_string(), "x".to_string()));
+            assert_eq!(params[3], ("tag".to_string(), "y".to_string()));
+            "ok"
+        });
+
+    let res = server.handle(req).block()?;
+
+    assert_eq!(res.status(), 200);
+    Ok(())
+}
+
+#[test]
+fn server_query_as() -> Result<(), Error> {
+    use serde_derive::Deserialize;
+
+    common::setup_logger();
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Search {
+        q: String,
+        page: u32,
+        limit: Option<u32>,
+    }
+
+    let mut server = Server::new();
+    server
+        .at("/path")
+        .all(|req: http::Request<Body>| async move {
+            let status = match req.query_as::<Search>() {
+                Ok(search) => {
+                    assert_eq!(
+                        search,
+                        Search {
+                            q: "1+1 = 2".into(),
+                            page: 3,
+                            limit: None
+                        }
+                    );
+                    200
+                }
+                Err(_) => 400,
+            };
+            http::Response::builder().status(status).body(()).unwrap()
+        });
+
+    let req = http::Request::builder()
+        .uri("/path")
+        .query("q", "1+1 = 2")
+        .query("page", "3")
+        .body(())?;
+    let res = server.handle(req).block()?;
+    assert_eq!(res.status(), 200);
+
+    let req = http::Request::builder()
+        .uri("/path")
+        .query("q", "x")
+        .query("page", "three")
+        .body(())?;
+    let res = server.handle(req).block()?;
+    assert_eq!(res.status(), 400);
+
+    Ok(())
+}
+
 #[test]
 fn request_header() -> Result<(), Error> {
     common::setup_logger();
@@ -0,0 +1,290 @@
+use hreq::prelude::*;
+use hreq::server::Compress;
+use hreq::{Agent, CacheStatus, DiskCache, Error, MemoryCache};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+mod common;
+
+type Hits = Arc<AtomicUsize>;
+
+/// Server counting the requests that reach it.
+fn start() -> Result<(hreq::server::ServerHandle, SocketAddr, Hits), Error> {
+    let hits = Arc::new(AtomicUsize::new(0));
+
+    let mut server = Server::with_state(hits.clone());
+
+    server
+        .at("/fresh")
+        .with_state()
+        .get(|hits: Hits, _: http::Request<Body>| async move {
+            let n = hits.fetch_add(1, Ordering::SeqCst);
+            http::Response::builder()
+                .header("cache-control", "max-age=60")
+                .body(format!("fresh {}", n))
+                .unwrap()
+        })
+        .delete(|_: Hits, _: http::Request<Body>| async move { "deleted" });
+
+    server
+        .at("/etag")
+        .with_state()
+        .get(|hits: Hits, req: http::Request<Body>| async move {
+            hits.fetch_add(1, Ordering::SeqCst);
+            if req.header("if-none-match") == Some("\"v1\"") {
+                http::Response::builder()
+                    .status(304)
+                    .header("etag", "\"v1\"")
+                    .header("x-extra", "updated")
+                    .body("".to_string())
+                    .unwrap()
+            } else {
+                http::Response::builder()
+                    .header("etag", "\"v1\"")
+                    .header("cache-control", "no-cache")
+                    .body("tagged".to_string())
+                    .unwrap()
+            }
+        });
+
+    server
+        .at("/vary")
+        .with_state()
+        .get(|hits: Hits, req: http::Request<Body>| async move {
+            hits.fetch_add(1, Ordering::SeqCst);
+            let lang = req.header("accept-language").unwrap_or("none").to_string();
+            http::Response::builder()
+                .header("cache-control", "max-age=60")
+                .header("vary", "accept-language")
+                .body(lang)
+                .unwrap()
+        });
+
+    server
+        .at("/nostore")
+        .with_state()
+        .get(|hits: Hits, _: http::Request<Body>| async move {
+            hits.fetch_add(1, Ordering::SeqCst);
+            http::Response::builder()
+                .header("cache-control", "no-store, max-age=60")
+                .body("secret")
+                .unwrap()
+        });
+
+    server
+        .at("/compressed")
+        .middleware(Compress::new().min_size(0))
+        .with_state()
+        .get(|hits: Hits, _: http::Request<Body>| async move {
+            hits.fetch_add(1, Ordering::SeqCst);
+            http::Response::builder()
+                .header("cache-control", "max-age=60")
+                .header("content-type", "text/plain")
+                .body("squeezed ".repeat(100))
+                .unwrap()
+        });
+
+    let (shut, addr) = server.listen(0).block()?;
+
+    Ok((shut, addr, hits))
+}
+
+fn get(agent: &Agent, addr: SocketAddr, path: &str) -> Result<http::Response<Body>, Error> {
+    let uri = format!("http://127.0.0.1:{}{}", addr.port(), path);
+    agent.send(http::Request::get(uri).body(())?).block()
+}
+
+fn read(res: http::Response<Body>) -> Result<String, Error> {
+    res.into_body().read_to_string().block()
+}
+
+#[test]
+fn fresh_hit() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr, hits) = start()?;
+
+    let mut agent = Agent::new();
+    agent.cache(MemoryCache::default());
+
+    let res = get(&agent, addr, "/fresh")?;
+    assert_eq!(res.cache_status(), Some(CacheStatus::Miss));
+    assert_eq!(read(res)?, "fresh 0");
+
+    let res = get(&agent, addr, "/fresh")?;
+    assert_eq!(res.cache_status(), Some(CacheStatus::Hit));
+    assert!(res.header("age").is_some());
+    assert_eq!(read(res)?, "fresh 0");
+
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+    // the request can demand a revalidation.
+    let uri = format!("http://127.0.0.1:{}/fresh", addr.port());
+    let req = http::Request::get(uri)
+        .header("cache-control", "no-cache")
+        .body(())?;
+    let res = agent.send(req).block()?;
+    assert_eq!(res.cache_status(), Some(CacheStatus::Miss));
+    assert_eq!(read(res)?, "fresh 1");
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn revalidate_with_etag() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr, hits) = start()?;
+
+    let mut agent = Agent::new();
+    agent.cache(MemoryCache::default());
+
+    let res = get(&agent, addr, "/etag")?;
+    assert_eq!(res.cache_status(), Some(CacheStatus::Miss));
+    assert_eq!(read(res)?, "tagged");
+
+    let res = get(&agent, addr, "/etag")?;
+    assert_eq!(res.cache_status(), Some(CacheStatus::Revalidated));
+    assert_eq!(res.status_code(), 200);
+    assert_eq!(res.header("x-extra"), Some("updated"));
+    assert_eq!(read(res)?, "tagged");
+
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn vary_on_request_header() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr, hits) = start()?;
+
+    let mut agent = Agent::new();
+    agent.cache(MemoryCache::default());
+
+    let uri = format!("http://127.0.0.1:{}/vary", addr.port());
+    let send = |lang: &str| -> Result<(Option<CacheStatus>, String), Error> {
+        let req = http::Request::get(&uri)
+            .header("accept-language", lang)
+            .body(())?;
+        let res = agent.send(req).block()?;
+        Ok((res.cache_status(), read(res)?))
+    };
+
+    assert_eq!(send("en")?, (Some(CacheStatus::Miss), "en".to_string()));
+    assert_eq!(send("en")?, (Some(CacheStatus::Hit), "en".to_string()));
+    assert_eq!(send("sv")?, (Some(CacheStatus::Miss), "sv".to_string()));
+
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn no_store() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr, hits) = start()?;
+
+    let mut agent = Agent::new();
+    agent.cache(MemoryCache::default());
+
+    for _ in 0..2 {
+        let res = get(&agent, addr, "/nostore")?;
+        assert_eq!(res.cache_status(), Some(CacheStatus::Miss));
+        assert_eq!(read(res)?, "secret");
+    }
+
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn unsafe_method_invalidates() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr, hits) = start()?;
+
+    let mut agent = Agent::new();
+    agent.cache(MemoryCache::default());
+
+    read(get(&agent, addr, "/fresh")?)?;
+
+    // a failed unsafe request changes nothing.
+    let uri = format!("http://127.0.0.1:{}/fresh", addr.port());
+    let res = agent.send(http::Request::post(&uri).body(())?).block()?;
+    assert!(!res.status().is_success());
+    read(res)?;
+
+    let res = get(&agent, addr, "/fresh")?;
+    assert_eq!(res.cache_status(), Some(CacheStatus::Hit));
+    assert_eq!(read(res)?, "fresh 0");
+
+    let res = agent.send(http::Request::delete(&uri).body(())?).block()?;
+    assert_eq!(read(res)?, "deleted");
+
+    let res = get(&agent, addr, "/fresh")?;
+    assert_eq!(res.cache_status(), Some(CacheStatus::Miss));
+    assert_eq!(read(res)?, "fresh 1");
+
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn compressed_body_stored_raw() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr, hits) = start()?;
+
+    let mut agent = Agent::new();
+    agent.cache(MemoryCache::default());
+
+    for status in &[CacheStatus::Miss, CacheStatus::Hit] {
+        let res = get(&agent, addr, "/compressed")?;
+        assert_eq!(res.cache_status(), Some(*status));
+        assert!(res.header("content-encoding").is_some());
+        assert_eq!(read(res)?, "squeezed ".repeat(100));
+    }
+
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn disk_cache_across_agents() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr, hits) = start()?;
+
+    let dir = std::env::temp_dir().join(format!("hreq-cache-test-{}", rand::random::<u64>()));
+
+    let mut agent = Agent::new();
+    agent.cache(DiskCache::new(&dir));
+    let res = get(&agent, addr, "/fresh")?;
+    assert_eq!(res.cache_status(), Some(CacheStatus::Miss));
+    assert_eq!(read(res)?, "fresh 0");
+
+    let mut agent = Agent::new();
+    agent.cache(DiskCache::new(&dir));
+    let res = get(&agent, addr, "/fresh")?;
+    assert_eq!(res.cache_status(), Some(CacheStatus::Hit));
+    assert_eq!(read(res)?, "fresh 0");
+
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+    std::fs::remove_dir_all(&dir).ok();
+    shut.shutdown().block();
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use hreq::prelude::*;
+use hreq::{AsyncRuntime, Error};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+mod common;
+
+#[test]
+fn request_body() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/echo")
+        .all(|req: http::Request<Body>| async move {
+            let chunked = req.header("transfer-encoding").unwrap_or("-").to_string();
+            let body = req.into_body().read_to_string().await.unwrap();
+            format!("{} {}", chunked, body)
+        });
+    let (shut, addr) = server.listen(0).block()?;
+
+    for http2 in &[false, true] {
+        let (mut sender, body) = Body::channel();
+
+        AsyncRuntime::spawn(async move {
+            for i in 0..3 {
+                sender
+                    .send_data(format!("{};", i).as_bytes())
+                    .await
+                    .unwrap();
+            }
+            let mut trailers = http::HeaderMap::new();
+            trailers.insert("x-count", "3".parse().unwrap());
+            sender.send_trailers(trailers);
+        });
+
+        let res = http::Request::post(common::url(addr, "/echo"))
+            .force_http2(*http2)
+            .send(body)
+            .block()?;
+        let text = res.into_body().read_to_string().block()?;
+
+        if *http2 {
+            assert_eq!(text, "- 0;1;2;");
+        } else {
+            assert_eq!(text, "chunked 0;1;2;");
+        }
+    }
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn response_body_backpressure() -> Result<(), Error> {
+    common::setup_logger();
+
+    const CHUNK: usize = 64 * 1024;
+    const TOTAL: usize = 1024 * CHUNK;
+
+    let sent = Arc::new(AtomicUsize::new(0));
+
+    let mut server = Server::with_state(sent.clone());
+    server.at("/stream").with_state().get(
+        |sent: Arc<AtomicUsize>, _req: http::Request<Body>| async move {
+            let (mut sender, body) = Body::channel();
+
+            AsyncRuntime::spawn(async move {
+                let chunk = vec![42_u8; CHUNK];
+                while sent.load(Ordering::SeqCst) < TOTAL {
+                    if sender.send_data(&chunk).await.is_err() {
+                        // client went away.
+                        break;
+                    }
+                    sent.fetch_add(CHUNK, Ordering::SeqCst);
+                }
+            });
+
+            body
+        },
+    );
+    let (shut, addr) = server.listen(0).block()?;
+
+    let res = http::Request::get(common::url(addr, "/stream"))
+        .call()
+        .block()?;
+    assert_eq!(res.header("transfer-encoding"), Some("chunked"));
+
+    // nothing reads the response, the sender must be held back.
+    std::thread::sleep(Duration::from_millis(500));
+    assert!(sent.load(Ordering::SeqCst) < TOTAL);
+
+    let mut body = res.into_body();
+    let mut buf = vec![0; CHUNK];
+    let amt = body.read(&mut buf).block()?;
+    assert!(amt > 0);
+    assert_eq!(buf[0], 42);
+
+    drop(body);
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn response_body_abort() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/abort")
+        .get(|_req: http::Request<Body>| async move {
+            let (mut sender, body) = Body::channel();
+
+            AsyncRuntime::spawn(async move {
+                sender.send_data(b"partial").await.unwrap();
+                sender.abort(Error::Proto("producer failed".into()));
+            });
+
+            body
+        });
+    let (shut, addr) = server.listen(0).block()?;
+
+    for http2 in &[false, true] {
+        let res = http::Request::get(common::url(addr, "/abort"))
+            .force_http2(*http2)
+            .call()
+            .block()?;
+        assert_eq!(res.status_code(), 200);
+
+        let err = res.into_body().read_to_vec(1024).block();
+        assert!(err.is_err(), "http2: {}", http2);
+    }
+
+    shut.shutdown().block();
+    Ok(())
+}
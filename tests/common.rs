@@ -3,6 +3,7 @@
 use rand::Rng;
 use std::io;
 use std::io::Read;
+use std::net::SocketAddr;
 use std::pin::Pin;
 use std::sync::Once;
 use std::task::{Context, Poll};
@@ -44,6 +45,11 @@ impl AsyncRead for DataGenerator {
     }
 }
 
+/// A url to `path` on a server listening on localhost.
+pub fn url(addr: SocketAddr, path: &str) -> String {
+    format!("http://127.0.0.1:{}{}", addr.port(), path)
+}
+
 pub fn setup_logger() {
     static START: Once = Once::new();
     START.call_once(|| {
@@ -0,0 +1,134 @@
+use hreq::prelude::*;
+use hreq::server::Compress;
+use hreq::Error;
+
+mod common;
+
+fn big_text() -> String {
+    "Hello compressed world! ".repeat(100)
+}
+
+fn server() -> Server<()> {
+    let mut server = Server::new();
+
+    server
+        .at("/text")
+        .middleware(Compress::new())
+        .get(|_: http::Request<Body>| async move { big_text() });
+
+    server
+        .at("/small")
+        .middleware(Compress::new())
+        .get(|_: http::Request<Body>| async move { "Hello" });
+
+    server
+        .at("/image")
+        .middleware(Compress::new())
+        .get(|_: http::Request<Body>| async move {
+            http::Response::builder()
+                .header("content-type", "image/png")
+                .body(vec![42_u8; 4096])
+                .unwrap()
+        });
+
+    server
+        .at("/length")
+        .middleware(Compress::new().min_size(10))
+        .get(|_: http::Request<Body>| async move {
+            let text = big_text();
+            http::Response::builder()
+                .header("content-type", "text/plain")
+                .header("content-length", text.len().to_string())
+                .prebuffer_response_body(false)
+                .body(text)
+                .unwrap()
+        });
+
+    server
+}
+
+#[test]
+#[cfg(feature = "gzip")]
+fn compress_negotiated() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr) = server().listen(0).block()?;
+    let uri = format!("http://127.0.0.1:{}/text", addr.port());
+
+    let res = http::Request::get(&uri)
+        .header("accept-encoding", "deflate;q=0.5, gzip, br;q=0")
+        .call()
+        .block()?;
+
+    assert_eq!(res.header("content-encoding"), Some("gzip"));
+    assert_eq!(res.header("vary"), Some("accept-encoding"));
+    let len: usize = res.header_as("content-length").unwrap();
+    assert!(len < big_text().len());
+    assert_eq!(res.into_body().read_to_string().block()?, big_text());
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn compress_not_accepted() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr) = server().listen(0).block()?;
+    let uri = format!("http://127.0.0.1:{}/text", addr.port());
+
+    let res = http::Request::get(&uri)
+        .header("accept-encoding", "identity")
+        .call()
+        .block()?;
+
+    assert_eq!(res.header("content-encoding"), None);
+    assert_eq!(res.header("vary"), Some("accept-encoding"));
+    assert_eq!(res.into_body().read_to_string().block()?, big_text());
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn compress_skipped() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr) = server().listen(0).block()?;
+
+    for path in &["/small", "/image"] {
+        let uri = format!("http://127.0.0.1:{}{}", addr.port(), path);
+        let res = http::Request::get(&uri)
+            .header("accept-encoding", "gzip, br, deflate, zstd")
+            .call()
+            .block()?;
+
+        assert_eq!(res.header("content-encoding"), None);
+        assert_eq!(res.header("vary"), None);
+    }
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "gzip")]
+fn compress_drops_content_length() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr) = server().listen(0).block()?;
+    let uri = format!("http://127.0.0.1:{}/length", addr.port());
+
+    let res = http::Request::get(&uri)
+        .header("accept-encoding", "gzip")
+        .call()
+        .block()?;
+
+    assert_eq!(res.header("content-encoding"), Some("gzip"));
+    assert_eq!(res.header("content-length"), None);
+    assert_eq!(res.header("transfer-encoding"), Some("chunked"));
+    assert_eq!(res.into_body().read_to_string().block()?, big_text());
+
+    shut.shutdown().block();
+    Ok(())
+}
@@ -1,6 +1,6 @@
 use hreq::prelude::*;
-use hreq::Agent;
 use hreq::Error;
+use hreq::{Agent, CookieJar, CookieStore};
 
 mod common;
 
@@ -10,7 +10,7 @@ fn cookie_simple() -> Result<(), Error> {
 
     let mut server = Server::new();
 
-    let mut agent = Agent::new();
+    let agent = Agent::new();
 
     server
         .at("/path1")
@@ -43,7 +43,7 @@ fn cookie_simple() -> Result<(), Error> {
     // check cookies set in /path1 are indeed in agent
     let cookies = agent.get_cookies(&uri1);
     assert!(cookies.len() == 1);
-    let cookie = cookies[0];
+    let cookie = &cookies[0];
     assert_eq!(cookie.name(), "Foo");
     assert_eq!(cookie.value(), "Bar Baz");
 
@@ -65,7 +65,7 @@ fn cookie_for_another_domain() -> Result<(), Error> {
 
     let mut server = Server::new();
 
-    let mut agent = Agent::new();
+    let agent = Agent::new();
 
     server
         .at("/path1")
@@ -114,7 +114,7 @@ fn cookie_with_shared_domain() -> Result<(), Error> {
 
     let mut server = Server::new();
 
-    let mut agent = Agent::new();
+    let agent = Agent::new();
 
     server
         .at("/path1")
@@ -163,7 +163,7 @@ fn cookie_with_different_path() -> Result<(), Error> {
 
     let mut server = Server::new();
 
-    let mut agent = Agent::new();
+    let agent = Agent::new();
 
     server
         .at("/cookie/path1")
@@ -212,7 +212,7 @@ fn cookie_with_matching_path() -> Result<(), Error> {
 
     let mut server = Server::new();
 
-    let mut agent = Agent::new();
+    let agent = Agent::new();
 
     server
         .at("/cookie/path1")
@@ -254,3 +254,125 @@ fn cookie_with_matching_path() -> Result<(), Error> {
     shut.shutdown().block();
     Ok(())
 }
+
+#[test]
+fn cookie_path_is_not_a_string_prefix() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+
+    let agent = Agent::new();
+
+    server
+        .at("/docs/set")
+        .all(|_: http::Request<Body>| async move {
+            http::Response::builder()
+                .header("set-cookie", "Foo=Bar; Path=/docs")
+                .body("Ok1")
+                .unwrap()
+        });
+
+    server
+        .at("/:dir/page")
+        .all(|req: http::Request<Body>| async move {
+            req.header("cookie").unwrap_or("none").to_string()
+        });
+
+    let (shut, addr) = server.listen(0).block()?;
+
+    let send = |path: &str| -> Result<String, Error> {
+        let uri = format!("https://some.host.com{}", path);
+        let req = http::Request::get(uri)
+            .with_override(&addr.ip().to_string(), addr.port(), false)
+            .body(())?;
+        agent
+            .send(req)
+            .block()?
+            .into_body()
+            .read_to_string()
+            .block()
+    };
+
+    send("/docs/set")?;
+
+    assert_eq!(send("/docs/page")?, "Foo=Bar");
+    assert_eq!(send("/docsets/page")?, "none");
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn cookie_store_save_and_load() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+
+    server
+        .at("/login")
+        .all(|_: http::Request<Body>| async move {
+            http::Response::builder()
+                .header("set-cookie", "session=a%20b; Max-Age=3600; Secure")
+                .header("set-cookie", "theme=dark; Path=/")
+                .body("Ok1")
+                .unwrap()
+        });
+
+    server
+        .at("/page")
+        .all(|req: http::Request<Body>| async move {
+            let mut cookies: Vec<_> = req.headers().get_all("cookie").iter().collect();
+            cookies.sort();
+            format!("{:?}", cookies)
+        });
+
+    let (shut, addr) = server.listen(0).block()?;
+
+    let send = |agent: &Agent, path: &str| -> Result<String, Error> {
+        let uri = format!("https://some.host.com{}", path);
+        let req = http::Request::get(uri)
+            .with_override(&addr.ip().to_string(), addr.port(), false)
+            .body(())?;
+        agent
+            .send(req)
+            .block()?
+            .into_body()
+            .read_to_string()
+            .block()
+    };
+
+    let agent = Agent::new();
+    send(&agent, "/login")?;
+    let expected = send(&agent, "/page")?;
+    assert_eq!(expected, "[\"session=a%20b\", \"theme=dark\"]");
+
+    let mut json = vec![];
+    agent.get_cookie_store().save_json(&mut json)?;
+    let mut txt = vec![];
+    agent.get_cookie_store().save_netscape(&mut txt)?;
+
+    for (saved, is_json) in &[(json, true), (txt, false)] {
+        let jar = CookieJar::new();
+        if *is_json {
+            jar.load_json(&mut &saved[..])?;
+        } else {
+            jar.load_netscape(&mut &saved[..])?;
+        }
+        assert_eq!(jar.all().len(), 2);
+
+        let mut agent = Agent::new();
+        agent.cookie_store(jar);
+        assert_eq!(send(&agent, "/page")?, expected);
+    }
+
+    // expired cookies are evicted, and removed ones are gone.
+    let store = agent.get_cookie_store();
+    store.remove("some.host.com", "/", "theme");
+    store.evict_expired();
+    assert_eq!(store.all().len(), 1);
+    store.clear();
+    assert!(store.all().is_empty());
+
+    shut.shutdown().block();
+    Ok(())
+}
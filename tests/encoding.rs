@@ -0,0 +1,188 @@
+use hreq::prelude::*;
+use hreq::Error;
+
+mod common;
+
+#[cfg(any(
+    feature = "gzip",
+    feature = "brotli",
+    feature = "deflate",
+    feature = "zstd"
+))]
+const TEXT: &str = "request that is compressed, compressed, compressed";
+
+#[cfg(any(
+    feature = "gzip",
+    feature = "brotli",
+    feature = "deflate",
+    feature = "zstd"
+))]
+fn roundtrip(encoding: &'static str) -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+
+    server
+        .at("/path")
+        .all(move |req: http::Request<Body>| async move {
+            assert_eq!(req.header("content-encoding"), Some(encoding));
+            let s = req.into_body().read_to_string().await?;
+            assert_eq!(s, TEXT);
+            let res = http::Response::builder()
+                .header("content-encoding", encoding)
+                .body(s)
+                .unwrap();
+            Ok::<_, Error>(res)
+        });
+
+    let (shut, addr) = server.listen(0).block()?;
+
+    let uri = format!("http://127.0.0.1:{}/path", addr.port());
+    let res = http::Request::post(uri)
+        .header("content-encoding", encoding)
+        .send(TEXT)
+        .block()?;
+
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.header("content-encoding"), Some(encoding));
+    assert_eq!(res.into_body().read_to_string().block()?, TEXT);
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "gzip")]
+fn gzip_roundtrip() -> Result<(), Error> {
+    roundtrip("gzip")
+}
+
+#[test]
+#[cfg(feature = "brotli")]
+fn brotli_roundtrip() -> Result<(), Error> {
+    roundtrip("br")
+}
+
+#[test]
+#[cfg(feature = "deflate")]
+fn deflate_roundtrip() -> Result<(), Error> {
+    roundtrip("deflate")
+}
+
+#[test]
+#[cfg(feature = "zstd")]
+fn zstd_roundtrip() -> Result<(), Error> {
+    roundtrip("zstd")
+}
+
+#[test]
+#[cfg(all(feature = "gzip", feature = "brotli"))]
+fn stacked_roundtrip() -> Result<(), Error> {
+    roundtrip("gzip, br")
+}
+
+#[test]
+#[cfg(all(feature = "gzip", feature = "brotli"))]
+fn stacked_order() -> Result<(), Error> {
+    use async_compression::futures::bufread::{BrotliDecoder, GzipDecoder};
+    use futures_util::io::AsyncReadExt;
+    use futures_util::io::BufReader;
+    use futures_util::io::Cursor;
+
+    common::setup_logger();
+
+    let mut server = Server::new();
+
+    server.at("/path").all(|_: http::Request<Body>| async move {
+        http::Response::builder()
+            .header("content-encoding", "gzip, br")
+            .body(TEXT)
+            .unwrap()
+    });
+
+    let req = http::Request::get("/path").content_decode(false).body(())?;
+    let res = server.handle(req).block()?;
+    let vec = res.into_body().read_to_vec(1024).block()?;
+
+    // gzip is applied first, which means br is the outer layer.
+    let brotli = BrotliDecoder::new(BufReader::new(Cursor::new(vec)));
+    let mut gzip = GzipDecoder::new(BufReader::new(brotli));
+    let mut s = String::new();
+    gzip.read_to_string(&mut s).block()?;
+
+    assert_eq!(s, TEXT);
+    Ok(())
+}
+
+#[test]
+fn unknown_encoding() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+
+    server.at("/path").all(|_: http::Request<Body>| async move {
+        http::Response::builder()
+            .header("content-encoding", "gzip, magic")
+            .body("not compressed")
+            .unwrap()
+    });
+
+    let req = http::Request::get("/path").body(())?;
+    let res = server.handle(req).block()?;
+
+    assert_eq!(res.into_body().read_to_string().block()?, "not compressed");
+    Ok(())
+}
+
+#[test]
+fn accept_encoding() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+
+    server
+        .at("/path")
+        .all(|req: http::Request<Body>| async move {
+            req.header("accept-encoding").unwrap_or("none").to_string()
+        });
+
+    let (shut, addr) = server.listen(0).block()?;
+    let uri = format!("http://127.0.0.1:{}/path", addr.port());
+
+    let mut expected = vec![];
+    if cfg!(feature = "brotli") {
+        expected.push("br");
+    }
+    if cfg!(feature = "zstd") {
+        expected.push("zstd");
+    }
+    if cfg!(feature = "gzip") {
+        expected.push("gzip");
+    }
+    if cfg!(feature = "deflate") {
+        expected.push("deflate");
+    }
+    let expected = if expected.is_empty() {
+        "none".to_string()
+    } else {
+        expected.join(", ")
+    };
+
+    let res = http::Request::get(&uri).call().block()?;
+    assert_eq!(res.into_body().read_to_string().block()?, expected);
+
+    let res = http::Request::get(&uri)
+        .header("accept-encoding", "identity")
+        .call()
+        .block()?;
+    assert_eq!(res.into_body().read_to_string().block()?, "identity");
+
+    let res = http::Request::get(&uri)
+        .content_decode(false)
+        .call()
+        .block()?;
+    assert_eq!(res.into_body().read_to_string().block()?, "none");
+
+    shut.shutdown().block();
+    Ok(())
+}
@@ -0,0 +1,300 @@
+use hreq::prelude::*;
+use hreq::Agent;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod common;
+
+#[test]
+fn expect_continue_body_read() -> Result<(), hreq::Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/path")
+        .post(|mut req: http::Request<Body>| async move {
+            assert_eq!(req.header("expect"), Some("100-continue"));
+            let body = req.body_mut().read_to_vec(1_000_000).await?;
+            Result::<_, hreq::Error>::Ok(format!("{}", body.len()))
+        });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let start = Instant::now();
+
+    let uri = format!("http://127.0.0.1:{}/path", addr.port());
+    let mut res = http::Request::post(uri)
+        .expect_continue(true)
+        .expect_continue_timeout(Duration::from_secs(10))
+        .send(vec![42_u8; 100_000])
+        .block()?;
+
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.body_mut().read_to_string().block()?, "100000");
+
+    // the 100 Continue arrived, we did not wait for the timeout.
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn expect_continue_rejected() -> Result<(), hreq::Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/reject")
+        .post(|_: http::Request<Body>| async move {
+            http::Response::builder().status(401).body("No").unwrap()
+        });
+    server
+        .at("/path")
+        .get(|_: http::Request<Body>| async move { "Ok" });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let uri = format!("http://127.0.0.1:{}", addr.port());
+    let agent = Agent::new();
+
+    let read = Arc::new(AtomicUsize::new(0));
+    let body = Body::from_sync_read(
+        CountReader(read.clone(), io::repeat(42).take(10_000_000)),
+        Some(10_000_000),
+    );
+
+    let req = http::Request::post(format!("{}/reject", uri))
+        .expect_continue(true)
+        .expect_continue_timeout(Duration::from_secs(10))
+        .with_body(body)?;
+    let mut res = agent.send(req).block()?;
+
+    assert_eq!(res.status(), 401);
+    assert_eq!(res.body_mut().read_to_string().block()?, "No");
+    // at most the prebuffer attempt read from the body.
+    assert!(read.load(Ordering::SeqCst) < 1_000_000);
+
+    // the agent is still usable after aborting the body.
+    let req = http::Request::get(format!("{}/path", uri)).with_body(())?;
+    let mut res = agent.send(req).block()?;
+    assert_eq!(res.body_mut().read_to_string().block()?, "Ok");
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn expect_continue_below_threshold() -> Result<(), hreq::Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/path")
+        .post(|mut req: http::Request<Body>| async move {
+            let expect = req.header("expect").map(|s| s.to_string());
+            let body = req.body_mut().read_to_vec(1_000_000).await?;
+            Result::<_, hreq::Error>::Ok(format!("{:?} {}", expect, body.len()))
+        });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let uri = format!("http://127.0.0.1:{}/path", addr.port());
+
+    let mut res = http::Request::post(&uri)
+        .expect_continue_above(1000)
+        .send(vec![42_u8; 10])
+        .block()?;
+    assert_eq!(res.body_mut().read_to_string().block()?, "None 10");
+
+    let mut res = http::Request::post(&uri)
+        .expect_continue_above(1000)
+        .expect_continue_timeout(Duration::from_secs(10))
+        .send(vec![42_u8; 2000])
+        .block()?;
+    assert_eq!(
+        res.body_mut().read_to_string().block()?,
+        "Some(\"100-continue\") 2000"
+    );
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn expect_continue_raw_server() -> Result<(), hreq::Error> {
+    common::setup_logger();
+
+    let port = raw_server(|head, stream| {
+        assert!(head.contains("expect: 100-continue"));
+        stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").unwrap();
+        read_body(stream, 5)
+    });
+
+    let start = Instant::now();
+
+    let uri = format!("http://127.0.0.1:{}/path", port);
+    let mut res = http::Request::post(uri)
+        .expect_continue(true)
+        .expect_continue_timeout(Duration::from_secs(10))
+        .send("hello")
+        .block()?;
+
+    assert_eq!(res.body_mut().read_to_string().block()?, "hello");
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    Ok(())
+}
+
+#[test]
+fn expect_continue_timeout() -> Result<(), hreq::Error> {
+    common::setup_logger();
+
+    // a server that doesn't know about 100-continue, it just reads the body.
+    let port = raw_server(|_, stream| read_body(stream, 5));
+
+    let start = Instant::now();
+
+    let uri = format!("http://127.0.0.1:{}/path", port);
+    let mut res = http::Request::post(uri)
+        .expect_continue(true)
+        .expect_continue_timeout(Duration::from_millis(100))
+        .send("hello")
+        .block()?;
+
+    assert_eq!(res.body_mut().read_to_string().block()?, "hello");
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    Ok(())
+}
+
+#[test]
+fn expect_continue_server_raw_client() -> Result<(), hreq::Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/read")
+        .post(|mut req: http::Request<Body>| async move { req.body_mut().read_to_string().await });
+    server
+        .at("/reject")
+        .post(|_: http::Request<Body>| async move {
+            http::Response::builder().status(413).body("").unwrap()
+        });
+    let (shut, addr) = server.listen(0).block()?;
+
+    // the server runs on the same runtime, a blocking client must run aside.
+    async move { tokio::task::spawn_blocking(move || raw_client(addr)).await }
+        .block()
+        .unwrap()?;
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+fn raw_client(addr: std::net::SocketAddr) -> io::Result<()> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    // rejected without reading the body, no 100 Continue.
+    stream.write_all(
+        b"POST /reject HTTP/1.1\r\nhost: localhost\r\n\
+        content-length: 5\r\nexpect: 100-continue\r\nconnection: close\r\n\r\n",
+    )?;
+    assert_eq!(read_status(&mut reader), "HTTP/1.1 413 Payload Too Large");
+
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    // a body that is never sent is not waited for, the connection is closed.
+    stream.write_all(
+        b"POST /reject HTTP/1.1\r\nhost: localhost\r\n\
+        content-length: 18446744073709551615\r\nexpect: 100-continue\r\n\r\n",
+    )?;
+    let mut rest = String::new();
+    reader.read_to_string(&mut rest)?;
+    assert!(
+        rest.starts_with("HTTP/1.1 413 Payload Too Large\r\n"),
+        "{}",
+        rest
+    );
+    assert!(rest.contains("connection: close\r\n"), "{}", rest);
+
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    // the handler reads the body, 100 Continue first.
+    stream.write_all(
+        b"POST /read HTTP/1.1\r\nhost: localhost\r\n\
+        content-length: 5\r\nexpect: 100-continue\r\n\r\n",
+    )?;
+    assert_eq!(read_status(&mut reader), "HTTP/1.1 100 Continue");
+    let mut empty = String::new();
+    reader.read_line(&mut empty)?;
+    assert_eq!(empty, "\r\n");
+
+    stream.write_all(b"hello")?;
+    assert_eq!(read_status(&mut reader), "HTTP/1.1 200 OK");
+
+    Ok(())
+}
+
+/// Reader that counts the bytes read from it.
+struct CountReader<R>(Arc<AtomicUsize>, R);
+
+impl<R: Read> Read for CountReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let amount = self.1.read(buf)?;
+        self.0.fetch_add(amount, Ordering::SeqCst);
+        Ok(amount)
+    }
+}
+
+/// Accepts one connection, reads the request head and responds with whatever
+/// the handler returns as body.
+fn raw_server<F>(handler: F) -> u16
+where
+    F: FnOnce(&str, &mut TcpStream) -> String + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+
+        let mut head = vec![];
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut buf = [0_u8; 1];
+            stream.read_exact(&mut buf).unwrap();
+            head.push(buf[0]);
+        }
+        let head = String::from_utf8(head).unwrap();
+
+        let body = handler(&head, &mut stream);
+
+        let res = format!(
+            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(res.as_bytes()).unwrap();
+    });
+
+    port
+}
+
+fn read_body(stream: &mut TcpStream, len: usize) -> String {
+    let mut buf = vec![0; len];
+    stream.read_exact(&mut buf).unwrap();
+    String::from_utf8(buf).unwrap()
+}
+
+fn read_status(reader: &mut impl BufRead) -> String {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    line.trim_end().to_string()
+}
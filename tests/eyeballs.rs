@@ -0,0 +1,71 @@
+use hreq::prelude::*;
+use hreq::{Agent, Error};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpSocket};
+
+mod common;
+
+/// A listener that never accepts, with a full backlog, which means connection
+/// attempts hang like for a broken route.
+fn black_hole() -> Result<(TcpListener, TcpStream, SocketAddr), Error> {
+    let listener = async {
+        let socket = TcpSocket::new_v4()?;
+        socket.bind("127.0.0.1:0".parse().unwrap())?;
+        socket.listen(0)
+    }
+    .block()?;
+    let addr = listener.local_addr()?;
+    let filler = TcpStream::connect(addr)?;
+    Ok((listener, filler, addr))
+}
+
+#[test]
+fn fallback_on_hanging_address() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/path")
+        .get(|_: http::Request<Body>| async move { "ok" });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let (_listener, _filler, hole) = black_hole()?;
+
+    let mut agent = Agent::new();
+    agent.happy_eyeballs_delay(Duration::from_millis(100));
+    agent.resolve_to("eyeballs.test", 80, &[hole, addr]);
+
+    let start = Instant::now();
+    let req = http::Request::get("http://eyeballs.test/path")
+        .timeout(Duration::from_secs(10))
+        .body(())?;
+    let res = agent.send(req).block()?;
+
+    assert_eq!(res.into_body().read_to_string().block()?, "ok");
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn connect_timeout() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (_listener, _filler, hole) = black_hole()?;
+
+    let mut agent = Agent::new();
+    agent.retries(0);
+    agent.connect_timeout(Some(Duration::from_millis(100)));
+    agent.resolve_to("eyeballs.test", 80, &[hole]);
+
+    let req = http::Request::get("http://eyeballs.test/path")
+        .timeout(Duration::from_secs(10))
+        .body(())?;
+    let err = agent.send(req).block().unwrap_err();
+
+    assert!(err.is_timeout());
+
+    Ok(())
+}
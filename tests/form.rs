@@ -0,0 +1,115 @@
+use hreq::prelude::*;
+use hreq::Error;
+use serde_derive::{Deserialize, Serialize};
+
+mod common;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Login {
+    username: String,
+    password: String,
+    remember: bool,
+}
+
+#[test]
+fn form_send_and_read() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/login")
+        .post(|req: http::Request<Body>| async move {
+            assert_eq!(
+                req.header("content-type"),
+                Some("application/x-www-form-urlencoded")
+            );
+            let form: Login = req.into_body().read_to_form().await?;
+            Result::<_, Error>::Ok(format!("{:?}", form))
+        });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let login = Login {
+        username: "Åsa & co".into(),
+        password: "p=w d+".into(),
+        remember: true,
+    };
+
+    let uri = format!("http://127.0.0.1:{}/login", addr.port());
+    let mut res = http::Request::post(uri).send_form(&login).block()?;
+
+    assert_eq!(res.status(), 200);
+    assert_eq!(
+        res.body_mut().read_to_string().block()?,
+        format!("{:?}", login)
+    );
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn form_latin1() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/raw")
+        .post(|req: http::Request<Body>| async move { req.into_body().read_to_string().await });
+    server
+        .at("/form")
+        .post(|req: http::Request<Body>| async move {
+            let form: Vec<(String, String)> = req.into_body().read_to_form().await?;
+            Result::<_, Error>::Ok(format!("{:?}", form))
+        });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let form = [("name", "Åsa Öst"), ("city", "Malmö")];
+
+    let send = |path: &str| {
+        http::Request::post(format!("http://127.0.0.1:{}{}", addr.port(), path))
+            .header(
+                "content-type",
+                "application/x-www-form-urlencoded; charset=iso-8859-1",
+            )
+            .send_form(&form)
+    };
+
+    let mut res = send("/raw").block()?;
+    assert_eq!(
+        res.body_mut().read_to_string().block()?,
+        "name=%C5sa+%D6st&city=Malm%F6"
+    );
+
+    let mut res = send("/form").block()?;
+    assert_eq!(
+        res.body_mut().read_to_string().block()?,
+        "[(\"name\", \"Åsa Öst\"), (\"city\", \"Malmö\")]"
+    );
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn form_read_invalid() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/login")
+        .post(|req: http::Request<Body>| async move {
+            let res: Result<Login, Error> = req.into_body().read_to_form().await;
+            let status = if res.is_err() { 400 } else { 200 };
+            http::Response::builder().status(status).body(()).unwrap()
+        });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let uri = format!("http://127.0.0.1:{}/login", addr.port());
+    let res = http::Request::post(uri)
+        .send_form(&[("username", "martin")])
+        .block()?;
+    assert_eq!(res.status(), 400);
+
+    shut.shutdown().block();
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use hreq::prelude::*;
+use hreq::{Agent, Error, Next};
+
+mod common;
+
+#[test]
+fn timings_and_addresses() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/path")
+        .get(|_: http::Request<Body>| async move { "ok" });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let agent = Agent::new();
+    let uri = format!("http://127.0.0.1:{}/path", addr.port());
+
+    let res = agent.send(http::Request::get(&uri).body(())?).block()?;
+
+    assert!(!res.is_connection_reused());
+    assert!(res.dns_time().is_some());
+    assert!(res.connect_time().is_some());
+    assert_eq!(res.tls_time(), None);
+    assert_eq!(res.tls_protocol(), None);
+    assert_eq!(res.alpn_protocol(), None);
+    assert!(res.first_byte_time().unwrap() <= res.total_time().unwrap());
+    assert_eq!(res.connection_version(), Some(http::Version::HTTP_11));
+    assert_eq!(res.remote_addr().map(|a| a.port()), Some(addr.port()));
+    assert!(res.local_addr().unwrap().ip().is_loopback());
+    assert_eq!(res.retries(), 0);
+    assert_eq!(res.redirects(), 0);
+
+    res.into_body().read_and_discard().block()?;
+
+    // second request goes over the pooled connection.
+    let res = agent.send(http::Request::get(&uri).body(())?).block()?;
+
+    assert!(res.is_connection_reused());
+    assert_eq!(res.dns_time(), None);
+    assert_eq!(res.connect_time(), None);
+    assert!(res.first_byte_time().is_some());
+    assert_eq!(res.remote_addr().map(|a| a.port()), Some(addr.port()));
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn redirects() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/path1")
+        .get(|_: http::Request<Body>| async move {
+            http::Response::builder()
+                .status(302)
+                .header("location", "/path2")
+                .body(())
+                .unwrap()
+        });
+    server
+        .at("/path2")
+        .get(|_: http::Request<Body>| async move {
+            http::Response::builder()
+                .status(302)
+                .header("location", "/path3")
+                .body(())
+                .unwrap()
+        });
+    server
+        .at("/path3")
+        .get(|_: http::Request<Body>| async move { "OK" });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let uri = format!("http://127.0.0.1:{}/path1", addr.port());
+    let res = http::Request::get(uri).call().block()?;
+
+    assert_eq!(res.status_code(), 200);
+    assert_eq!(res.redirects(), 2);
+    assert!(res.is_connection_reused());
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn no_metadata_for_synthetic_response() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut agent = Agent::new();
+    agent.middleware(|_: http::Request<Body>, _: Next| async move {
+        Ok(http::Response::builder().body("stub".into()).unwrap())
+    });
+
+    let res = agent
+        .send(http::Request::get("http://stub.test/").body(())?)
+        .block()?;
+
+    assert_eq!(res.total_time(), None);
+    assert_eq!(res.remote_addr(), None);
+    assert!(!res.is_connection_reused());
+
+    Ok(())
+}
@@ -0,0 +1,186 @@
+use hreq::prelude::*;
+use hreq::{Agent, Error, Next};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+mod common;
+
+#[test]
+fn rewrite_request() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/path")
+        .get(|req: http::Request<Body>| async move {
+            req.header("x-token").unwrap_or("none").to_string()
+        });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let mut agent = Agent::new();
+    agent.middleware(|mut req: http::Request<Body>, next: Next| async move {
+        req.headers_mut()
+            .insert("x-token", "secret".parse().unwrap());
+        next.run(req).await
+    });
+
+    let uri = format!("http://127.0.0.1:{}/path", addr.port());
+    let res = agent.send(http::Request::get(uri).body(())?).block()?;
+
+    assert_eq!(res.into_body().read_to_string().block()?, "secret");
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn short_circuit() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut agent = Agent::new();
+    agent.middleware(|req: http::Request<Body>, _: Next| async move {
+        assert_eq!(req.uri().host(), Some("stub.test"));
+        Ok(http::Response::builder()
+            .status(418)
+            .body("stubbed".into())
+            .unwrap())
+    });
+
+    // there is no such host, the middleware answers before any lookup.
+    let res = agent
+        .send(http::Request::get("http://stub.test/path").body(())?)
+        .block()?;
+
+    assert_eq!(res.status_code(), 418);
+    assert_eq!(res.into_body().read_to_string().block()?, "stubbed");
+
+    Ok(())
+}
+
+#[test]
+fn inspect_response() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server.at("/path").get(|_: http::Request<Body>| async move {
+        http::Response::builder()
+            .header("x-version", "2")
+            .body("ok")
+            .unwrap()
+    });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let mut agent = Agent::new();
+    agent.middleware(|req: http::Request<Body>, next: Next| async move {
+        let mut res = next.run(req).await?;
+        let version = res.header("x-version").unwrap().to_string();
+        res.headers_mut().insert("x-seen", version.parse().unwrap());
+        Ok(res)
+    });
+
+    let uri = format!("http://127.0.0.1:{}/path", addr.port());
+    let res = agent.send(http::Request::get(uri).body(())?).block()?;
+
+    assert_eq!(res.header("x-seen"), Some("2"));
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn order_of_middleware() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut agent = Agent::new();
+    agent.middleware(|mut req: http::Request<Body>, next: Next| async move {
+        req.headers_mut().append("x-order", "1".parse().unwrap());
+        next.run(req).await
+    });
+    agent.middleware(|req: http::Request<Body>, _: Next| async move {
+        let order: Vec<_> = req
+            .headers()
+            .get_all("x-order")
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect();
+        Ok(http::Response::builder()
+            .body(order.join(",").into())
+            .unwrap())
+    });
+    agent.middleware(|_: http::Request<Body>, _: Next| async move {
+        panic!("Middleware after short circuit");
+    });
+
+    let res = agent
+        .send(http::Request::get("http://stub.test/").body(())?)
+        .block()?;
+
+    assert_eq!(res.into_body().read_to_string().block()?, "1");
+
+    Ok(())
+}
+
+#[test]
+fn per_hop_on_redirect() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/path1")
+        .get(|_: http::Request<Body>| async move {
+            http::Response::builder()
+                .status(302)
+                .header("location", "/path2")
+                .body(())
+                .unwrap()
+        });
+    server
+        .at("/path2")
+        .get(|_: http::Request<Body>| async move { "OK" });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let once = Arc::new(AtomicUsize::new(0));
+    let hops = Arc::new(AtomicUsize::new(0));
+    let statuses = Arc::new(std::sync::Mutex::new(vec![]));
+
+    let mut agent = Agent::new();
+    {
+        let once = once.clone();
+        agent.middleware(move |req: http::Request<Body>, next: Next| {
+            once.fetch_add(1, Ordering::SeqCst);
+            async move { next.run(req).await }
+        });
+    }
+    {
+        let hops = hops.clone();
+        let statuses = statuses.clone();
+        agent.middleware_per_hop(move |req: http::Request<Body>, next: Next| {
+            hops.fetch_add(1, Ordering::SeqCst);
+            let statuses = statuses.clone();
+            async move {
+                let path = req.uri().path().to_string();
+                let res = next.run(req).await?;
+                statuses
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} {}", path, res.status_code()));
+                Ok(res)
+            }
+        });
+    }
+
+    let uri = format!("http://127.0.0.1:{}/path1", addr.port());
+    let res = agent.send(http::Request::get(uri).body(())?).block()?;
+
+    assert_eq!(res.status_code(), 200);
+    assert_eq!(res.into_body().read_to_string().block()?, "OK");
+    assert_eq!(once.load(Ordering::SeqCst), 1);
+    assert_eq!(hops.load(Ordering::SeqCst), 2);
+    assert_eq!(
+        *statuses.lock().unwrap(),
+        vec!["/path1 302".to_string(), "/path2 200".to_string()]
+    );
+
+    shut.shutdown().block();
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use hreq::prelude::*;
+use hreq::{Multipart, Part};
+
+mod common;
+
+#[test]
+fn multipart_text_and_file() -> Result<(), hreq::Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/path")
+        .post(|req: http::Request<Body>| async move {
+            let ctype = req.header("content-type").unwrap().to_string();
+            let length: u64 = req.header_as("content-length").unwrap();
+            let body = req.into_body().read_to_string().await?;
+            assert_eq!(body.len() as u64, length);
+            Result::<_, hreq::Error>::Ok(format!("{}\n{}", ctype, body))
+        });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let form = Multipart::new()
+        .text("name", "Martin")
+        .file_path("page", "tests/data/index.html")?;
+    let boundary = form.boundary().to_string();
+
+    let uri = format!("http://127.0.0.1:{}/path", addr.port());
+    let mut res = http::Request::post(uri).send_multipart(form).block()?;
+
+    let body = res.body_mut().read_to_string().block()?;
+    let (ctype, body) = body.split_at(body.find('\n').unwrap());
+
+    assert_eq!(ctype, format!("multipart/form-data; boundary={}", boundary));
+
+    let index = std::fs::read_to_string("tests/data/index.html")?;
+    let expected = format!(
+        "\n--{b}\r\n\
+        content-disposition: form-data; name=\"name\"\r\n\r\n\
+        Martin\r\n\
+        --{b}\r\n\
+        content-disposition: form-data; name=\"page\"; filename=\"index.html\"\r\n\
+        content-type: text/html\r\n\r\n\
+        {}\r\n\
+        --{b}--\r\n",
+        index,
+        b = boundary
+    );
+    assert_eq!(body, expected);
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn multipart_unknown_length_is_chunked() -> Result<(), hreq::Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/path")
+        .post(|req: http::Request<Body>| async move {
+            assert_eq!(req.header("content-length"), None);
+            assert_eq!(req.header("transfer-encoding"), Some("chunked"));
+            req.into_body().read_to_string().await
+        });
+    let (shut, addr) = server.listen(0).block()?;
+
+    // a reader big enough to not be prebuffered.
+    let data = vec![b'x'; 1024 * 1024];
+    let part = Part::reader(futures_util::io::Cursor::new(data), None).file_name("big.txt");
+
+    let form = Multipart::new().part("big", part);
+
+    let uri = format!("http://127.0.0.1:{}/path", addr.port());
+    let mut res = http::Request::post(uri).send_multipart(form).block()?;
+
+    let body = res.body_mut().read_to_string().block()?;
+    assert!(body.contains("filename=\"big.txt\"\r\n\r\nxxxx"));
+    assert!(body.contains(&"x".repeat(1024 * 1024)));
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn multipart_server_streaming_parts() -> Result<(), hreq::Error> {
+    use futures_util::io::AsyncReadExt;
+    use hreq::server::MultipartReader;
+
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/upload")
+        .post(|req: http::Request<Body>| async move {
+            let mut form = MultipartReader::new(req)?;
+            let mut out = vec![];
+
+            while let Some(mut part) = form.next_part().await? {
+                let desc = format!(
+                    "{:?} {:?} {:?}",
+                    part.name(),
+                    part.file_name(),
+                    part.content_type()
+                );
+
+                // count the content in small chunks to not hold it in memory.
+                let mut buf = [0_u8; 1000];
+                let mut total = 0;
+                loop {
+                    let amount = part.read(&mut buf).await?;
+                    if amount == 0 {
+                        break;
+                    }
+                    total += amount;
+                }
+
+                out.push(format!("{} {}", desc, total));
+            }
+
+            Result::<_, hreq::Error>::Ok(out.join("\n"))
+        });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let data = vec![42_u8; 3 * 1024 * 1024];
+    let form = Multipart::new().text("title", "Holiday").part(
+        "photo",
+        Part::reader(futures_util::io::Cursor::new(data), Some(3 * 1024 * 1024))
+            .file_name("beach.jpg")
+            .content_type("image/jpeg"),
+    );
+
+    let uri = format!("http://127.0.0.1:{}/upload", addr.port());
+    let mut res = http::Request::post(uri).send_multipart(form).block()?;
+
+    assert_eq!(
+        res.body_mut().read_to_string().block()?,
+        "Some(\"title\") None None 7\n\
+        Some(\"photo\") Some(\"beach.jpg\") Some(\"image/jpeg\") 3145728"
+    );
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn multipart_server_max_size() -> Result<(), hreq::Error> {
+    use hreq::server::MultipartReader;
+
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/upload")
+        .post(|req: http::Request<Body>| async move {
+            let mut form = MultipartReader::new(req).unwrap().max_size(1024);
+
+            let res = async {
+                while let Some(mut part) = form.next_part().await? {
+                    part.read_to_vec(1024 * 1024).await?;
+                }
+                Result::<_, hreq::Error>::Ok(())
+            }
+            .await;
+
+            let status = if res.is_err() { 413 } else { 200 };
+            http::Response::builder().status(status).body(()).unwrap()
+        });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let uri = format!("http://127.0.0.1:{}/upload", addr.port());
+
+    let form = Multipart::new().text("small", "data");
+    let res = http::Request::post(&uri).send_multipart(form).block()?;
+    assert_eq!(res.status(), 200);
+
+    let form = Multipart::new().part("big", Part::bytes(vec![42_u8; 10_000]));
+    let res = http::Request::post(&uri).send_multipart(form).block()?;
+    assert_eq!(res.status(), 413);
+
+    shut.shutdown().block();
+    Ok(())
+}
@@ -0,0 +1,281 @@
+use futures_util::future::join_all;
+use hreq::prelude::*;
+use hreq::Agent;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+mod common;
+
+#[test]
+fn pool_reuse_connection() -> Result<(), hreq::Error> {
+    common::setup_logger();
+
+    let agent = Agent::new();
+
+    let count = run_requests(&agent, 3, None)?;
+    assert_eq!(count, 1);
+
+    Ok(())
+}
+
+#[test]
+fn pool_disabled() -> Result<(), hreq::Error> {
+    common::setup_logger();
+
+    let mut agent = Agent::new();
+    agent.pooling(false);
+
+    let count = run_requests(&agent, 3, None)?;
+    assert_eq!(count, 3);
+
+    Ok(())
+}
+
+#[test]
+fn pool_idle_timeout() -> Result<(), hreq::Error> {
+    common::setup_logger();
+
+    let mut agent = Agent::new();
+    agent.idle_timeout(Some(Duration::from_millis(50)));
+
+    let count = run_requests(&agent, 2, Some(Duration::from_millis(200)))?;
+    assert_eq!(count, 2);
+
+    Ok(())
+}
+
+#[test]
+fn pool_idle_timeout_after_slow_request() -> Result<(), hreq::Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server.at("/slow").get(|_: http::Request<Body>| async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        "Ok"
+    });
+    server
+        .at("/path")
+        .get(|_: http::Request<Body>| async move { "Ok" });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let (port, count) = counting_forwarder(addr.port());
+
+    let mut agent = Agent::new();
+    agent.idle_timeout(Some(Duration::from_millis(200)));
+
+    // the idle time starts when the slow request is finished, not when it's sent.
+    for path in &["slow", "path"] {
+        let req = http::Request::get(format!("http://127.0.0.1:{}/{}", port, path)).with_body(())?;
+        let mut res = agent.send(req).block()?;
+        assert_eq!(res.body_mut().read_to_string().block()?, "Ok");
+    }
+
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn pool_max_idle_per_host() -> Result<(), hreq::Error> {
+    common::setup_logger();
+
+    let mut agent = Agent::new();
+    agent.max_idle_per_host(0);
+
+    let count = run_requests(&agent, 2, None)?;
+    assert_eq!(count, 2);
+
+    Ok(())
+}
+
+#[test]
+fn pool_max_connections_total() -> Result<(), hreq::Error> {
+    common::setup_logger();
+
+    let mut agent = Agent::new();
+    agent.max_connections_total(0);
+
+    let count = run_requests(&agent, 2, None)?;
+    assert_eq!(count, 2);
+
+    Ok(())
+}
+
+#[test]
+fn pool_evict_closed_connection() -> Result<(), hreq::Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server.at("/path").get(|_: http::Request<Body>| async move {
+        http::Response::builder()
+            .header("connection", "close")
+            .body("Ok")
+            .unwrap()
+    });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let (port, count) = counting_forwarder(addr.port());
+
+    let agent = Agent::new();
+
+    for _ in 0..2 {
+        let req = http::Request::get(format!("http://127.0.0.1:{}/path", port)).with_body(())?;
+        let mut res = agent.send(req).block()?;
+        assert_eq!(res.body_mut().read_to_string().block()?, "Ok");
+    }
+
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn agent_is_clone_send_sync() {
+    fn assert_clone_send_sync<T: Clone + Send + Sync>() {}
+    assert_clone_send_sync::<Agent>();
+}
+
+#[test]
+fn agent_concurrent_http1() -> Result<(), hreq::Error> {
+    common::setup_logger();
+
+    let count = run_concurrent(false)?;
+
+    // the warm up connection is reused, then one new per concurrent request.
+    assert_eq!(count, 4);
+
+    Ok(())
+}
+
+#[test]
+fn agent_concurrent_http2() -> Result<(), hreq::Error> {
+    common::setup_logger();
+
+    let count = run_concurrent(true)?;
+
+    // all requests multiplexed over the warm up connection.
+    assert_eq!(count, 1);
+
+    Ok(())
+}
+
+/// Sends one warm up request followed by 4 concurrent requests using clones of the
+/// same agent. Returns how many connections were opened.
+fn run_concurrent(http2: bool) -> Result<usize, hreq::Error> {
+    const CONCURRENT: usize = 4;
+
+    let arrived = Arc::new(AtomicUsize::new(0));
+
+    let mut server = Server::with_state(arrived);
+    server
+        .at("/warm")
+        .get(|_: http::Request<Body>| async move { "Ok" });
+    server.at("/path").with_state().get(
+        |arrived: Arc<AtomicUsize>, _: http::Request<Body>| async move {
+            arrived.fetch_add(1, Ordering::SeqCst);
+            // hold the response until all requests are in flight.
+            for _ in 0..200 {
+                if arrived.load(Ordering::SeqCst) >= CONCURRENT {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            "Ok"
+        },
+    );
+    let (shut, addr) = server.listen(0).block()?;
+
+    let (port, count) = counting_forwarder(addr.port());
+
+    let agent = Agent::new();
+
+    let req = http::Request::get(format!("http://127.0.0.1:{}/warm", port))
+        .force_http2(http2)
+        .with_body(())?;
+    agent
+        .send(req)
+        .block()?
+        .body_mut()
+        .read_to_string()
+        .block()?;
+
+    let reqs = (0..CONCURRENT).map(|_| {
+        let agent = agent.clone();
+        let uri = format!("http://127.0.0.1:{}/path", port);
+        async move {
+            let req = http::Request::get(uri).force_http2(http2).with_body(())?;
+            let mut res = agent.send(req).await?;
+            res.body_mut().read_to_string().await
+        }
+    });
+
+    for body in join_all(reqs).block() {
+        assert_eq!(body?, "Ok");
+    }
+
+    shut.shutdown().block();
+    Ok(count.load(Ordering::SeqCst))
+}
+
+/// Sends a number of sequential requests and returns how many connections were opened.
+fn run_requests(
+    agent: &Agent,
+    amount: usize,
+    pause: Option<Duration>,
+) -> Result<usize, hreq::Error> {
+    let mut server = Server::new();
+    server
+        .at("/path")
+        .get(|_: http::Request<Body>| async move { "Ok" });
+    let (shut, addr) = server.listen(0).block()?;
+
+    let (port, count) = counting_forwarder(addr.port());
+
+    for _ in 0..amount {
+        let req = http::Request::get(format!("http://127.0.0.1:{}/path", port)).with_body(())?;
+        let mut res = agent.send(req).block()?;
+        assert_eq!(res.body_mut().read_to_string().block()?, "Ok");
+
+        if let Some(pause) = pause {
+            thread::sleep(pause);
+        }
+    }
+
+    shut.shutdown().block();
+    Ok(count.load(Ordering::SeqCst))
+}
+
+/// Forwards connections to the target port on localhost, counting them.
+fn counting_forwarder(target_port: u16) -> (u16, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let count = Arc::new(AtomicUsize::new(0));
+
+    let count2 = count.clone();
+    thread::spawn(move || {
+        for client in listener.incoming() {
+            let mut client = client.unwrap();
+            count2.fetch_add(1, Ordering::SeqCst);
+
+            let mut target = TcpStream::connect(("127.0.0.1", target_port)).unwrap();
+            let mut client2 = client.try_clone().unwrap();
+            let mut target2 = target.try_clone().unwrap();
+
+            thread::spawn(move || {
+                let _ = std::io::copy(&mut target2, &mut client2);
+                let _ = client2.shutdown(std::net::Shutdown::Write);
+            });
+            thread::spawn(move || {
+                let _ = std::io::copy(&mut client, &mut target);
+                let _ = target.shutdown(std::net::Shutdown::Write);
+            });
+        }
+    });
+
+    (port, count)
+}
@@ -1,5 +1,6 @@
 use hreq::prelude::*;
-use hreq::Error;
+use hreq::{Agent, Error, RedirectPolicy};
+use std::net::SocketAddr;
 
 mod common;
 
@@ -37,3 +38,181 @@ fn code_302() -> Result<(), Error> {
     shut.shutdown().block();
     Ok(())
 }
+
+/// Server redirecting `/r/<status>` to `target`, and echoing the request at `/echo`.
+fn start(target: String) -> Result<(hreq::server::ServerHandle, SocketAddr), Error> {
+    let mut server = Server::with_state(target);
+
+    server.at("/r/:status").with_state().all(
+        |target: String, req: http::Request<Body>| async move {
+            let status: u16 = req.path_param_as("status").unwrap();
+            http::Response::builder()
+                .status(status)
+                .header("location", target)
+                .body("")
+                .unwrap()
+        },
+    );
+
+    server
+        .at("/echo")
+        .all(|req: http::Request<Body>| async move {
+            let method = req.method().to_string();
+            let creds = ["authorization", "cookie", "proxy-authorization"]
+                .iter()
+                .filter(|h| req.header(h).is_some())
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(",");
+            let body = req.into_body().read_to_string().await.unwrap();
+            format!("{} {} [{}]", method, body, creds)
+        });
+
+    server.listen(0).block()
+}
+
+fn read(res: http::Response<Body>) -> Result<String, Error> {
+    res.into_body().read_to_string().block()
+}
+
+#[test]
+fn method_rewrite() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr) = start("/echo".into())?;
+
+    let send = |method: &str, status: &str| -> Result<String, Error> {
+        let res = http::Request::builder()
+            .method(method)
+            .uri(common::url(addr, &format!("/r/{}", status)))
+            .redirect_body_buffer(1024)
+            .send("data")
+            .block()?;
+        assert_eq!(res.status_code(), 200);
+        read(res)
+    };
+
+    assert_eq!(send("POST", "303")?, "GET  []");
+    assert_eq!(send("PUT", "303")?, "GET  []");
+    assert_eq!(send("POST", "301")?, "GET  []");
+    assert_eq!(send("POST", "302")?, "GET  []");
+    assert_eq!(send("POST", "307")?, "POST data []");
+    assert_eq!(send("PUT", "308")?, "PUT data []");
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn history_and_final_uri() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut_b, addr_b) = start("/echo".into())?;
+    let (shut_a, addr_a) = start(common::url(addr_b, "/r/302"))?;
+
+    let res = http::Request::get(common::url(addr_a, "/r/301"))
+        .call()
+        .block()?;
+    assert_eq!(res.redirects(), 2);
+
+    let history = res.redirect_history();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].status(), 301);
+    assert_eq!(history[0].from().to_string(), common::url(addr_a, "/r/301"));
+    assert_eq!(history[0].to().to_string(), common::url(addr_b, "/r/302"));
+    assert_eq!(history[1].status(), 302);
+    assert_eq!(history[1].to().to_string(), common::url(addr_b, "/echo"));
+
+    assert_eq!(
+        res.final_uri().map(|u| u.to_string()),
+        Some(common::url(addr_b, "/echo"))
+    );
+    assert_eq!(read(res)?, "GET  []");
+
+    // not redirected at all.
+    let res = http::Request::get(common::url(addr_b, "/echo"))
+        .call()
+        .block()?;
+    assert!(res.redirect_history().is_empty());
+    assert_eq!(
+        res.final_uri().map(|u| u.to_string()),
+        Some(common::url(addr_b, "/echo"))
+    );
+
+    shut_a.shutdown().block();
+    shut_b.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn max_redirects() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut_b, addr_b) = start("/echo".into())?;
+    let (shut_a, addr_a) = start(common::url(addr_b, "/r/302"))?;
+
+    let res = http::Request::get(common::url(addr_a, "/r/301"))
+        .redirect_policy(RedirectPolicy::new().max_redirects(1))
+        .call()
+        .block()?;
+    assert_eq!(res.status_code(), 302);
+    assert_eq!(res.redirects(), 1);
+
+    shut_a.shutdown().block();
+    shut_b.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn veto_redirect() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr) = start("/echo".into())?;
+
+    let mut agent = Agent::new();
+    agent.redirect_policy(RedirectPolicy::new().on_redirect(|hop| hop.status() != 307));
+
+    let req = http::Request::get(common::url(addr, "/r/307")).body(())?;
+    let res = agent.send(req).block()?;
+    assert_eq!(res.status_code(), 307);
+    assert!(res.redirect_history().is_empty());
+    read(res)?;
+
+    let req = http::Request::get(common::url(addr, "/r/308")).body(())?;
+    let res = agent.send(req).block()?;
+    assert_eq!(read(res)?, "GET  []");
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn strip_credentials_cross_origin() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut_b, addr_b) = start("/echo".into())?;
+    let (shut_a, addr_a) = start(common::url(addr_b, "/echo"))?;
+
+    let send = |uri: String| -> Result<String, Error> {
+        let res = http::Request::get(uri)
+            .header("authorization", "Bearer secret")
+            .header("cookie", "session=secret")
+            .header("proxy-authorization", "Basic c2VjcmV0")
+            .call()
+            .block()?;
+        read(res)
+    };
+
+    // same origin keeps them.
+    assert_eq!(
+        send(common::url(addr_b, "/r/302"))?,
+        "GET  [authorization,cookie,proxy-authorization]"
+    );
+
+    // another origin does not.
+    assert_eq!(send(common::url(addr_a, "/r/302"))?, "GET  []");
+
+    shut_a.shutdown().block();
+    shut_b.shutdown().block();
+    Ok(())
+}
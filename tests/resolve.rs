@@ -0,0 +1,94 @@
+use hreq::prelude::*;
+use hreq::{Agent, Error, Resolve};
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+mod common;
+
+fn server() -> Server<()> {
+    let mut server = Server::new();
+    server.at("/path").get(|req: http::Request<Body>| async move {
+        req.header("host").unwrap_or("none").to_string()
+    });
+    server
+}
+
+#[test]
+fn resolve_to_static() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr) = server().listen(0).block()?;
+
+    let mut agent = Agent::new();
+    let clone = agent.clone();
+    agent.resolve_to("my-fake-host.test", addr.port(), &[addr]);
+
+    // clones share the overrides.
+    let uri = format!("http://my-fake-host.test:{}/path", addr.port());
+    let req = http::Request::get(&uri).body(())?;
+    let res = clone.send(req).block()?;
+
+    assert_eq!(res.status(), 200);
+    assert_eq!(
+        res.into_body().read_to_string().block()?,
+        format!("my-fake-host.test:{}", addr.port())
+    );
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+struct Counting(Arc<AtomicUsize>, SocketAddr);
+
+impl Resolve for Counting {
+    fn resolve<'a>(
+        &'a self,
+        host: &'a str,
+        port: u16,
+    ) -> Pin<Box<dyn Future<Output = io::Result<Vec<SocketAddr>>> + Send + 'a>> {
+        Box::pin(async move {
+            assert_eq!(host, "counted.test");
+            assert_eq!(port, self.1.port());
+            self.0.fetch_add(1, Ordering::SeqCst);
+            // first address is unreachable, which means we fall back on the second.
+            Ok(vec!["127.0.0.1:1".parse().unwrap(), self.1])
+        })
+    }
+}
+
+#[test]
+fn custom_resolver() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr) = server().listen(0).block()?;
+
+    let count = Arc::new(AtomicUsize::new(0));
+
+    let mut agent = Agent::new();
+    agent.pooling(false);
+    agent.resolver(Counting(count.clone(), addr));
+
+    let uri = format!("http://counted.test:{}/path", addr.port());
+
+    for _ in 0..2 {
+        let req = http::Request::get(&uri).body(())?;
+        let res = agent.send(req).block()?;
+        assert_eq!(res.status(), 200);
+    }
+
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+
+    // ip addresses are not passed to the resolver.
+    let uri = format!("http://127.0.0.1:{}/path", addr.port());
+    let req = http::Request::get(&uri).body(())?;
+    agent.send(req).block()?;
+
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+
+    shut.shutdown().block();
+    Ok(())
+}
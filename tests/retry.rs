@@ -0,0 +1,226 @@
+use hreq::prelude::*;
+use hreq::{Agent, Error, RetryPolicy};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+mod common;
+
+/// Requests seen by the server, as (idempotency-key, body).
+type Seen = Arc<Mutex<Vec<(Option<String>, String)>>>;
+
+/// Server answering with the given statuses in order, then 200.
+fn start(
+    statuses: &'static [u16],
+    retry_after: &'static str,
+) -> Result<(hreq::server::ServerHandle, SocketAddr, Seen), Error> {
+    let seen: Seen = Arc::new(Mutex::new(vec![]));
+
+    let mut server = Server::with_state(seen.clone());
+
+    server
+        .at("/path")
+        .with_state()
+        .all(move |seen: Seen, req: http::Request<Body>| async move {
+            let key = req.header("idempotency-key").map(|k| k.to_string());
+            let body = req.into_body().read_to_string().await.unwrap();
+
+            let n = {
+                let mut seen = seen.lock().unwrap();
+                seen.push((key, body));
+                seen.len() - 1
+            };
+
+            let status = statuses.get(n).cloned().unwrap_or(200);
+
+            http::Response::builder()
+                .status(status)
+                .header("retry-after", retry_after)
+                .body(format!("attempt {}", n))
+                .unwrap()
+        });
+
+    let (shut, addr) = server.listen(0).block()?;
+
+    Ok((shut, addr, seen))
+}
+
+fn uri(addr: SocketAddr) -> String {
+    format!("http://127.0.0.1:{}/path", addr.port())
+}
+
+fn quick() -> RetryPolicy {
+    RetryPolicy::new().backoff(Duration::from_millis(1), Duration::from_millis(10))
+}
+
+#[test]
+fn retry_on_status() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr, seen) = start(&[503, 429], "0")?;
+
+    let mut agent = Agent::new();
+    agent.retry_policy(quick().statuses(&[429, 503]));
+
+    let res = agent
+        .send(http::Request::get(uri(addr)).body(())?)
+        .block()?;
+    assert_eq!(res.status_code(), 200);
+    assert_eq!(res.retries(), 2);
+    assert_eq!(res.into_body().read_to_string().block()?, "attempt 2");
+
+    assert_eq!(seen.lock().unwrap().len(), 3);
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn no_status_retry_by_default() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr, seen) = start(&[503], "0")?;
+
+    let res = http::Request::get(uri(addr)).call().block()?;
+    assert_eq!(res.status_code(), 503);
+
+    assert_eq!(seen.lock().unwrap().len(), 1);
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn max_attempts_returns_last_response() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr, seen) = start(&[503, 503, 503], "0")?;
+
+    let res = http::Request::get(uri(addr))
+        .retry_policy(quick().statuses(&[503]).max_attempts(2))
+        .call()
+        .block()?;
+    assert_eq!(res.status_code(), 503);
+    assert_eq!(res.into_body().read_to_string().block()?, "attempt 1");
+
+    assert_eq!(seen.lock().unwrap().len(), 2);
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn honour_retry_after() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr, seen) = start(&[503], "1")?;
+
+    let start = Instant::now();
+    let res = http::Request::get(uri(addr))
+        .retry_policy(quick().statuses(&[503]))
+        .call()
+        .block()?;
+    assert_eq!(res.status_code(), 200);
+    assert!(start.elapsed() >= Duration::from_secs(1));
+
+    assert_eq!(seen.lock().unwrap().len(), 2);
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn retry_after_too_long() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr, seen) = start(&[503], "3600")?;
+
+    let res = http::Request::get(uri(addr))
+        .retry_policy(quick().statuses(&[503]))
+        .call()
+        .block()?;
+    assert_eq!(res.status_code(), 503);
+
+    assert_eq!(seen.lock().unwrap().len(), 1);
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn post_with_idempotency_key() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr, seen) = start(&[503, 503], "0")?;
+
+    // without a key, a POST is not retried.
+    let res = http::Request::post(uri(addr))
+        .redirect_body_buffer(1024)
+        .retry_policy(quick().statuses(&[503]))
+        .send("order")
+        .block()?;
+    assert_eq!(res.status_code(), 503);
+    assert_eq!(seen.lock().unwrap().len(), 1);
+
+    let res = http::Request::post(uri(addr))
+        .redirect_body_buffer(1024)
+        .retry_policy(quick().statuses(&[503]).idempotency_key(true))
+        .send("order")
+        .block()?;
+    assert_eq!(res.status_code(), 200);
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 3);
+
+    let (key1, body1) = &seen[1];
+    let (key2, body2) = &seen[2];
+    assert_eq!(seen[0].0, None);
+    assert!(key1.is_some());
+    assert_eq!(key1, key2);
+    assert_eq!(body1, "order");
+    assert_eq!(body2, "order");
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn body_not_buffered_is_not_resent() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr, seen) = start(&[503], "0")?;
+
+    let res = http::Request::put(uri(addr))
+        .retry_policy(quick().statuses(&[503]))
+        .send("data")
+        .block()?;
+    assert_eq!(res.status_code(), 503);
+
+    assert_eq!(seen.lock().unwrap().len(), 1);
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn retry_if_predicate() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr, seen) = start(&[418], "0")?;
+
+    let policy = quick().retry_if(|outcome| match outcome {
+        Ok(res) => res.status_code() == 418,
+        Err(_) => false,
+    });
+
+    let res = http::Request::get(uri(addr))
+        .retry_policy(policy)
+        .call()
+        .block()?;
+    assert_eq!(res.status_code(), 200);
+
+    assert_eq!(seen.lock().unwrap().len(), 2);
+
+    shut.shutdown().block();
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use futures_util::stream::{self, StreamExt};
+use hreq::prelude::*;
+use hreq::Error;
+use std::net::SocketAddr;
+
+mod common;
+
+fn data() -> Vec<u8> {
+    (0..200_000).map(|i| (i % 251) as u8).collect()
+}
+
+fn start() -> Result<(hreq::server::ServerHandle, SocketAddr), Error> {
+    let mut server = Server::new();
+
+    server
+        .at("/data")
+        .get(|_req: http::Request<Body>| async move {
+            let chunks: Vec<Result<Vec<u8>, Error>> =
+                data().chunks(10_000).map(|c| Ok(c.to_vec())).collect();
+            Body::from_stream(stream::iter(chunks))
+        });
+
+    server
+        .at("/echo")
+        .post(|req: http::Request<Body>| async move {
+            req.into_body().read_to_vec(1024 * 1024).await.unwrap()
+        });
+
+    server
+        .at("/chunks")
+        .get(|_req: http::Request<Body>| async move {
+            let chunks = vec!["one ", "", "two ", "three"];
+            Body::from_stream(stream::iter(chunks.into_iter().map(Ok::<_, Error>)))
+        });
+
+    server
+        .at("/fail")
+        .get(|_req: http::Request<Body>| async move {
+            let chunks = vec![Ok("partial"), Err(Error::Proto("source failed".into()))];
+            Body::from_stream(stream::iter(chunks))
+        });
+
+    server.listen(0).block()
+}
+
+#[test]
+fn stream_chunks() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr) = start()?;
+
+    for http2 in &[false, true] {
+        let res = http::Request::get(common::url(addr, "/chunks"))
+            .force_http2(*http2)
+            .call()
+            .block()?;
+
+        let mut body = res.into_body();
+        let mut all = vec![];
+        while let Some(chunk) = body.next().block() {
+            let chunk = chunk?;
+            assert!(!chunk.is_empty());
+            all.extend_from_slice(&chunk);
+        }
+
+        assert_eq!(all, b"one two three");
+    }
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn stream_pipe() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr) = start()?;
+
+    for http2 in &[false, true] {
+        let res = http::Request::get(common::url(addr, "/data"))
+            .force_http2(*http2)
+            .call()
+            .block()?;
+
+        // the response body piped into the next request.
+        let res = http::Request::post(common::url(addr, "/echo"))
+            .force_http2(*http2)
+            .send(Body::from_stream(res.into_body()))
+            .block()?;
+
+        let chunks: Vec<_> = res.into_body().collect::<Vec<_>>().block();
+
+        let mut all = vec![];
+        for chunk in chunks {
+            all.extend_from_slice(&chunk?);
+        }
+
+        assert_eq!(all, data());
+    }
+
+    shut.shutdown().block();
+    Ok(())
+}
+
+#[test]
+fn stream_error() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (shut, addr) = start()?;
+
+    for http2 in &[false, true] {
+        let res = http::Request::get(common::url(addr, "/fail"))
+            .force_http2(*http2)
+            .call()
+            .block();
+
+        // http2 might reset the stream before the response is read.
+        if let Ok(res) = res {
+            let chunks: Vec<_> = res.into_body().collect::<Vec<_>>().block();
+            assert!(chunks.iter().any(|c| c.is_err()), "http2: {}", http2);
+        }
+    }
+
+    shut.shutdown().block();
+    Ok(())
+}
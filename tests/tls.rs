@@ -31,6 +31,45 @@ fn tls_client_to_server() -> Result<(), hreq::Error> {
     Ok(())
 }
 
+#[test]
+#[cfg(feature = "tls")]
+fn tls_unverified_connection_not_reused() -> Result<(), hreq::Error> {
+    use hreq::prelude::*;
+    use hreq::Agent;
+
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server
+        .at("/path")
+        .all(|_: http::Request<Body>| async move { "ok" });
+
+    let config = hreq::server::TlsConfig::new()
+        .key_path("tests/data/tls_cert.pem")
+        .cert_path("tests/data/tls_cert.pem");
+
+    let (handle, addr) = server.listen_tls(0, config).block()?;
+
+    let uri = format!("https://localhost:{}/path", addr.port());
+
+    let agent = Agent::new();
+
+    let req = http::Request::get(&uri)
+        .tls_disable_server_cert_verify(true)
+        .with_body(())?;
+    let res = agent.send(req).block()?;
+    assert_eq!(res.status(), 200);
+    res.into_body().read_to_string().block()?;
+
+    // the pooled connection is unverified, so this request must connect anew
+    // and fail on the self-signed certificate.
+    let req = http::Request::get(&uri).with_body(())?;
+    assert!(agent.send(req).block().is_err());
+
+    handle.shutdown().block();
+    Ok(())
+}
+
 #[test]
 #[cfg(feature = "tls")]
 fn tls_req_body100mb_with_size() -> Result<(), hreq::Error> {
@@ -75,3 +114,240 @@ fn tls_req_body100mb_with_size() -> Result<(), hreq::Error> {
     handle.shutdown().block();
     Ok(())
 }
+
+#[cfg(feature = "tls")]
+mod client_config {
+    use super::common;
+    use hreq::prelude::*;
+    use hreq::server::ServerHandle;
+    use hreq::{Agent, Error, TlsConfig};
+    use std::net::SocketAddr;
+
+    const CA: &[u8] = include_bytes!("data/tls_ca.pem");
+    const SERVER: &[u8] = include_bytes!("data/tls_server.pem");
+    const CLIENT: &[u8] = include_bytes!("data/tls_client.pem");
+
+    // openssl x509 -in tls_server.pem -noout -pubkey | openssl pkey -pubin -outform der \
+    //   | openssl dgst -sha256 -binary | base64
+    const SERVER_PIN: &str = "InAKoIx2GoNqX+lsZkIvVmHvGmC1/eicTYi49eiuVdw=";
+
+    fn server() -> Server<()> {
+        let mut server = Server::new();
+        server
+            .at("/path")
+            .all(|_: http::Request<Body>| async move { "ok" });
+        server
+    }
+
+    fn start() -> Result<(ServerHandle, SocketAddr), Error> {
+        let config = hreq::server::TlsConfig::new().key(SERVER).cert(SERVER);
+        server().listen_tls(0, config).block()
+    }
+
+    fn get(addr: SocketAddr, config: TlsConfig) -> Result<http::Response<Body>, Error> {
+        let uri = format!("https://localhost:{}/path", addr.port());
+        http::Request::get(uri).tls_config(config).call().block()
+    }
+
+    #[test]
+    fn connection_metadata() -> Result<(), Error> {
+        common::setup_logger();
+
+        let (handle, addr) = start()?;
+
+        let res = get(addr, TlsConfig::new().root_certificates(CA))?;
+
+        assert_eq!(res.alpn_protocol(), Some("h2"));
+        assert_eq!(res.connection_version(), Some(http::Version::HTTP_2));
+        assert_eq!(res.tls_protocol(), Some("TLSv1_3"));
+        assert!(res.tls_cipher().unwrap().starts_with("TLS13_"));
+        assert!(res.tls_time().is_some());
+
+        handle.shutdown().block();
+        Ok(())
+    }
+
+    #[test]
+    fn private_root_certificate() -> Result<(), Error> {
+        common::setup_logger();
+
+        let (handle, addr) = start()?;
+
+        // not trusted by default
+        assert!(get(addr, TlsConfig::new()).is_err());
+
+        let res = get(addr, TlsConfig::new().root_certificates(CA))?;
+        assert_eq!(res.status(), 200);
+
+        let res = get(
+            addr,
+            TlsConfig::new().root_certificates(CA).webpki_roots(false),
+        )?;
+        assert_eq!(res.status(), 200);
+
+        // agent wide
+        let mut agent = Agent::new();
+        agent.tls_config(TlsConfig::new().root_certificates(CA));
+        let uri = format!("https://localhost:{}/path", addr.port());
+        let res = agent.send(http::Request::get(uri).body(())?).block()?;
+        assert_eq!(res.status(), 200);
+
+        // bad pem is an error of the request
+        assert!(get(addr, TlsConfig::new().root_certificates("not a cert")).is_err());
+
+        handle.shutdown().block();
+        Ok(())
+    }
+
+    #[test]
+    fn pinned_public_key() -> Result<(), Error> {
+        common::setup_logger();
+
+        let (handle, addr) = start()?;
+
+        let config = TlsConfig::new().root_certificates(CA);
+
+        let res = get(addr, config.clone().pin_sha256(SERVER_PIN))?;
+        assert_eq!(res.status(), 200);
+
+        let res = get(
+            addr,
+            config
+                .clone()
+                .pin_sha256("sha256//AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=")
+                .pin_sha256(&format!("sha256//{}", SERVER_PIN)),
+        )?;
+        assert_eq!(res.status(), 200);
+
+        let wrong = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+        assert!(get(addr, config.pin_sha256(wrong)).is_err());
+
+        // pin without chain verification
+        let uri = format!("https://localhost:{}/path", addr.port());
+        let res = http::Request::get(&uri)
+            .tls_disable_server_cert_verify(true)
+            .tls_config(TlsConfig::new().pin_sha256(SERVER_PIN))
+            .call()
+            .block()?;
+        assert_eq!(res.status(), 200);
+
+        let res = http::Request::get(&uri)
+            .tls_disable_server_cert_verify(true)
+            .tls_config(TlsConfig::new().pin_sha256(wrong))
+            .call()
+            .block();
+        assert!(res.is_err());
+
+        handle.shutdown().block();
+        Ok(())
+    }
+
+    #[test]
+    fn pinned_public_key_not_in_leaf() -> Result<(), Error> {
+        use rustls::internal::pemfile;
+        use std::io::Cursor;
+
+        common::setup_logger();
+
+        // unpinned leaf followed by the pinned certificate in the chain
+        const LEAF: &[u8] = include_bytes!("data/tls_cert.pem");
+        let mut certs = pemfile::certs(&mut Cursor::new(LEAF)).unwrap();
+        certs.extend(pemfile::certs(&mut Cursor::new(SERVER)).unwrap());
+        let key = pemfile::pkcs8_private_keys(&mut Cursor::new(LEAF))
+            .unwrap()
+            .remove(0);
+
+        let mut tls = rustls::ServerConfig::new(rustls::NoClientAuth::new());
+        tls.set_single_cert(certs, key)?;
+
+        let (handle, addr) = server().listen_tls_rustls(0, tls).block()?;
+
+        let uri = format!("https://localhost:{}/path", addr.port());
+        let res = http::Request::get(&uri)
+            .tls_disable_server_cert_verify(true)
+            .tls_config(TlsConfig::new().pin_sha256(SERVER_PIN))
+            .call()
+            .block();
+        assert!(res.is_err());
+
+        handle.shutdown().block();
+        Ok(())
+    }
+
+    #[test]
+    fn client_certificate() -> Result<(), Error> {
+        use rustls::internal::pemfile;
+        use std::io::Cursor;
+
+        common::setup_logger();
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add_pem_file(&mut Cursor::new(CA)).unwrap();
+
+        let mut tls = rustls::ServerConfig::new(rustls::AllowAnyAuthenticatedClient::new(roots));
+        let certs = pemfile::certs(&mut Cursor::new(SERVER)).unwrap();
+        let key = pemfile::pkcs8_private_keys(&mut Cursor::new(SERVER))
+            .unwrap()
+            .remove(0);
+        tls.set_single_cert(certs, key)?;
+
+        let (handle, addr) = server().listen_tls_rustls(0, tls).block()?;
+
+        let config = TlsConfig::new().root_certificates(CA);
+
+        assert!(get(addr, config.clone()).is_err());
+
+        let res = get(addr, config.client_certificate(CLIENT, CLIENT))?;
+        assert_eq!(res.status(), 200);
+
+        handle.shutdown().block();
+        Ok(())
+    }
+
+    #[test]
+    fn key_log_file() -> Result<(), Error> {
+        common::setup_logger();
+
+        let (handle, addr) = start()?;
+
+        let path = std::env::temp_dir().join(format!("hreq-keylog-{}.txt", addr.port()));
+        let _ = std::fs::remove_file(&path);
+
+        let config = TlsConfig::new().root_certificates(CA).key_log_file(&path);
+        get(addr, config)?;
+
+        let log = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+
+        assert!(log.lines().count() > 0);
+        assert!(log
+            .lines()
+            .all(|l| l.split(' ').count() == 3 && l.contains("SECRET")
+                || l.starts_with("CLIENT_RANDOM")));
+
+        handle.shutdown().block();
+        Ok(())
+    }
+
+    #[test]
+    fn from_rustls_config() -> Result<(), Error> {
+        use std::io::Cursor;
+
+        common::setup_logger();
+
+        let (handle, addr) = start()?;
+
+        let mut config = rustls::ClientConfig::new();
+        config
+            .root_store
+            .add_pem_file(&mut Cursor::new(CA))
+            .unwrap();
+
+        let res = get(addr, TlsConfig::from_rustls(config))?;
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.version(), http::Version::HTTP_2);
+
+        handle.shutdown().block();
+        Ok(())
+    }
+}
@@ -0,0 +1,404 @@
+use hreq::prelude::*;
+use hreq::ws::{CloseFrame, Message};
+use hreq::{Agent, AsyncRuntime, Error, WebSocket};
+use sha1::{Digest, Sha1};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+mod common;
+
+/// A WebSocket peer that pings once, and then echoes every frame as is.
+///
+/// Echoing compressed frames works, since the client inflates what it deflated. The
+/// request head is returned when the connection is closed.
+fn peer(status: &'static str, deflate: bool) -> (SocketAddr, JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let handle = thread::spawn(move || {
+        let (mut tcp, _) = listener.accept().unwrap();
+
+        let mut head = vec![];
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut b = [0];
+            tcp.read_exact(&mut b).unwrap();
+            head.push(b[0]);
+        }
+        let head = String::from_utf8(head).unwrap();
+
+        let header = |name: &str| {
+            head.lines()
+                .find(|l| l.to_lowercase().starts_with(&format!("{}:", name)))
+                .map(|l| l[name.len() + 1..].trim().to_string())
+        };
+
+        if status != "101" {
+            write!(tcp, "HTTP/1.1 {} Nope\r\ncontent-length: 0\r\n\r\n", status).unwrap();
+            return head;
+        }
+
+        let mut hasher = Sha1::new();
+        hasher.update(header("sec-websocket-key").unwrap().as_bytes());
+        hasher.update(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11");
+        let accept = base64::encode(hasher.finalize());
+
+        let mut res = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nupgrade: websocket\r\nconnection: Upgrade\r\n\
+             sec-websocket-accept: {}\r\nset-cookie: session=abc\r\n",
+            accept
+        );
+        if let Some(p) = header("sec-websocket-protocol") {
+            res.push_str(&format!("sec-websocket-protocol: {}\r\n", p));
+        }
+        if deflate && header("sec-websocket-extensions").is_some() {
+            res.push_str("sec-websocket-extensions: permessage-deflate\r\n");
+        }
+        res.push_str("\r\n");
+
+        tcp.write_all(res.as_bytes()).unwrap();
+        tcp.write_all(&[0x89, 0x02, b'h', b'i']).unwrap();
+
+        loop {
+            let (b0, payload) = read_frame(&mut tcp);
+
+            let mut out = vec![b0];
+            if payload.len() < 126 {
+                out.push(payload.len() as u8);
+            } else if payload.len() <= 65535 {
+                out.push(126);
+                out.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+            } else {
+                out.push(127);
+                out.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+            }
+            out.extend_from_slice(&payload);
+            tcp.write_all(&out).unwrap();
+
+            // close
+            if b0 & 0x0f == 0x8 {
+                break;
+            }
+        }
+
+        head
+    });
+
+    (addr, handle)
+}
+
+fn read_frame(tcp: &mut TcpStream) -> (u8, Vec<u8>) {
+    let mut h = [0; 2];
+    tcp.read_exact(&mut h).unwrap();
+    assert!(h[1] & 0x80 > 0, "client frames are masked");
+
+    let len = match h[1] & 0x7f {
+        126 => {
+            let mut b = [0; 2];
+            tcp.read_exact(&mut b).unwrap();
+            u16::from_be_bytes(b) as usize
+        }
+        127 => {
+            let mut b = [0; 8];
+            tcp.read_exact(&mut b).unwrap();
+            u64::from_be_bytes(b) as usize
+        }
+        n => n as usize,
+    };
+
+    let mut mask = [0; 4];
+    tcp.read_exact(&mut mask).unwrap();
+    let mut payload = vec![0; len];
+    tcp.read_exact(&mut payload).unwrap();
+    for (i, b) in payload.iter_mut().enumerate() {
+        *b ^= mask[i % 4];
+    }
+
+    (h[0], payload)
+}
+
+#[test]
+fn echo_messages() -> Result<(), Error> {
+    common::setup_logger();
+
+    for deflate in &[false, true] {
+        let (addr, handle) = peer("101", *deflate);
+
+        let agent = Agent::new();
+        let mut ws = agent
+            .websocket(format!("ws://127.0.0.1:{}/echo", addr.port()))
+            .block()?;
+
+        assert_eq!(ws.is_deflate(), *deflate && cfg!(feature = "deflate"));
+
+        // the ping is answered, and the answer echoed.
+        assert_eq!(ws.recv().block().unwrap()?, Message::Ping(b"hi".to_vec()));
+        assert_eq!(ws.recv().block().unwrap()?, Message::Pong(b"hi".to_vec()));
+
+        ws.send(Message::Text("Hello".into())).block()?;
+        assert_eq!(ws.recv().block().unwrap()?, Message::Text("Hello".into()));
+
+        // in fragments
+        let data: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+        ws.max_frame_size(Some(1000));
+        ws.send(Message::Binary(data.clone())).block()?;
+        assert_eq!(ws.recv().block().unwrap()?, Message::Binary(data));
+
+        ws.close(Some(CloseFrame::new(4000, "done"))).block()?;
+        assert!(ws.recv().block().is_none());
+
+        let head = handle.join().unwrap();
+        assert!(head.starts_with("GET /echo HTTP/1.1\r\n"));
+        assert!(head.contains("sec-websocket-version: 13\r\n"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn headers_and_cookies() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (addr, handle) = peer("101", true);
+    let uri = format!("ws://127.0.0.1:{}/chat", addr.port());
+
+    let agent = Agent::new();
+
+    let req = http::Request::get(&uri)
+        .header("sec-websocket-protocol", "chat")
+        .header("x-token", "secret")
+        .body(())?;
+    let mut ws = agent.websocket_request(req).block()?;
+
+    assert_eq!(ws.protocol(), Some("chat"));
+
+    let cookies = agent.get_cookies(&"http://127.0.0.1/".parse().unwrap());
+    assert_eq!(cookies.len(), 1);
+    assert_eq!(cookies[0].value(), "abc");
+
+    ws.close(None).block()?;
+
+    let head = handle.join().unwrap();
+    assert!(head.contains("x-token: secret\r\n"));
+
+    Ok(())
+}
+
+#[test]
+fn refused() -> Result<(), Error> {
+    common::setup_logger();
+
+    let (addr, handle) = peer("404", false);
+
+    let res = http::Request::get(format!("http://127.0.0.1:{}/", addr.port()))
+        .websocket()
+        .block();
+
+    let err = res.unwrap_err();
+    assert!(err.to_string().contains("404"), "{}", err);
+
+    handle.join().unwrap();
+
+    Ok(())
+}
+
+/// Server handler that greets with the path, echoes messages and keeps the close code.
+async fn echo(req: http::Request<Body>, mut ws: WebSocket, closed: Arc<Mutex<Option<u16>>>) {
+    ws.send(Message::Text(req.uri().path().into()))
+        .await
+        .unwrap();
+
+    while let Some(msg) = ws.recv().await {
+        match msg.unwrap() {
+            Message::Close(c) => *closed.lock().unwrap() = c.map(|c| c.code()),
+            m @ Message::Text(_) | m @ Message::Binary(_) => ws.send(m).await.unwrap(),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn server_echo() -> Result<(), Error> {
+    common::setup_logger();
+
+    let closed = Arc::new(Mutex::new(None));
+    let closed2 = closed.clone();
+
+    let mut server = Server::new();
+    server
+        .at("/echo")
+        .websocket(move |req, ws| echo(req, ws, closed2.clone()));
+    let (shut, addr) = server.listen(0).block()?;
+
+    let mut ws = Agent::new()
+        .websocket(format!("ws://127.0.0.1:{}/echo", addr.port()))
+        .block()?;
+
+    assert_eq!(ws.is_deflate(), cfg!(feature = "deflate"));
+    assert_eq!(ws.recv().block().unwrap()?, Message::Text("/echo".into()));
+
+    ws.send(Message::Text("Hello".into())).block()?;
+    assert_eq!(ws.recv().block().unwrap()?, Message::Text("Hello".into()));
+
+    let data: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+    ws.max_frame_size(Some(1000));
+    ws.send(Message::Binary(data.clone())).block()?;
+    assert_eq!(ws.recv().block().unwrap()?, Message::Binary(data));
+
+    ws.close(Some(CloseFrame::new(4000, "done"))).block()?;
+    assert!(ws.recv().block().is_none());
+
+    // the handler may still be finishing up.
+    for _ in 0..100 {
+        if closed.lock().unwrap().is_some() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(*closed.lock().unwrap(), Some(4000));
+
+    // the route still answers plain requests.
+    let res = Request::get(format!("http://127.0.0.1:{}/echo", addr.port()))
+        .call()
+        .block()?;
+    assert_eq!(res.status(), 426);
+    assert_eq!(res.header("upgrade"), Some("websocket"));
+
+    shut.shutdown().block();
+
+    Ok(())
+}
+
+#[test]
+fn server_rejects_version() -> Result<(), Error> {
+    common::setup_logger();
+
+    let mut server = Server::new();
+    server.at("/ws").websocket(|_req, _ws| async {});
+    let (shut, addr) = server.listen(0).block()?;
+
+    let res = Request::get(format!("http://127.0.0.1:{}/ws", addr.port()))
+        .header("upgrade", "websocket")
+        .header("connection", "Upgrade")
+        .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+        .header("sec-websocket-version", "8")
+        .call()
+        .block()?;
+
+    assert_eq!(res.status(), 426);
+    assert_eq!(res.header("sec-websocket-version"), Some("13"));
+
+    shut.shutdown().block();
+
+    Ok(())
+}
+
+/// Take an unmasked server frame with a short payload from the front of `buf`.
+fn take_frame(buf: &mut Vec<u8>) -> Option<(u8, Vec<u8>)> {
+    if buf.len() < 2 {
+        return None;
+    }
+    let len = buf[1] as usize;
+    assert!(len < 126);
+    if buf.len() < 2 + len {
+        return None;
+    }
+    let frame: Vec<u8> = buf.drain(..2 + len).collect();
+    Some((frame[0], frame[2..].to_vec()))
+}
+
+async fn next_frame(recv: &mut h2::RecvStream, buf: &mut Vec<u8>) -> Option<(u8, Vec<u8>)> {
+    loop {
+        if let Some(f) = take_frame(buf) {
+            return Some(f);
+        }
+        let data = recv.data().await?.unwrap();
+        recv.flow_control().release_capacity(data.len()).unwrap();
+        buf.extend_from_slice(&data);
+    }
+}
+
+fn masked_frame(b0: u8, payload: &[u8]) -> bytes::Bytes {
+    let mask = [1, 2, 3, 4];
+    let mut out = vec![b0, 0x80 | payload.len() as u8];
+    out.extend_from_slice(&mask);
+    out.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    out.into()
+}
+
+#[test]
+fn server_http2() -> Result<(), Error> {
+    common::setup_logger();
+
+    let closed = Arc::new(Mutex::new(None));
+    let closed2 = closed.clone();
+
+    let mut server = Server::new();
+    server
+        .at("/echo")
+        .websocket(move |req, ws| echo(req, ws, closed2.clone()));
+    let (shut, addr) = server.listen(0).block()?;
+
+    async move {
+        let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (h2, conn) = h2::client::handshake(tcp).await.unwrap();
+        AsyncRuntime::spawn(async move {
+            conn.await.ok();
+        });
+        let mut h2 = h2.ready().await.unwrap();
+
+        // the setting arrives with the server preface.
+        for _ in 0..100 {
+            if h2.is_extended_connect_protocol_enabled() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(h2.is_extended_connect_protocol_enabled());
+
+        let mut req = http::Request::connect(format!("http://127.0.0.1:{}/echo", addr.port()))
+            .header("sec-websocket-version", "13")
+            .body(())
+            .unwrap();
+        req.extensions_mut()
+            .insert(h2::ext::Protocol::from("websocket"));
+
+        let (res, mut send) = h2.send_request(req, false).unwrap();
+        let res = res.await.unwrap();
+        assert_eq!(res.status(), 200);
+        assert!(res.headers().get("content-length").is_none());
+
+        let mut recv = res.into_body();
+        let mut buf = vec![];
+
+        assert_eq!(
+            next_frame(&mut recv, &mut buf).await,
+            Some((0x81, b"/echo".to_vec()))
+        );
+
+        send.send_data(masked_frame(0x81, b"Hello"), false).unwrap();
+        assert_eq!(
+            next_frame(&mut recv, &mut buf).await,
+            Some((0x81, b"Hello".to_vec()))
+        );
+
+        // both ends end the stream after the closing handshake.
+        send.send_data(masked_frame(0x88, &1000_u16.to_be_bytes()), true)
+            .unwrap();
+        assert_eq!(
+            next_frame(&mut recv, &mut buf).await,
+            Some((0x88, 1000_u16.to_be_bytes().to_vec()))
+        );
+
+        assert_eq!(next_frame(&mut recv, &mut buf).await, None);
+    }
+    .block();
+
+    assert_eq!(*closed.lock().unwrap(), Some(1000));
+
+    shut.shutdown().block();
+
+    Ok(())
+}